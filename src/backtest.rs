@@ -0,0 +1,306 @@
+// 历史回测：逐根回放K线，驱动完整的多智能体决策与模拟撮合
+
+use crate::circuit_breaker::{BreakerConfig, CircuitBreaker};
+use crate::exchange::{BinanceExchange, Exchange};
+use crate::executor::SymbolConstraints;
+use crate::journal;
+use crate::kline_store::{KlineStore, DEFAULT_STORE_DIR};
+use crate::logging;
+use crate::market;
+use crate::paper::{PaperConfig, PaperExchange};
+use crate::performance::{PerformanceSnapshot, PerformanceTracker};
+use crate::types::{Kline, TradeResult};
+use crate::{load_leverage_brackets, run_portfolio_cycle, Config};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::env;
+use std::fs::{create_dir_all, write};
+use std::path::Path;
+use std::sync::Arc;
+
+const BACKTEST_DIR: &str = "backtests";
+
+struct BacktestConfig {
+    start_ms: i64,
+    end_ms: i64,
+    warmup_bars: i64, // 起始时间之前预加载的K线数，用于计算指标
+}
+
+impl BacktestConfig {
+    fn from_env() -> Result<Self> {
+        let start_ms =
+            market::parse_time(&env::var("BACKTEST_START").context("缺少 BACKTEST_START")?)?;
+        let end_ms = match env::var("BACKTEST_END") {
+            Ok(value) => market::parse_time(&value)?,
+            Err(_) => Utc::now().timestamp_millis(),
+        };
+        if end_ms <= start_ms {
+            bail!("BACKTEST_END 必须晚于 BACKTEST_START");
+        }
+
+        Ok(BacktestConfig {
+            start_ms,
+            end_ms,
+            warmup_bars: env::var("BACKTEST_WARMUP_BARS")
+                .unwrap_or_else(|_| "120".to_string())
+                .parse()
+                .context("BACKTEST_WARMUP_BARS 格式错误")?,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct BacktestReport {
+    symbols: Vec<String>,
+    interval: String,
+    start: i64,
+    end: i64,
+    bars: usize,
+    cycles: usize,
+    failed_cycles: usize,
+    initial_balance: f64,
+    final_equity: f64,
+    return_pct: f64,
+    performance: PerformanceSnapshot,
+    trades: Vec<TradeResult>,
+}
+
+pub async fn run(config: &Config) -> Result<()> {
+    let bt = BacktestConfig::from_env().context("回测配置加载失败")?;
+    let interval = config.interval_str();
+    let interval_ms = market::interval_to_millis(interval)?;
+
+    let output_dir = format!("{}/{}", BACKTEST_DIR, Utc::now().format("%Y%m%d_%H%M%S"));
+    create_dir_all(&output_dir).context("创建回测输出目录失败")?;
+    logging::set_logs_directory(&output_dir);
+
+    info!("============================================================");
+    info!("历史回测");
+    info!("============================================================");
+    info!("交易标的: {:?}", config.trade_symbols);
+    info!(
+        "区间: {} → {} ({})",
+        format_ms(bt.start_ms),
+        format_ms(bt.end_ms),
+        interval
+    );
+    info!("LLM 后端: {}", config.llm.name());
+    info!("持仓模式: {}", config.position_mode.label());
+    info!("风控规则: {}", config.risk_engine.limits());
+    info!("熔断规则: {}", config.circuit_breaker);
+    info!("扩展指标: {}", config.indicator_selection);
+    info!("输出目录: {}", output_dir);
+
+    let binance: Arc<dyn Exchange> = Arc::new(BinanceExchange::new(
+        &config.binance_api_key,
+        &config.binance_secret,
+    ));
+    let paper = PaperExchange::replay(binance, PaperConfig::from_env()?)
+        .with_position_mode(config.position_mode);
+    let store = KlineStore::new(
+        config
+            .kline_store_dir
+            .as_deref()
+            .unwrap_or(DEFAULT_STORE_DIR),
+    );
+
+    // 离线回测时无法拉取交易规则，退回到宽松的默认精度
+    let mut constraints = match paper.fetch_symbol_constraints(&config.trade_symbols).await {
+        Ok(constraints) => constraints,
+        Err(e) => {
+            warn!("拉取交易规则失败，使用默认精度: {:#}", e);
+            config
+                .trade_symbols
+                .iter()
+                .map(|symbol| (symbol.clone(), offline_constraints()))
+                .collect()
+        }
+    };
+    load_leverage_brackets(&paper, &mut constraints).await;
+    for symbol in &config.trade_symbols {
+        paper.set_leverage(symbol, config.leverage).await?;
+    }
+
+    // 按开盘时间汇总所有标的的K线
+    let load_start = bt.start_ms - bt.warmup_bars * interval_ms;
+    let mut timeline: BTreeMap<i64, Vec<(String, Kline)>> = BTreeMap::new();
+    for symbol in &config.trade_symbols {
+        // 先补齐本地存储，离线时直接使用已有数据
+        match store.sync(symbol, interval, load_start, bt.end_ms).await {
+            Ok(added) if added > 0 => info!("{} 补齐 {} 根K线", symbol, added),
+            Ok(_) => {}
+            Err(e) => warn!("{} 历史K线同步失败，使用本地数据: {:#}", symbol, e),
+        }
+        let klines = store
+            .range(symbol, interval, load_start, bt.end_ms)
+            .with_context(|| format!("加载 {} 历史K线失败", symbol))?;
+        info!("{} 载入 {} 根K线", symbol, klines.len());
+        for kline in klines {
+            timeline
+                .entry(kline.timestamp)
+                .or_default()
+                .push((symbol.clone(), kline));
+        }
+    }
+
+    // 附加分析周期：只在K线收盘后放入回放行情，避免读取到未来数据
+    let mut context_klines: Vec<(String, String, i64, VecDeque<Kline>)> = Vec::new();
+    for symbol in &config.trade_symbols {
+        for tf in config.context_timeframes() {
+            let tf_ms = market::interval_to_millis(tf)?;
+            let tf_start = bt.start_ms - bt.warmup_bars * tf_ms;
+            if let Err(e) = store.sync(symbol, tf, tf_start, bt.end_ms).await {
+                warn!("{} {} 历史K线同步失败，使用本地数据: {:#}", symbol, tf, e);
+            }
+            let klines = store
+                .range(symbol, tf, tf_start, bt.end_ms)
+                .with_context(|| format!("加载 {} {} 历史K线失败", symbol, tf))?;
+            info!("{} 载入 {} 周期K线 {} 根", symbol, tf, klines.len());
+            context_klines.push((symbol.clone(), tf.to_string(), tf_ms, klines.into()));
+        }
+    }
+
+    let mut tracker = PerformanceTracker::new();
+    // 回测不读取实盘配置的手动熔断文件：置空后取日志目录下的 KILL，日志目录已重定向到输出目录
+    let mut breaker = CircuitBreaker::load(BreakerConfig {
+        kill_switch_file: None,
+        ..config.circuit_breaker.clone()
+    })?;
+    let mut trades = Vec::new();
+    let mut bars = 0;
+    let mut cycles = 0;
+    let mut failed_cycles = 0;
+
+    for (open_time, bar) in timeline {
+        for (symbol, kline) in bar {
+            paper.push_kline(&symbol, interval, kline)?;
+        }
+        let bar_close = open_time + interval_ms;
+        for (symbol, tf, tf_ms, pending) in context_klines.iter_mut() {
+            while pending
+                .front()
+                .is_some_and(|k| k.timestamp + *tf_ms <= bar_close)
+            {
+                let kline = pending.pop_front().unwrap();
+                paper.push_history(symbol, tf, kline);
+            }
+        }
+        if open_time < bt.start_ms {
+            continue;
+        }
+        bars += 1;
+
+        // 每根K线使用全新缓存，确保持仓直接取自模拟账户
+        let mut symbols_cache = HashMap::new();
+        match run_portfolio_cycle(
+            &paper,
+            config,
+            interval,
+            &constraints,
+            &mut symbols_cache,
+            &mut tracker,
+            &mut breaker,
+        )
+        .await
+        {
+            Ok(executed) => {
+                cycles += 1;
+                trades.extend(executed);
+                journal::finish_cycle(paper.now_millis(), None);
+            }
+            Err(e) => {
+                failed_cycles += 1;
+                error!("回测周期失败 @ {}: {:#}", format_ms(open_time), e);
+                journal::finish_cycle(paper.now_millis(), Some(&format!("{:#}", e)));
+            }
+        }
+    }
+
+    if bars == 0 {
+        warn!("回测区间内没有可用K线");
+    }
+    // 计入最后一个周期的成交流水
+    tracker
+        .sync_income(&paper, &config.trade_symbols)
+        .await
+        .context("同步模拟盘资金流水失败")?;
+
+    let initial_balance = paper.initial_balance();
+    let final_equity = paper.equity();
+    let return_pct = if initial_balance > 0.0 {
+        (final_equity - initial_balance) / initial_balance * 100.0
+    } else {
+        0.0
+    };
+
+    let report = BacktestReport {
+        symbols: config.trade_symbols.clone(),
+        interval: interval.to_string(),
+        start: bt.start_ms,
+        end: bt.end_ms,
+        bars,
+        cycles,
+        failed_cycles,
+        initial_balance,
+        final_equity,
+        return_pct,
+        performance: tracker.snapshot().clone(),
+        trades,
+    };
+
+    let report_path = Path::new(&output_dir).join("report.json");
+    let json = serde_json::to_string_pretty(&report).context("序列化回测报告失败")?;
+    write(&report_path, json).context("写入回测报告失败")?;
+
+    info!("============================================================");
+    info!(
+        "回测完成: K线 {} 根, 周期 {} 次 (失败 {}), 交易 {} 笔",
+        report.bars,
+        report.cycles,
+        report.failed_cycles,
+        report.trades.len()
+    );
+    info!(
+        "初始资金 {:.2} → 期末权益 {:.2} USDT ({:+.2}%)",
+        initial_balance, final_equity, return_pct
+    );
+    info!(
+        "胜/负: {}/{}, 净盈亏 {:.2} USDT (已实现 {:.2}, 手续费 {:.2}), 最大回撤 {:.2} USDT",
+        report.performance.winning_trades,
+        report.performance.losing_trades,
+        report.performance.net_pnl,
+        report.performance.total_realized_pnl,
+        report.performance.total_commission,
+        report.performance.max_drawdown
+    );
+    info!("组合指标: {}", report.performance.metrics);
+    for (symbol, pnl) in &report.performance.per_symbol {
+        info!(
+            "{} 指标: 净盈亏 {:.2} USDT, {}",
+            symbol, pnl.net_pnl, pnl.metrics
+        );
+    }
+    info!("报告: {}", report_path.display());
+
+    Ok(())
+}
+
+fn offline_constraints() -> SymbolConstraints {
+    SymbolConstraints {
+        step_size: 0.001,
+        min_qty: 0.001,
+        max_qty: None,
+        min_notional: 0.0,
+        tick_size: 0.01,
+        leverage_brackets: Vec::new(),
+    }
+}
+
+fn format_ms(ms: i64) -> String {
+    DateTime::from_timestamp_millis(ms)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| ms.to_string())
+}
@@ -0,0 +1,498 @@
+// 熔断：按 UTC 自然日与滚动窗口统计权益变化（已实现 + 未实现盈亏，含手续费与资金费），
+// 滚动窗口按窗口内峰值计算回撤；亏损超过上限时停止开仓（可选全部平仓），熔断状态落盘，重启后保持。
+// 手动熔断：创建 KILL_SWITCH_FILE 文件或向进程发送 SIGUSR1，删除该文件即恢复
+
+use crate::logging;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::fs::{self, create_dir_all};
+use std::path::{Path, PathBuf};
+
+const STATE_FILE: &str = "circuit_breaker.json";
+const DEFAULT_KILL_SWITCH_FILE: &str = "KILL";
+const DAY_MS: i64 = 86_400_000;
+
+// 亏损上限：固定金额 (USDT) 或相对区间起点权益的百分比
+#[derive(Debug, Clone, Copy)]
+pub enum LossLimit {
+    Amount(f64),
+    Percent(f64),
+}
+
+impl LossLimit {
+    // "300" 表示 300 USDT，"3%" 表示起点权益的 3%
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        let (number, percent) = match value.strip_suffix('%') {
+            Some(number) => (number.trim(), true),
+            None => (value, false),
+        };
+        let number: f64 = number.parse().context("亏损上限应为数字或百分比")?;
+        if number <= 0.0 {
+            bail!("亏损上限必须大于 0");
+        }
+        Ok(if percent {
+            LossLimit::Percent(number / 100.0)
+        } else {
+            LossLimit::Amount(number)
+        })
+    }
+
+    fn threshold(&self, base_equity: f64) -> f64 {
+        match self {
+            LossLimit::Amount(amount) => *amount,
+            LossLimit::Percent(pct) => base_equity * pct,
+        }
+    }
+
+    // 百分比上限附带换算后的金额
+    fn describe(&self, base_equity: f64) -> String {
+        match self {
+            LossLimit::Amount(_) => self.to_string(),
+            LossLimit::Percent(_) => format!("{} ({:.2} USDT)", self, self.threshold(base_equity)),
+        }
+    }
+}
+
+impl fmt::Display for LossLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LossLimit::Amount(amount) => write!(f, "{} USDT", amount),
+            LossLimit::Percent(pct) => write!(f, "{}%", pct * 100.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BreakerConfig {
+    pub daily_loss_limit: Option<LossLimit>,
+    pub rolling_loss_limit: Option<LossLimit>,
+    pub rolling_window_ms: i64,
+    pub flatten_on_halt: bool,            // 熔断时平掉全部持仓
+    pub kill_switch_file: Option<String>, // 未设置时为日志目录下的 KILL
+}
+
+fn env_loss_limit(key: &str) -> Result<Option<LossLimit>> {
+    match env::var(key) {
+        Ok(value) if !value.trim().is_empty() => LossLimit::parse(&value)
+            .map(Some)
+            .with_context(|| format!("{} 格式错误: {}", key, value)),
+        _ => Ok(None),
+    }
+}
+
+impl BreakerConfig {
+    pub fn from_env() -> Result<Self> {
+        let window_hours: f64 = match env::var("ROLLING_LOSS_WINDOW_HOURS") {
+            Ok(value) => value
+                .trim()
+                .parse()
+                .with_context(|| format!("ROLLING_LOSS_WINDOW_HOURS 格式错误: {}", value))?,
+            Err(_) => 4.0,
+        };
+        Ok(BreakerConfig {
+            daily_loss_limit: env_loss_limit("DAILY_LOSS_LIMIT")?,
+            rolling_loss_limit: env_loss_limit("ROLLING_LOSS_LIMIT")?,
+            rolling_window_ms: (window_hours * 3600.0 * 1000.0) as i64,
+            flatten_on_halt: env::var("HALT_FLATTEN")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            kill_switch_file: env::var("KILL_SWITCH_FILE")
+                .ok()
+                .filter(|v| !v.trim().is_empty()),
+        })
+    }
+
+    pub fn kill_switch_path(&self) -> PathBuf {
+        match &self.kill_switch_file {
+            Some(path) => PathBuf::from(path),
+            None => Path::new(logging::logs_directory()).join(DEFAULT_KILL_SWITCH_FILE),
+        }
+    }
+}
+
+impl fmt::Display for BreakerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show =
+            |limit: Option<LossLimit>| limit.map_or_else(|| "-".to_string(), |l| l.to_string());
+        write!(
+            f,
+            "日亏损上限 {}, 滚动 {:.1}h 亏损上限 {}, 熔断平仓 {}, 手动熔断文件 {}",
+            show(self.daily_loss_limit),
+            self.rolling_window_ms as f64 / 3_600_000.0,
+            show(self.rolling_loss_limit),
+            if self.flatten_on_halt { "是" } else { "否" },
+            self.kill_switch_path().display()
+        )
+    }
+}
+
+// 由亏损上限触发的熔断，到期自动解除
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Halt {
+    pub reason: String,
+    pub tripped_at: i64,
+    pub until: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct BreakerState {
+    day: Option<String>, // 当前 UTC 日期
+    day_start_equity: f64,
+    window: VecDeque<(i64, f64)>, // 滚动窗口内的 (时间, 权益) 采样
+    halt: Option<Halt>,
+    manual: bool, // 上次检查时手动熔断文件是否存在
+}
+
+pub struct CircuitBreaker {
+    config: BreakerConfig,
+    state: BreakerState,
+    path: PathBuf,
+}
+
+fn state_path() -> PathBuf {
+    Path::new(logging::logs_directory()).join(STATE_FILE)
+}
+
+impl CircuitBreaker {
+    // 从日志目录恢复熔断状态
+    pub fn load(config: BreakerConfig) -> Result<Self> {
+        Self::load_from(config, state_path())
+    }
+
+    fn load_from(config: BreakerConfig, path: PathBuf) -> Result<Self> {
+        let state = if path.exists() {
+            let content = fs::read_to_string(&path).context("读取熔断状态失败")?;
+            serde_json::from_str(&content).context("解析熔断状态失败")?
+        } else {
+            BreakerState::default()
+        };
+        Ok(CircuitBreaker {
+            config,
+            state,
+            path,
+        })
+    }
+
+    fn persist(&self) -> Result<()> {
+        let path = &self.path;
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).context("创建logs目录失败")?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        let json = serde_json::to_string_pretty(&self.state).context("序列化熔断状态失败")?;
+        fs::write(&tmp_path, json).context("写入熔断状态失败")?;
+        fs::rename(&tmp_path, path).context("替换熔断状态失败")?;
+        Ok(())
+    }
+
+    fn manual_reason(&self) -> Option<String> {
+        let path = self.config.kill_switch_path();
+        path.exists()
+            .then(|| format!("手动熔断 ({} 存在)", path.display()))
+    }
+
+    // 当前是否熔断；到期的熔断在此解除
+    pub fn halt_reason(&mut self, now_ms: i64) -> Result<Option<String>> {
+        if let Some(reason) = self.manual_reason() {
+            return Ok(Some(reason));
+        }
+        match &self.state.halt {
+            Some(halt) if now_ms < halt.until => Ok(Some(halt.reason.clone())),
+            Some(halt) => {
+                info!("熔断解除: {}", halt.reason);
+                self.state.halt = None;
+                self.persist()?;
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    // 每周期结束时以最新权益更新日内与滚动盈亏；返回本次新触发的熔断原因
+    pub fn update(&mut self, now_ms: i64, equity: f64) -> Result<Option<String>> {
+        let now = DateTime::from_timestamp_millis(now_ms).unwrap_or_else(Utc::now);
+        let day = now.format("%Y-%m-%d").to_string();
+        if self.state.day.as_deref() != Some(day.as_str()) {
+            // 新一天的起点取跨日前最后一次采样，零点到本次采样之间的亏损计入当日；
+            // 前一天没有采样（首次运行或停机跨日）时取当前权益
+            let day_start_ms = now_ms - now_ms.rem_euclid(DAY_MS);
+            self.state.day_start_equity = match self.state.window.back() {
+                Some((time, last)) if *time < day_start_ms && *time >= day_start_ms - DAY_MS => {
+                    *last
+                }
+                _ => equity,
+            };
+            self.state.day = Some(day);
+        }
+        self.state.window.push_back((now_ms, equity));
+        while self
+            .state
+            .window
+            .front()
+            .is_some_and(|(time, _)| *time < now_ms - self.config.rolling_window_ms)
+        {
+            self.state.window.pop_front();
+        }
+
+        let was_halted = self.state.manual || self.state.halt.is_some();
+        let manual = self.manual_reason();
+        self.state.manual = manual.is_some();
+        let mut tripped = manual;
+
+        if self.state.halt.is_none() {
+            let daily_pnl = equity - self.state.day_start_equity;
+            let window_peak = self.window_peak();
+            let rolling_pnl = equity - window_peak;
+
+            if let Some(limit) = self.config.daily_loss_limit {
+                let threshold = limit.threshold(self.state.day_start_equity);
+                if daily_pnl <= -threshold {
+                    let next_day = (now + ChronoDuration::days(1))
+                        .date_naive()
+                        .and_hms_opt(0, 0, 0)
+                        .map(|t| t.and_utc().timestamp_millis())
+                        .unwrap_or(now_ms);
+                    self.state.halt = Some(Halt {
+                        reason: format!(
+                            "日内亏损 {:.2} USDT 超过上限 {}",
+                            daily_pnl,
+                            limit.describe(self.state.day_start_equity)
+                        ),
+                        tripped_at: now_ms,
+                        until: next_day,
+                    });
+                }
+            }
+            if let (None, Some(limit)) = (&self.state.halt, self.config.rolling_loss_limit) {
+                let threshold = limit.threshold(window_peak);
+                if rolling_pnl <= -threshold {
+                    self.state.halt = Some(Halt {
+                        reason: format!(
+                            "滚动 {:.1}h 亏损 {:.2} USDT 超过上限 {}",
+                            self.config.rolling_window_ms as f64 / 3_600_000.0,
+                            rolling_pnl,
+                            limit.describe(window_peak)
+                        ),
+                        tripped_at: now_ms,
+                        until: now_ms + self.config.rolling_window_ms,
+                    });
+                }
+            }
+            if let Some(halt) = &self.state.halt {
+                tripped = Some(halt.reason.clone());
+            }
+        }
+        self.persist()?;
+
+        Ok(tripped.filter(|_| !was_halted))
+    }
+
+    // 滚动窗口内的最高权益，窗口内先涨后跌的回撤按峰值计算
+    fn window_peak(&self) -> f64 {
+        self.state
+            .window
+            .iter()
+            .map(|(_, e)| *e)
+            .fold(f64::MIN, f64::max)
+    }
+
+    // 日内盈亏与滚动窗口内自峰值的回撤，用于日志
+    pub fn pnl(&self) -> (f64, f64) {
+        let Some((_, latest)) = self.state.window.back() else {
+            return (0.0, 0.0);
+        };
+        (
+            latest - self.state.day_start_equity,
+            latest - self.window_peak(),
+        )
+    }
+}
+
+// 手动熔断：创建熔断文件（SIGUSR1 处理与运维脚本共用）
+pub fn trip_manually(config: &BreakerConfig, reason: &str) -> Result<()> {
+    let path = config.kill_switch_path();
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).context("创建熔断文件目录失败")?;
+    }
+    fs::write(&path, format!("{} @ {}\n", reason, Utc::now().to_rfc3339()))
+        .context("写入熔断文件失败")?;
+    warn!("已触发手动熔断: {} ({})", reason, path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY_START: i64 = 1_735_689_600_000; // 2025-01-01 00:00 UTC
+    const HOUR_MS: i64 = 3_600_000;
+
+    // 每个测试使用独立的临时目录存放状态与熔断文件
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("breaker_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn config(dir: &Path) -> BreakerConfig {
+        BreakerConfig {
+            daily_loss_limit: None,
+            rolling_loss_limit: None,
+            rolling_window_ms: 4 * HOUR_MS,
+            flatten_on_halt: false,
+            kill_switch_file: Some(dir.join("KILL").to_string_lossy().into_owned()),
+        }
+    }
+
+    fn breaker(dir: &Path, config: BreakerConfig) -> CircuitBreaker {
+        CircuitBreaker::load_from(config, dir.join(STATE_FILE)).unwrap()
+    }
+
+    #[test]
+    fn parse_loss_limit() {
+        assert!(matches!(LossLimit::parse("300").unwrap(), LossLimit::Amount(a) if a == 300.0));
+        assert!(
+            matches!(LossLimit::parse(" 3% ").unwrap(), LossLimit::Percent(p) if (p - 0.03).abs() < 1e-12)
+        );
+        assert!(LossLimit::parse("0").is_err());
+        assert!(LossLimit::parse("abc").is_err());
+    }
+
+    #[test]
+    fn daily_loss_counts_from_last_sample_before_midnight() {
+        let dir = temp_dir("daily");
+        let mut b = breaker(
+            &dir,
+            BreakerConfig {
+                daily_loss_limit: Some(LossLimit::Percent(0.03)),
+                ..config(&dir)
+            },
+        );
+        assert!(b
+            .update(DAY_START + 23 * HOUR_MS, 1000.0)
+            .unwrap()
+            .is_none());
+        // 零点后首次采样：起点为前一天 23:00 的 1000，亏损 40 超过 30
+        let now = DAY_START + 24 * HOUR_MS + HOUR_MS / 2;
+        let tripped = b.update(now, 960.0).unwrap();
+        assert!(tripped.unwrap().contains("日内亏损"));
+        assert_eq!(b.pnl().0, -40.0);
+        // 熔断至下一个 UTC 零点
+        assert!(b
+            .halt_reason(DAY_START + 48 * HOUR_MS - 1)
+            .unwrap()
+            .is_some());
+        assert!(b.halt_reason(DAY_START + 48 * HOUR_MS).unwrap().is_none());
+
+        // 停机跨越多日后以当前权益为起点
+        fs::remove_file(dir.join(STATE_FILE)).unwrap();
+        let mut b = breaker(&dir, config(&dir));
+        b.update(DAY_START, 1000.0).unwrap();
+        b.update(DAY_START + 3 * 24 * HOUR_MS, 900.0).unwrap();
+        assert_eq!(b.pnl().0, 0.0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rolling_loss_measured_from_window_peak() {
+        let dir = temp_dir("rolling");
+        let mut b = breaker(
+            &dir,
+            BreakerConfig {
+                rolling_loss_limit: Some(LossLimit::Amount(50.0)),
+                ..config(&dir)
+            },
+        );
+        assert!(b.update(DAY_START, 1000.0).unwrap().is_none());
+        assert!(b.update(DAY_START + HOUR_MS, 1100.0).unwrap().is_none());
+        // 相对窗口起点仍盈利 40，但自峰值 1100 回撤 60
+        let now = DAY_START + 2 * HOUR_MS;
+        let tripped = b.update(now, 1040.0).unwrap();
+        assert!(tripped.unwrap().contains("滚动"));
+        assert_eq!(b.pnl().1, -60.0);
+        // 熔断持续一个窗口长度
+        assert!(b.halt_reason(now + 4 * HOUR_MS - 1).unwrap().is_some());
+        assert!(b.halt_reason(now + 4 * HOUR_MS).unwrap().is_none());
+
+        // 峰值移出窗口后不再计入
+        fs::remove_file(dir.join(STATE_FILE)).unwrap();
+        let mut b = breaker(
+            &dir,
+            BreakerConfig {
+                rolling_loss_limit: Some(LossLimit::Amount(50.0)),
+                ..config(&dir)
+            },
+        );
+        b.update(DAY_START, 1100.0).unwrap();
+        b.update(DAY_START + 3 * HOUR_MS, 1050.0).unwrap();
+        assert!(b.update(DAY_START + 5 * HOUR_MS, 1040.0).unwrap().is_none());
+        assert_eq!(b.pnl().1, -10.0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn halt_survives_restart_until_expiry() {
+        let dir = temp_dir("persist");
+        let config = BreakerConfig {
+            daily_loss_limit: Some(LossLimit::Amount(100.0)),
+            ..config(&dir)
+        };
+        let mut b = breaker(&dir, config.clone());
+        b.update(DAY_START + HOUR_MS, 1000.0).unwrap();
+        assert!(b.update(DAY_START + 2 * HOUR_MS, 850.0).unwrap().is_some());
+
+        // 重启后恢复熔断，已熔断时不再重复报告
+        let mut restored = breaker(&dir, config.clone());
+        assert!(restored
+            .halt_reason(DAY_START + 3 * HOUR_MS)
+            .unwrap()
+            .unwrap()
+            .contains("日内亏损"));
+        assert!(restored
+            .update(DAY_START + 3 * HOUR_MS, 800.0)
+            .unwrap()
+            .is_none());
+
+        // 到期解除并落盘
+        assert!(restored
+            .halt_reason(DAY_START + 24 * HOUR_MS)
+            .unwrap()
+            .is_none());
+        let mut reloaded = breaker(&dir, config);
+        assert!(reloaded
+            .halt_reason(DAY_START + 24 * HOUR_MS)
+            .unwrap()
+            .is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn kill_file_halts_until_removed() {
+        let dir = temp_dir("manual");
+        let config = config(&dir);
+        let mut b = breaker(&dir, config.clone());
+        assert!(b.halt_reason(DAY_START).unwrap().is_none());
+
+        trip_manually(&config, "测试").unwrap();
+        assert!(b
+            .halt_reason(DAY_START)
+            .unwrap()
+            .unwrap()
+            .contains("手动熔断"));
+        // 首次发现时报告一次
+        assert!(b.update(DAY_START, 1000.0).unwrap().is_some());
+        assert!(b.update(DAY_START + HOUR_MS, 1000.0).unwrap().is_none());
+
+        fs::remove_file(config.kill_switch_path()).unwrap();
+        assert!(b.halt_reason(DAY_START + HOUR_MS).unwrap().is_none());
+        assert!(b.update(DAY_START + 2 * HOUR_MS, 1000.0).unwrap().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
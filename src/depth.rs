@@ -0,0 +1,461 @@
+// 盘口深度：REST 快照 (/fapi/v1/depth) 与 @depth 增量流按 updateId 顺序维护本地订单簿，
+// 计算买卖价差、前 N 档多空失衡与指定数量市价成交的预估滑点
+
+use anyhow::{anyhow, Context, Result};
+use futures::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::time::{sleep, timeout, Duration};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+pub const SNAPSHOT_LEVELS: u32 = 100; // 无增量流时 REST 快照档数
+const SYNC_SNAPSHOT_LEVELS: u32 = 1000; // 与增量流对齐时的快照档数
+const STALE_AFTER: Duration = Duration::from_secs(30);
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+const PRICE_SCALE: f64 = 1e8; // 价格转为整数键，保证有序
+
+fn get_binance_base_url() -> String {
+    match env::var("BINANCE_TESTNET").as_deref() {
+        Ok("true") => "https://testnet.binancefuture.com".to_string(),
+        _ => "https://fapi.binance.com".to_string(),
+    }
+}
+
+fn stream_base_url() -> String {
+    match env::var("BINANCE_TESTNET").as_deref() {
+        Ok("true") => "wss://stream.binancefuture.com/stream".to_string(),
+        _ => "wss://fstream.binance.com/stream".to_string(),
+    }
+}
+
+// 订单簿快照：bids 按价格降序，asks 按价格升序，元素为 (价格, 数量)
+#[derive(Debug, Clone, Default)]
+pub struct OrderBook {
+    pub bids: Vec<(f64, f64)>,
+    pub asks: Vec<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DepthMetrics {
+    pub best_bid: f64,
+    pub best_ask: f64,
+    pub spread_bps: f64,               // (卖一 - 买一) / 中间价，基点
+    pub levels: usize,                 // 统计失衡使用的档数
+    pub bid_depth_usdt: f64,           // 前 N 档买盘名义价值
+    pub ask_depth_usdt: f64,           // 前 N 档卖盘名义价值
+    pub imbalance: f64,                // (买盘 - 卖盘) / (买盘 + 卖盘)，-1 ~ 1
+    pub quantity: f64,                 // 估算滑点使用的数量
+    pub buy_slippage_bps: Option<f64>, // 市价买入成交均价相对中间价，深度不足时为空
+    pub sell_slippage_bps: Option<f64>,
+}
+
+impl OrderBook {
+    pub fn metrics(&self, levels: usize, quantity: f64) -> Option<DepthMetrics> {
+        let (best_bid, _) = *self.bids.first()?;
+        let (best_ask, _) = *self.asks.first()?;
+        let mid = (best_bid + best_ask) / 2.0;
+        if mid <= 0.0 {
+            return None;
+        }
+        let depth =
+            |side: &[(f64, f64)]| -> f64 { side.iter().take(levels).map(|(p, q)| p * q).sum() };
+        let bid_depth = depth(&self.bids);
+        let ask_depth = depth(&self.asks);
+        let imbalance = if bid_depth + ask_depth > 0.0 {
+            (bid_depth - ask_depth) / (bid_depth + ask_depth)
+        } else {
+            0.0
+        };
+        Some(DepthMetrics {
+            best_bid,
+            best_ask,
+            spread_bps: (best_ask - best_bid) / mid * 10_000.0,
+            levels,
+            bid_depth_usdt: bid_depth,
+            ask_depth_usdt: ask_depth,
+            imbalance,
+            quantity,
+            buy_slippage_bps: average_fill(&self.asks, quantity)
+                .map(|avg| (avg - mid) / mid * 10_000.0),
+            sell_slippage_bps: average_fill(&self.bids, quantity)
+                .map(|avg| (mid - avg) / mid * 10_000.0),
+        })
+    }
+}
+
+// 逐档吃单的成交均价，快照深度不足时返回 None
+fn average_fill(side: &[(f64, f64)], quantity: f64) -> Option<f64> {
+    if quantity <= 0.0 {
+        return side.first().map(|(p, _)| *p);
+    }
+    let mut remaining = quantity;
+    let mut cost = 0.0;
+    for (price, qty) in side {
+        let take = remaining.min(*qty);
+        cost += take * price;
+        remaining -= take;
+        if remaining <= f64::EPSILON {
+            return Some(cost / quantity);
+        }
+    }
+    None
+}
+
+impl std::fmt::Display for DepthMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let show = |value: Option<f64>| {
+            value.map_or_else(|| "深度不足".to_string(), |v| format!("{:.2}bp", v))
+        };
+        write!(
+            f,
+            "买一 {} / 卖一 {}, 价差 {:.2}bp, 前{}档失衡 {:+.2} (买 {:.0} / 卖 {:.0} USDT), 数量 {} 滑点 买 {} 卖 {}",
+            self.best_bid,
+            self.best_ask,
+            self.spread_bps,
+            self.levels,
+            self.imbalance,
+            self.bid_depth_usdt,
+            self.ask_depth_usdt,
+            self.quantity,
+            show(self.buy_slippage_bps),
+            show(self.sell_slippage_bps)
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DepthSnapshotResponse {
+    last_update_id: i64,
+    bids: Vec<[String; 2]>,
+    asks: Vec<[String; 2]>,
+}
+
+fn parse_levels(levels: &[[String; 2]]) -> impl Iterator<Item = (f64, f64)> + '_ {
+    levels
+        .iter()
+        .filter_map(|[price, qty]| Some((price.parse().ok()?, qty.parse().ok()?)))
+}
+
+async fn fetch_snapshot(symbol: &str, limit: u32) -> Result<DepthSnapshotResponse> {
+    let url = format!(
+        "{}/fapi/v1/depth?symbol={}&limit={}",
+        get_binance_base_url(),
+        symbol,
+        limit
+    );
+    reqwest::get(&url)
+        .await
+        .with_context(|| format!("获取 {} 盘口失败", symbol))?
+        .json()
+        .await
+        .context("解析盘口数据失败")
+}
+
+// REST 盘口快照
+pub async fn fetch_depth(symbol: &str, limit: u32) -> Result<OrderBook> {
+    let snapshot = fetch_snapshot(symbol, limit).await?;
+    Ok(OrderBook {
+        bids: parse_levels(&snapshot.bids).collect(),
+        asks: parse_levels(&snapshot.asks).collect(),
+    })
+}
+
+// ===== 增量流维护的本地订单簿 =====
+
+#[derive(Debug, Deserialize)]
+struct CombinedMessage {
+    data: DepthUpdate,
+}
+
+#[derive(Debug, Deserialize)]
+struct DepthUpdate {
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "U")]
+    first_update_id: i64,
+    #[serde(rename = "u")]
+    final_update_id: i64,
+    #[serde(rename = "pu")]
+    previous_final_update_id: i64,
+    #[serde(rename = "b")]
+    bids: Vec<[String; 2]>,
+    #[serde(rename = "a")]
+    asks: Vec<[String; 2]>,
+}
+
+#[derive(Default)]
+struct LocalBook {
+    last_update_id: i64,
+    bids: BTreeMap<i64, (f64, f64)>,
+    asks: BTreeMap<i64, (f64, f64)>,
+    synced: bool, // 已应用与快照衔接的首个增量
+}
+
+fn price_key(price: f64) -> i64 {
+    (price * PRICE_SCALE).round() as i64
+}
+
+impl LocalBook {
+    fn from_snapshot(snapshot: &DepthSnapshotResponse) -> Self {
+        let mut book = LocalBook {
+            last_update_id: snapshot.last_update_id,
+            ..LocalBook::default()
+        };
+        book.apply_levels(&snapshot.bids, &snapshot.asks);
+        book
+    }
+
+    fn apply_levels(&mut self, bids: &[[String; 2]], asks: &[[String; 2]]) {
+        for (side, levels) in [(&mut self.bids, bids), (&mut self.asks, asks)] {
+            for (price, qty) in parse_levels(levels) {
+                if qty == 0.0 {
+                    side.remove(&price_key(price));
+                } else {
+                    side.insert(price_key(price), (price, qty));
+                }
+            }
+        }
+    }
+
+    // 按币安规则应用增量；返回 false 表示序号断档，需要重新拉取快照
+    fn apply(&mut self, update: &DepthUpdate) -> bool {
+        if update.final_update_id < self.last_update_id {
+            return true;
+        }
+        if self.synced {
+            if update.previous_final_update_id != self.last_update_id {
+                return false;
+            }
+        } else if update.first_update_id > self.last_update_id {
+            // 首个增量必须覆盖快照的 lastUpdateId
+            return false;
+        }
+        self.apply_levels(&update.bids, &update.asks);
+        self.last_update_id = update.final_update_id;
+        self.synced = true;
+        true
+    }
+
+    fn snapshot(&self, levels: usize) -> OrderBook {
+        OrderBook {
+            bids: self.bids.values().rev().take(levels).copied().collect(),
+            asks: self.asks.values().take(levels).copied().collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct DepthState {
+    books: HashMap<String, LocalBook>,
+    connected: bool,
+    last_message: Option<Instant>,
+}
+
+pub struct DepthStream {
+    symbols: Vec<String>,
+    state: Mutex<DepthState>,
+}
+
+impl DepthStream {
+    pub fn start(symbols: &[String]) -> Arc<Self> {
+        let stream = Arc::new(DepthStream {
+            symbols: symbols.to_vec(),
+            state: Mutex::new(DepthState::default()),
+        });
+        let worker = stream.clone();
+        tokio::spawn(async move {
+            worker.run().await;
+        });
+        stream
+    }
+
+    // 前 levels 档订单簿；未同步或已过期时返回 None
+    pub fn book(&self, symbol: &str, levels: usize) -> Option<OrderBook> {
+        let state = self.state.lock().unwrap();
+        let fresh = state.connected
+            && state
+                .last_message
+                .is_some_and(|time| time.elapsed() < STALE_AFTER);
+        if !fresh {
+            return None;
+        }
+        let book = state.books.get(symbol).filter(|book| book.synced)?;
+        Some(book.snapshot(levels))
+    }
+
+    async fn run(&self) {
+        let mut backoff = Duration::from_secs(5);
+        loop {
+            match self.connect_and_stream().await {
+                Ok(()) => {
+                    debug!("盘口 WebSocket 流结束，准备重连");
+                    backoff = Duration::from_secs(5);
+                    sleep(Duration::from_secs(1)).await;
+                }
+                Err(err) => {
+                    warn!(
+                        "盘口 WebSocket 流异常，{} 秒后重连: {:#}",
+                        backoff.as_secs(),
+                        err
+                    );
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(Duration::from_secs(60));
+                }
+            }
+        }
+    }
+
+    async fn resync(&self, symbol: &str) -> Result<()> {
+        let snapshot = fetch_snapshot(symbol, SYNC_SNAPSHOT_LEVELS).await?;
+        self.state
+            .lock()
+            .unwrap()
+            .books
+            .insert(symbol.to_string(), LocalBook::from_snapshot(&snapshot));
+        Ok(())
+    }
+
+    async fn connect_and_stream(&self) -> Result<()> {
+        let streams: Vec<String> = self
+            .symbols
+            .iter()
+            .map(|symbol| format!("{}@depth@100ms", symbol.to_lowercase()))
+            .collect();
+        let url = format!("{}?streams={}", stream_base_url(), streams.join("/"));
+        let (mut ws_stream, _) = connect_async(&url)
+            .await
+            .with_context(|| format!("连接盘口 WebSocket 失败: {}", url))?;
+
+        // 先订阅再拉快照，快照之前的增量按 updateId 丢弃
+        let result = async {
+            for symbol in &self.symbols {
+                self.resync(symbol).await?;
+            }
+            {
+                let mut state = self.state.lock().unwrap();
+                state.connected = true;
+                state.last_message = Some(Instant::now());
+            }
+            info!("盘口 WebSocket 已连接: {} 个标的", self.symbols.len());
+
+            loop {
+                let message = timeout(READ_TIMEOUT, ws_stream.next())
+                    .await
+                    .map_err(|_| anyhow!("盘口 WebSocket {} 秒无消息", READ_TIMEOUT.as_secs()))?;
+                match message {
+                    Some(Ok(Message::Text(text))) => {
+                        let Ok(message) = serde_json::from_str::<CombinedMessage>(&text) else {
+                            continue;
+                        };
+                        let update = message.data;
+                        let in_sequence = {
+                            let mut state = self.state.lock().unwrap();
+                            state.last_message = Some(Instant::now());
+                            state
+                                .books
+                                .get_mut(&update.symbol)
+                                .is_none_or(|book| book.apply(&update))
+                        };
+                        if !in_sequence {
+                            warn!("{} 盘口增量序号不连续，重新拉取快照", update.symbol);
+                            self.resync(&update.symbol).await?;
+                        }
+                    }
+                    Some(Ok(Message::Ping(payload))) => {
+                        ws_stream.send(Message::Pong(payload)).await?;
+                    }
+                    Some(Ok(Message::Close(frame))) => {
+                        return Err(anyhow!("盘口 WebSocket 主动关闭: {:?}", frame));
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(err)) => return Err(err.into()),
+                    None => return Ok(()),
+                }
+            }
+        }
+        .await;
+
+        self.state.lock().unwrap().connected = false;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 快照 lastUpdateId = 100，买一 99.5、卖一 100.5
+    fn synced_from_snapshot() -> LocalBook {
+        let snapshot: DepthSnapshotResponse = serde_json::from_str(
+            r#"{"lastUpdateId":100,"bids":[["99.5","2"],["99.0","3"]],"asks":[["100.5","1"],["101.0","4"]]}"#,
+        )
+        .unwrap();
+        LocalBook::from_snapshot(&snapshot)
+    }
+
+    fn update(first: i64, last: i64, previous: i64, bids: &str, asks: &str) -> DepthUpdate {
+        serde_json::from_str(&format!(
+            r#"{{"e":"depthUpdate","E":1,"T":1,"s":"BTCUSDT","U":{},"u":{},"pu":{},"b":{},"a":{}}}"#,
+            first, last, previous, bids, asks
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn drops_events_older_than_snapshot() {
+        let mut book = synced_from_snapshot();
+        assert!(book.apply(&update(90, 99, 89, r#"[["99.5","7"]]"#, "[]")));
+        assert!(!book.synced);
+        assert_eq!(book.last_update_id, 100);
+        assert_eq!(book.snapshot(1).bids, vec![(99.5, 2.0)]);
+    }
+
+    #[test]
+    fn first_event_must_bridge_snapshot() {
+        // U > lastUpdateId：快照之后的增量已丢失
+        let mut book = synced_from_snapshot();
+        assert!(!book.apply(&update(101, 105, 100, r#"[["99.5","7"]]"#, "[]")));
+        assert!(!book.synced);
+
+        // U <= lastUpdateId <= u：首个增量覆盖快照，之后按 pu 衔接
+        let mut book = synced_from_snapshot();
+        assert!(book.apply(&update(95, 105, 94, r#"[["99.5","7"]]"#, "[]")));
+        assert!(book.synced);
+        assert_eq!(book.last_update_id, 105);
+        assert_eq!(book.snapshot(1).bids, vec![(99.5, 7.0)]);
+        assert!(book.apply(&update(106, 110, 105, "[]", r#"[["100.5","2"]]"#)));
+        assert_eq!(book.last_update_id, 110);
+        assert_eq!(book.snapshot(1).asks, vec![(100.5, 2.0)]);
+    }
+
+    #[test]
+    fn previous_id_gap_requests_resync() {
+        let mut book = synced_from_snapshot();
+        assert!(book.apply(&update(95, 105, 94, "[]", "[]")));
+        // pu 应为 105，中间的增量丢失
+        assert!(!book.apply(&update(108, 112, 107, r#"[["99.5","7"]]"#, "[]")));
+        assert_eq!(book.last_update_id, 105);
+        assert_eq!(book.snapshot(1).bids, vec![(99.5, 2.0)]);
+    }
+
+    #[test]
+    fn zero_quantity_removes_level() {
+        let mut book = synced_from_snapshot();
+        assert!(book.apply(&update(
+            95,
+            105,
+            94,
+            r#"[["99.5","0"],["98.0","5"]]"#,
+            r#"[["100.5","0.000"]]"#
+        )));
+        let top = book.snapshot(5);
+        assert_eq!(top.bids, vec![(99.0, 3.0), (98.0, 5.0)]);
+        assert_eq!(top.asks, vec![(101.0, 4.0)]);
+    }
+}
@@ -0,0 +1,216 @@
+// 永续合约衍生品数据：溢价指数与资金费率历史、持仓量历史、主动买卖量比，
+// 供行情分析员与策略研究员判断拥挤度与多空力量
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+
+const FUNDING_POINTS: u32 = 8; // 约 2.7 天的资金费率
+const HISTORY_POINTS: u32 = 12; // 持仓量与主动买卖比的采样数
+
+fn get_binance_base_url() -> String {
+    match env::var("BINANCE_TESTNET").as_deref() {
+        Ok("true") => "https://testnet.binancefuture.com".to_string(),
+        _ => "https://fapi.binance.com".to_string(),
+    }
+}
+
+// /futures/data 统计接口支持的周期，不支持的K线周期就近取较粗的一档
+pub fn data_period(interval: &str) -> &'static str {
+    match interval {
+        "1m" | "3m" | "5m" => "5m",
+        "15m" => "15m",
+        "30m" => "30m",
+        "1h" => "1h",
+        "2h" => "2h",
+        "4h" => "4h",
+        "6h" => "6h",
+        "12h" => "12h",
+        _ => "1d",
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FundingPoint {
+    pub time: i64,
+    pub rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenInterestPoint {
+    pub time: i64,
+    pub open_interest: f64,       // 持仓量（币）
+    pub open_interest_value: f64, // 持仓价值 (USDT)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TakerRatioPoint {
+    pub time: i64,
+    pub buy_sell_ratio: f64, // 主动买入量 / 主动卖出量
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DerivativesSnapshot {
+    pub period: String,
+    pub mark_price: f64,
+    pub index_price: f64,
+    pub basis_bps: f64,         // (标记价格 - 指数价格) / 指数价格，基点
+    pub funding_rate: f64,      // 当期预测资金费率
+    pub next_funding_time: i64, // 下次结算时间
+    pub avg_funding_rate: f64,  // 近期已结算资金费率均值
+    pub funding_history: Vec<FundingPoint>,
+    pub open_interest_change_pct: f64, // 持仓价值在统计窗口内的变化
+    pub open_interest: Vec<OpenInterestPoint>,
+    pub taker_buy_sell_ratio: f64, // 最近一期主动买卖比
+    pub taker_ratio: Vec<TakerRatioPoint>,
+}
+
+impl std::fmt::Display for DerivativesSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "资金费率 {:+.4}% (近{}期均值 {:+.4}%), 基差 {:+.2}bp, 持仓价值 {} 变化 {:+.2}%, 主动买卖比 {:.3}",
+            self.funding_rate * 100.0,
+            self.funding_history.len(),
+            self.avg_funding_rate * 100.0,
+            self.basis_bps,
+            self.period,
+            self.open_interest_change_pct,
+            self.taker_buy_sell_ratio
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PremiumIndexResponse {
+    mark_price: String,
+    index_price: String,
+    last_funding_rate: String,
+    next_funding_time: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FundingRateResponse {
+    funding_rate: String,
+    funding_time: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OpenInterestResponse {
+    sum_open_interest: String,
+    sum_open_interest_value: String,
+    timestamp: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TakerRatioResponse {
+    buy_sell_ratio: String,
+    buy_vol: String,
+    sell_vol: String,
+    timestamp: i64,
+}
+
+fn parse_float(value: &str) -> f64 {
+    value.parse().unwrap_or(0.0)
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(url: &str, what: &str) -> Result<T> {
+    reqwest::get(url)
+        .await
+        .with_context(|| format!("获取{}失败", what))?
+        .json()
+        .await
+        .with_context(|| format!("解析{}失败", what))
+}
+
+pub async fn fetch_snapshot(symbol: &str, interval: &str) -> Result<DerivativesSnapshot> {
+    let base_url = get_binance_base_url();
+    let period = data_period(interval);
+    let premium_url = format!("{}/fapi/v1/premiumIndex?symbol={}", base_url, symbol);
+    let funding_url = format!(
+        "{}/fapi/v1/fundingRate?symbol={}&limit={}",
+        base_url, symbol, FUNDING_POINTS
+    );
+    let oi_url = format!(
+        "{}/futures/data/openInterestHist?symbol={}&period={}&limit={}",
+        base_url, symbol, period, HISTORY_POINTS
+    );
+    let taker_url = format!(
+        "{}/futures/data/takerlongshortRatio?symbol={}&period={}&limit={}",
+        base_url, symbol, period, HISTORY_POINTS
+    );
+
+    let (premium, funding, open_interest, taker) = tokio::try_join!(
+        get_json::<PremiumIndexResponse>(&premium_url, "溢价指数"),
+        get_json::<Vec<FundingRateResponse>>(&funding_url, "资金费率历史"),
+        get_json::<Vec<OpenInterestResponse>>(&oi_url, "持仓量历史"),
+        get_json::<Vec<TakerRatioResponse>>(&taker_url, "主动买卖量比"),
+    )?;
+
+    let mark_price = parse_float(&premium.mark_price);
+    let index_price = parse_float(&premium.index_price);
+    let funding_history: Vec<FundingPoint> = funding
+        .iter()
+        .map(|f| FundingPoint {
+            time: f.funding_time,
+            rate: parse_float(&f.funding_rate),
+        })
+        .collect();
+    let avg_funding_rate = if funding_history.is_empty() {
+        0.0
+    } else {
+        funding_history.iter().map(|f| f.rate).sum::<f64>() / funding_history.len() as f64
+    };
+
+    // 统计接口按时间升序返回
+    let open_interest: Vec<OpenInterestPoint> = open_interest
+        .iter()
+        .map(|oi| OpenInterestPoint {
+            time: oi.timestamp,
+            open_interest: parse_float(&oi.sum_open_interest),
+            open_interest_value: parse_float(&oi.sum_open_interest_value),
+        })
+        .collect();
+    let open_interest_change_pct = match (open_interest.first(), open_interest.last()) {
+        (Some(first), Some(last)) if first.open_interest_value > 0.0 => {
+            (last.open_interest_value - first.open_interest_value) / first.open_interest_value
+                * 100.0
+        }
+        _ => 0.0,
+    };
+
+    let taker_ratio: Vec<TakerRatioPoint> = taker
+        .iter()
+        .map(|t| TakerRatioPoint {
+            time: t.timestamp,
+            buy_sell_ratio: parse_float(&t.buy_sell_ratio),
+            buy_volume: parse_float(&t.buy_vol),
+            sell_volume: parse_float(&t.sell_vol),
+        })
+        .collect();
+
+    Ok(DerivativesSnapshot {
+        period: period.to_string(),
+        mark_price,
+        index_price,
+        basis_bps: if index_price > 0.0 {
+            (mark_price - index_price) / index_price * 10_000.0
+        } else {
+            0.0
+        },
+        funding_rate: parse_float(&premium.last_funding_rate),
+        next_funding_time: premium.next_funding_time,
+        avg_funding_rate,
+        funding_history,
+        open_interest_change_pct,
+        taker_buy_sell_ratio: taker_ratio.last().map_or(0.0, |t| t.buy_sell_ratio),
+        open_interest,
+        taker_ratio,
+    })
+}
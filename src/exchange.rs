@@ -0,0 +1,613 @@
+// 交易所抽象层：行情、账户、持仓、杠杆与下单
+
+use crate::depth::{self, DepthStream, OrderBook};
+use crate::derivatives::{self, DerivativesSnapshot};
+use crate::executor::{self, AccountInfo, SymbolConstraints};
+use crate::kline_store::KlineStore;
+use crate::margin::LeverageBracket;
+use crate::market::{self, BookTicker};
+use crate::market_stream::MarketStream;
+use crate::types::{Kline, PositionBook, PositionSide};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+impl OrderSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderSide::Buy => "BUY",
+            OrderSide::Sell => "SELL",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderType {
+    Market,
+    Limit,
+    StopMarket,       // 止损市价单
+    TakeProfitMarket, // 止盈市价单
+}
+
+impl OrderType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderType::Market => "MARKET",
+            OrderType::Limit => "LIMIT",
+            OrderType::StopMarket => "STOP_MARKET",
+            OrderType::TakeProfitMarket => "TAKE_PROFIT_MARKET",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "MARKET" => Some(OrderType::Market),
+            "LIMIT" => Some(OrderType::Limit),
+            "STOP_MARKET" => Some(OrderType::StopMarket),
+            "TAKE_PROFIT_MARKET" => Some(OrderType::TakeProfitMarket),
+            _ => None,
+        }
+    }
+
+    // 止损/止盈条件单
+    pub fn is_protective(&self) -> bool {
+        matches!(self, OrderType::StopMarket | OrderType::TakeProfitMarket)
+    }
+}
+
+// 限价单有效方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeInForce {
+    Gtc, // 成交为止
+    Ioc, // 立即成交，剩余撤销
+    Fok, // 全部成交或撤销
+    Gtx, // 只做 Maker (post-only)
+}
+
+impl TimeInForce {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeInForce::Gtc => "GTC",
+            TimeInForce::Ioc => "IOC",
+            TimeInForce::Fok => "FOK",
+            TimeInForce::Gtx => "GTX",
+        }
+    }
+}
+
+// 账户持仓模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionMode {
+    #[default]
+    Hedge, // 双向持仓：多空两条腿独立，按 positionSide 区分
+    OneWay, // 单向持仓：每个标的一个净头寸，positionSide=BOTH，平仓使用 reduceOnly
+}
+
+impl PositionMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "hedge" | "dual" => Some(PositionMode::Hedge),
+            "one_way" | "oneway" | "one-way" => Some(PositionMode::OneWay),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PositionMode::Hedge => "双向持仓",
+            PositionMode::OneWay => "单向持仓",
+        }
+    }
+}
+
+// 下单请求；position_side 表示订单作用的持仓方向，单向持仓下由交易所实现映射为 BOTH
+#[derive(Debug, Clone)]
+pub struct OrderRequest {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub position_side: PositionSide,
+    pub order_type: OrderType,
+    pub quantity: f64,
+    pub price: Option<f64>, // 限价单价格
+    pub time_in_force: Option<TimeInForce>,
+    pub stop_price: Option<f64>,         // 条件单触发价
+    pub close_position: bool,            // 触发后平掉该方向全部持仓，忽略 quantity
+    pub client_order_id: Option<String>, // newClientOrderId，用于重试去重与重启对账
+}
+
+impl OrderRequest {
+    pub fn market(
+        symbol: &str,
+        side: OrderSide,
+        position_side: PositionSide,
+        quantity: f64,
+    ) -> Self {
+        OrderRequest {
+            symbol: symbol.to_string(),
+            side,
+            position_side,
+            order_type: OrderType::Market,
+            quantity,
+            price: None,
+            time_in_force: None,
+            stop_price: None,
+            close_position: false,
+            client_order_id: None,
+        }
+    }
+
+    pub fn limit(
+        symbol: &str,
+        side: OrderSide,
+        position_side: PositionSide,
+        quantity: f64,
+        price: f64,
+        time_in_force: TimeInForce,
+    ) -> Self {
+        OrderRequest {
+            order_type: OrderType::Limit,
+            price: Some(price),
+            time_in_force: Some(time_in_force),
+            ..Self::market(symbol, side, position_side, quantity)
+        }
+    }
+
+    // 持仓保护单：quantity 为 None 时使用 closePosition
+    pub fn protective(
+        symbol: &str,
+        order_type: OrderType,
+        position_side: PositionSide,
+        stop_price: f64,
+        quantity: Option<f64>,
+    ) -> Self {
+        let side = match position_side {
+            PositionSide::Long => OrderSide::Sell,
+            PositionSide::Short => OrderSide::Buy,
+        };
+        OrderRequest {
+            symbol: symbol.to_string(),
+            side,
+            position_side,
+            order_type,
+            quantity: quantity.unwrap_or(0.0),
+            price: None,
+            time_in_force: None,
+            stop_price: Some(stop_price),
+            close_position: quantity.is_none(),
+            client_order_id: None,
+        }
+    }
+
+    pub fn with_client_order_id(mut self, client_order_id: String) -> Self {
+        self.client_order_id = Some(client_order_id);
+        self
+    }
+
+    // 减少 position_side 方向持仓的订单（平仓与保护单）
+    pub fn reduces_position(&self) -> bool {
+        matches!(
+            (&self.position_side, self.side),
+            (PositionSide::Long, OrderSide::Sell) | (PositionSide::Short, OrderSide::Buy)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Expired,
+    Rejected,
+}
+
+impl OrderStatus {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "NEW" => Some(OrderStatus::New),
+            "PARTIALLY_FILLED" => Some(OrderStatus::PartiallyFilled),
+            "FILLED" => Some(OrderStatus::Filled),
+            "CANCELED" => Some(OrderStatus::Canceled),
+            "EXPIRED" | "EXPIRED_IN_MATCH" => Some(OrderStatus::Expired),
+            "REJECTED" => Some(OrderStatus::Rejected),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::New => "NEW",
+            OrderStatus::PartiallyFilled => "PARTIALLY_FILLED",
+            OrderStatus::Filled => "FILLED",
+            OrderStatus::Canceled => "CANCELED",
+            OrderStatus::Expired => "EXPIRED",
+            OrderStatus::Rejected => "REJECTED",
+        }
+    }
+
+    // 订单已结束，不会再有成交
+    pub fn is_final(&self) -> bool {
+        !matches!(self, OrderStatus::New | OrderStatus::PartiallyFilled)
+    }
+}
+
+// 订单当前状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderState {
+    pub order_id: i64,
+    pub status: OrderStatus,
+    pub executed_qty: f64,
+    pub avg_price: f64,
+}
+
+impl fmt::Display for OrderState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "订单ID:{}, 状态:{}", self.order_id, self.status.as_str())?;
+        if self.executed_qty > 0.0 {
+            write!(f, ", 成交:{}@{:.6}", self.executed_qty, self.avg_price)?;
+        }
+        Ok(())
+    }
+}
+
+// 订单的单笔成交明细
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fill {
+    pub trade_id: i64,
+    pub price: f64,
+    pub quantity: f64,
+    pub commission: f64,
+    pub commission_asset: String,
+    pub realized_pnl: f64, // 平仓成交的已实现盈亏（不含手续费）
+    pub maker: bool,
+    pub time: i64,
+}
+
+// 账户成交记录，用于识别交易所侧止损止盈与强平等非本程序下单的平仓
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountTrade {
+    pub symbol: String,
+    pub order_id: i64,
+    pub side: OrderSide,
+    pub position_side: PositionSide,
+    pub fill: Fill,
+}
+
+impl AccountTrade {
+    // 卖出多头或买入空头为平仓成交
+    pub fn is_close(&self) -> bool {
+        matches!(
+            (self.side, &self.position_side),
+            (OrderSide::Sell, PositionSide::Long) | (OrderSide::Buy, PositionSide::Short)
+        )
+    }
+}
+
+// 与盈亏相关的资金流水类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IncomeKind {
+    RealizedPnl,
+    Commission,
+    FundingFee,
+}
+
+impl IncomeKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "REALIZED_PNL" => Some(IncomeKind::RealizedPnl),
+            "COMMISSION" => Some(IncomeKind::Commission),
+            "FUNDING_FEE" => Some(IncomeKind::FundingFee),
+            _ => None,
+        }
+    }
+}
+
+// 账户资金流水，amount 为带符号金额（手续费为负，收到资金费为正）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Income {
+    pub symbol: String,
+    pub kind: IncomeKind,
+    pub amount: f64,
+    pub asset: String,
+    pub time: i64,
+    pub tran_id: i64,
+}
+
+// 未成交挂单
+#[derive(Debug, Clone)]
+pub struct OpenOrder {
+    pub order_id: i64,
+    pub side: OrderSide,
+    pub position_side: PositionSide,
+    pub order_type: OrderType,
+    pub quantity: f64,
+    pub price: f64,
+    pub stop_price: f64,
+    pub close_position: bool,
+}
+
+#[async_trait]
+pub trait Exchange: Send + Sync {
+    fn name(&self) -> &str;
+
+    // 交易所时钟（毫秒），回放时为当前K线收盘时间
+    fn now_millis(&self) -> i64 {
+        chrono::Utc::now().timestamp_millis()
+    }
+
+    // 行情数据
+    async fn fetch_klines(&self, symbol: &str, interval: &str, limit: u32) -> Result<Vec<Kline>>;
+    async fn fetch_current_price(&self, symbol: &str) -> Result<f64>;
+    // 标记价格，默认以最新价近似
+    async fn fetch_mark_price(&self, symbol: &str) -> Result<f64> {
+        self.fetch_current_price(symbol).await
+    }
+    async fn fetch_book_ticker(&self, symbol: &str) -> Result<BookTicker>;
+    // 盘口深度快照（前若干档）
+    async fn fetch_depth(&self, symbol: &str) -> Result<OrderBook>;
+    // 资金费率、持仓量与主动买卖比，无对应数据（如历史回放）时返回 None
+    async fn fetch_derivatives(
+        &self,
+        symbol: &str,
+        interval: &str,
+    ) -> Result<Option<DerivativesSnapshot>>;
+    async fn fetch_symbol_constraints(
+        &self,
+        symbols: &[String],
+    ) -> Result<HashMap<String, SymbolConstraints>>;
+
+    // 账户与持仓
+    async fn get_account_info(&self) -> Result<AccountInfo>;
+    async fn get_positions(&self, symbol: &str) -> Result<PositionBook>;
+    // 多个标的的持仓，默认逐个查询
+    async fn get_portfolio_positions(
+        &self,
+        symbols: &[String],
+    ) -> Result<HashMap<String, PositionBook>> {
+        let mut books = HashMap::new();
+        for symbol in symbols {
+            books.insert(symbol.clone(), self.get_positions(symbol).await?);
+        }
+        Ok(books)
+    }
+    // [start_ms, end_ms] 内的已实现盈亏、手续费与资金费流水
+    async fn income(&self, start_ms: i64, end_ms: i64) -> Result<Vec<Income>>;
+    // [start_ms, end_ms] 内标的的全部成交
+    async fn account_trades(
+        &self,
+        symbol: &str,
+        start_ms: i64,
+        end_ms: i64,
+    ) -> Result<Vec<AccountTrade>>;
+
+    // 账户设置
+    fn position_mode(&self) -> PositionMode;
+    // 将账户切换到配置的持仓模式，已是该模式时视为成功
+    async fn apply_position_mode(&self) -> Result<()>;
+    async fn set_leverage(&self, symbol: &str, leverage: u32) -> Result<()>;
+    // 杠杆分层（维持保证金率），用于估算强平价
+    async fn leverage_brackets(&self, symbol: &str) -> Result<Vec<LeverageBracket>>;
+
+    // 下单与订单查询
+    async fn place_order(&self, order: &OrderRequest) -> Result<OrderState>;
+    async fn get_order(&self, symbol: &str, order_id: i64) -> Result<OrderState>;
+    // 按客户端订单号查询，订单不存在时返回 None
+    async fn find_order(&self, symbol: &str, client_order_id: &str) -> Result<Option<OrderState>>;
+    async fn order_fills(&self, symbol: &str, order_id: i64) -> Result<Vec<Fill>>;
+    async fn open_orders(&self, symbol: &str) -> Result<Vec<OpenOrder>>;
+    async fn cancel_order(&self, symbol: &str, order_id: i64) -> Result<()>;
+}
+
+// ===== Binance U本位合约实现 =====
+pub struct BinanceExchange {
+    api_key: String,
+    secret: String,
+    kline_store: Option<Arc<KlineStore>>,
+    market_stream: Option<Arc<MarketStream>>,
+    depth_stream: Option<Arc<DepthStream>>,
+    position_mode: PositionMode,
+    // 交易规则缓存，下单时按 tickSize/stepSize 的精度格式化价格与数量
+    constraints: RwLock<HashMap<String, SymbolConstraints>>,
+}
+
+impl BinanceExchange {
+    pub fn new(api_key: &str, secret: &str) -> Self {
+        BinanceExchange {
+            api_key: api_key.to_string(),
+            secret: secret.to_string(),
+            kline_store: None,
+            market_stream: None,
+            depth_stream: None,
+            position_mode: PositionMode::Hedge,
+            constraints: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // K线优先从本地存储读取并增量补齐
+    pub fn with_kline_store(mut self, store: Arc<KlineStore>) -> Self {
+        self.kline_store = Some(store);
+        self
+    }
+
+    // 行情优先取自 WebSocket 缓冲，流未就绪或过期时回退到 REST
+    pub fn with_market_stream(mut self, stream: Arc<MarketStream>) -> Self {
+        self.market_stream = Some(stream);
+        self
+    }
+
+    // 盘口优先取自增量流维护的本地订单簿
+    pub fn with_depth_stream(mut self, stream: Arc<DepthStream>) -> Self {
+        self.depth_stream = Some(stream);
+        self
+    }
+
+    pub fn with_position_mode(mut self, mode: PositionMode) -> Self {
+        self.position_mode = mode;
+        self
+    }
+
+    fn stream(&self) -> Option<&MarketStream> {
+        self.market_stream.as_deref()
+    }
+}
+
+#[async_trait]
+impl Exchange for BinanceExchange {
+    fn name(&self) -> &str {
+        "binance-usdm"
+    }
+
+    async fn fetch_klines(&self, symbol: &str, interval: &str, limit: u32) -> Result<Vec<Kline>> {
+        if let Some(klines) = self
+            .stream()
+            .and_then(|s| s.klines(symbol, interval, limit))
+        {
+            return Ok(klines);
+        }
+        match &self.kline_store {
+            Some(store) => store.recent(symbol, interval, limit).await,
+            None => market::fetch_klines(symbol, interval, limit).await,
+        }
+    }
+
+    async fn fetch_current_price(&self, symbol: &str) -> Result<f64> {
+        match self.stream().and_then(|s| s.last_price(symbol)) {
+            Some(price) => Ok(price),
+            None => market::fetch_current_price(symbol).await,
+        }
+    }
+
+    async fn fetch_mark_price(&self, symbol: &str) -> Result<f64> {
+        match self.stream().and_then(|s| s.mark_price(symbol)) {
+            Some(price) => Ok(price),
+            None => market::fetch_mark_price(symbol).await,
+        }
+    }
+
+    async fn fetch_book_ticker(&self, symbol: &str) -> Result<BookTicker> {
+        match self.stream().and_then(|s| s.book_ticker(symbol)) {
+            Some(book) => Ok(book),
+            None => market::fetch_book_ticker(symbol).await,
+        }
+    }
+
+    async fn fetch_depth(&self, symbol: &str) -> Result<OrderBook> {
+        let levels = depth::SNAPSHOT_LEVELS;
+        match self
+            .depth_stream
+            .as_ref()
+            .and_then(|s| s.book(symbol, levels as usize))
+        {
+            Some(book) => Ok(book),
+            None => depth::fetch_depth(symbol, levels).await,
+        }
+    }
+
+    async fn fetch_derivatives(
+        &self,
+        symbol: &str,
+        interval: &str,
+    ) -> Result<Option<DerivativesSnapshot>> {
+        derivatives::fetch_snapshot(symbol, interval)
+            .await
+            .map(Some)
+    }
+
+    async fn fetch_symbol_constraints(
+        &self,
+        symbols: &[String],
+    ) -> Result<HashMap<String, SymbolConstraints>> {
+        let constraints = executor::fetch_symbol_constraints(symbols).await?;
+        self.constraints
+            .write()
+            .unwrap()
+            .extend(constraints.clone());
+        Ok(constraints)
+    }
+
+    async fn get_account_info(&self) -> Result<AccountInfo> {
+        executor::get_account_info(&self.api_key, &self.secret).await
+    }
+
+    async fn get_positions(&self, symbol: &str) -> Result<PositionBook> {
+        executor::get_positions(symbol, &self.api_key, &self.secret).await
+    }
+
+    async fn get_portfolio_positions(
+        &self,
+        symbols: &[String],
+    ) -> Result<HashMap<String, PositionBook>> {
+        executor::get_all_positions(symbols, &self.api_key, &self.secret).await
+    }
+
+    async fn income(&self, start_ms: i64, end_ms: i64) -> Result<Vec<Income>> {
+        executor::fetch_income(start_ms, end_ms, &self.api_key, &self.secret).await
+    }
+
+    async fn account_trades(
+        &self,
+        symbol: &str,
+        start_ms: i64,
+        end_ms: i64,
+    ) -> Result<Vec<AccountTrade>> {
+        executor::fetch_account_trades(symbol, start_ms, end_ms, &self.api_key, &self.secret).await
+    }
+
+    fn position_mode(&self) -> PositionMode {
+        self.position_mode
+    }
+
+    async fn apply_position_mode(&self) -> Result<()> {
+        executor::set_position_mode(self.position_mode, &self.api_key, &self.secret).await
+    }
+
+    async fn set_leverage(&self, symbol: &str, leverage: u32) -> Result<()> {
+        executor::set_leverage(symbol, leverage, &self.api_key, &self.secret).await
+    }
+
+    async fn leverage_brackets(&self, symbol: &str) -> Result<Vec<LeverageBracket>> {
+        executor::fetch_leverage_brackets(symbol, &self.api_key, &self.secret).await
+    }
+
+    async fn place_order(&self, order: &OrderRequest) -> Result<OrderState> {
+        let constraints = self.constraints.read().unwrap().get(&order.symbol).cloned();
+        executor::place_order(
+            order,
+            constraints.as_ref(),
+            self.position_mode,
+            &self.api_key,
+            &self.secret,
+        )
+        .await
+    }
+
+    async fn get_order(&self, symbol: &str, order_id: i64) -> Result<OrderState> {
+        executor::get_order(symbol, order_id, &self.api_key, &self.secret).await
+    }
+
+    async fn find_order(&self, symbol: &str, client_order_id: &str) -> Result<Option<OrderState>> {
+        executor::find_order(symbol, client_order_id, &self.api_key, &self.secret).await
+    }
+
+    async fn order_fills(&self, symbol: &str, order_id: i64) -> Result<Vec<Fill>> {
+        executor::fetch_order_fills(symbol, order_id, &self.api_key, &self.secret).await
+    }
+
+    async fn open_orders(&self, symbol: &str) -> Result<Vec<OpenOrder>> {
+        executor::fetch_open_orders(symbol, &self.api_key, &self.secret).await
+    }
+
+    async fn cancel_order(&self, symbol: &str, order_id: i64) -> Result<()> {
+        executor::cancel_order(symbol, order_id, &self.api_key, &self.secret).await
+    }
+}
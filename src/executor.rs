@@ -1,4 +1,13 @@
-use crate::types::{Position, PositionSide, Signal, TradeAction, TradeResult, TradingDecision};
+use crate::exchange::{
+    AccountTrade, Exchange, Fill, Income, IncomeKind, OpenOrder, OrderRequest, OrderSide,
+    OrderState, OrderStatus, OrderType, PositionMode, TimeInForce,
+};
+use crate::margin::LeverageBracket;
+use crate::order_tracker::{self, ClientOrderIds, TrackedOrder};
+use crate::types::{
+    Position, PositionBook, PositionSide, Signal, StrategyAction, StrategyAdvice, TradeAction,
+    TradeResult, TradingDecision,
+};
 use anyhow::{anyhow, Context, Result};
 use futures::{SinkExt, StreamExt};
 use hmac::{Hmac, Mac};
@@ -9,20 +18,21 @@ use std::env;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::{watch, OnceCell};
-use tokio::time::{sleep, timeout, Duration, MissedTickBehavior};
+use tokio::time::{sleep, timeout, Duration, Instant, MissedTickBehavior};
 use tokio_tungstenite::connect_async;
 use tokio_tungstenite::tungstenite::protocol::Message;
-use log::{debug, warn};
+use log::{debug, error, info, warn};
 
 type HmacSha256 = Hmac<Sha256>;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct SymbolConstraints {
     pub step_size: f64,
     pub min_qty: f64,
     pub max_qty: Option<f64>,
     pub min_notional: f64,
     pub tick_size: f64,
+    pub leverage_brackets: Vec<LeverageBracket>, // 杠杆分层，未获取时为空
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -65,9 +75,23 @@ impl AccountStreamHandle {
 #[derive(Debug, Deserialize)]
 struct BinanceOrderResponse {
     #[serde(rename = "orderId")]
-    order_id: Option<i64>,
-    symbol: Option<String>,
-    status: Option<String>,
+    order_id: i64,
+    status: String,
+    #[serde(rename = "executedQty", default)]
+    executed_qty: String,
+    #[serde(rename = "avgPrice", default)]
+    avg_price: String,
+}
+
+impl BinanceOrderResponse {
+    fn into_state(self) -> OrderState {
+        OrderState {
+            order_id: self.order_id,
+            status: OrderStatus::parse(&self.status).unwrap_or(OrderStatus::New),
+            executed_qty: parse_float(&self.executed_qty),
+            avg_price: parse_float(&self.avg_price),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -196,6 +220,7 @@ pub async fn fetch_symbol_constraints(
             max_qty,
             min_notional: min_notional.unwrap_or(0.0),
             tick_size: tick_size.unwrap_or(0.0),
+            leverage_brackets: Vec::new(), // 需签名查询，启动时由 leverage_brackets 填充
         };
 
         map.insert(symbol.clone(), constraints);
@@ -228,6 +253,18 @@ pub fn quantize_price(price: f64, tick_size: f64) -> f64 {
     (steps * tick_size).max(0.0)
 }
 
+// 按步长的小数位数格式化下单参数，避免浮点误差（如 0.30000000000000004）触发 -1111 精度错误
+pub fn format_with_step(value: f64, step: f64) -> String {
+    if step <= 0.0 {
+        return value.to_string();
+    }
+    let decimals = step
+        .to_string()
+        .split_once('.')
+        .map_or(0, |(_, fraction)| fraction.len());
+    format!("{:.*}", decimals, value)
+}
+
 // Task 5.1: 查询当前持仓
 #[derive(Debug, Deserialize)]
 #[allow(non_snake_case)]
@@ -237,12 +274,32 @@ struct PositionRisk {
     positionAmt: String,
     entryPrice: String,
     unRealizedProfit: String,
+    #[serde(default)]
+    markPrice: String,
+    #[serde(default)]
+    liquidationPrice: String,
+    #[serde(default)]
+    leverage: String,
+    #[serde(default)]
+    marginType: String,
+    #[serde(default)]
+    isolatedMargin: String,
+    #[serde(default)]
+    notional: String,
 }
 
-pub async fn get_position(symbol: &str, api_key: &str, secret: &str) -> Result<Option<Position>> {
+// 查询持仓风险，symbol 为空时返回全部标的
+async fn fetch_position_risk(
+    symbol: Option<&str>,
+    api_key: &str,
+    secret: &str,
+) -> Result<Vec<PositionRisk>> {
     let base_url = get_binance_base_url();
     let timestamp = get_timestamp();
-    let query_string = format!("timestamp={}", timestamp);
+    let query_string = match symbol {
+        Some(symbol) => format!("symbol={}&timestamp={}", symbol, timestamp),
+        None => format!("timestamp={}", timestamp),
+    };
     let signature = generate_signature(&query_string, secret);
 
     let url = format!(
@@ -251,7 +308,7 @@ pub async fn get_position(symbol: &str, api_key: &str, secret: &str) -> Result<O
     );
 
     let client = reqwest::Client::new();
-    let positions: Vec<PositionRisk> = client
+    client
         .get(&url)
         .header("X-MBX-APIKEY", api_key)
         .send()
@@ -259,18 +316,19 @@ pub async fn get_position(symbol: &str, api_key: &str, secret: &str) -> Result<O
         .context("查询持仓失败")?
         .json()
         .await
-        .context("解析持仓数据失败")?;
-
-    let mut best_position: Option<Position> = None;
+        .context("解析持仓数据失败")
+}
 
-    for pos in positions {
+// 从持仓风险中取出标的的多空两腿
+fn position_book(positions: &[PositionRisk], symbol: &str) -> PositionBook {
+    let legs = positions.iter().filter_map(|pos| {
         if pos.symbol != symbol {
-            continue;
+            return None;
         }
 
         let amount: f64 = pos.positionAmt.parse().unwrap_or(0.0);
         if amount.abs() < 0.0001 {
-            continue;
+            return None;
         }
 
         let side = match pos.positionSide.as_str() {
@@ -285,24 +343,46 @@ pub async fn get_position(symbol: &str, api_key: &str, secret: &str) -> Result<O
             }
         };
 
-        let candidate = Position {
+        let leverage: u32 = pos.leverage.parse().unwrap_or(1).max(1);
+        // 逐仓取逐仓保证金，全仓按名义价值 / 杠杆估算
+        let margin = if pos.marginType == "isolated" {
+            parse_float(&pos.isolatedMargin)
+        } else {
+            parse_float(&pos.notional).abs() / leverage as f64
+        };
+
+        Some(Position {
             side,
             amount: amount.abs(),
             entry_price: pos.entryPrice.parse().unwrap_or(0.0),
             unrealized_pnl: pos.unRealizedProfit.parse().unwrap_or(0.0),
-        };
+            mark_price: parse_float(&pos.markPrice),
+            liquidation_price: parse_float(&pos.liquidationPrice),
+            margin,
+            leverage,
+        })
+    });
 
-        let replace = match &best_position {
-            None => true,
-            Some(existing) => candidate.amount > existing.amount,
-        };
+    PositionBook::from_legs(legs)
+}
 
-        if replace {
-            best_position = Some(candidate);
-        }
-    }
+// 查询标的的多空两腿持仓
+pub async fn get_positions(symbol: &str, api_key: &str, secret: &str) -> Result<PositionBook> {
+    let positions = fetch_position_risk(Some(symbol), api_key, secret).await?;
+    Ok(position_book(&positions, symbol))
+}
 
-    Ok(best_position)
+// 一次请求查询多个标的的持仓
+pub async fn get_all_positions(
+    symbols: &[String],
+    api_key: &str,
+    secret: &str,
+) -> Result<std::collections::HashMap<String, PositionBook>> {
+    let positions = fetch_position_risk(None, api_key, secret).await?;
+    Ok(symbols
+        .iter()
+        .map(|symbol| (symbol.clone(), position_book(&positions, symbol)))
+        .collect())
 }
 
 // 通过 REST 接口获取账户信息（备用路径）
@@ -558,11 +638,15 @@ fn get_binance_ws_base_url() -> String {
     }
 }
 
-// 设置持仓模式为双向 (Hedge Mode)
-pub async fn set_dual_position_mode(api_key: &str, secret: &str) -> Result<()> {
+// 设置持仓模式：双向 (Hedge Mode) 或单向 (One-way Mode)
+pub async fn set_position_mode(mode: PositionMode, api_key: &str, secret: &str) -> Result<()> {
     let base_url = get_binance_base_url();
     let timestamp = get_timestamp();
-    let query_string = format!("dualSidePosition=true&timestamp={}", timestamp);
+    let query_string = format!(
+        "dualSidePosition={}&timestamp={}",
+        mode == PositionMode::Hedge,
+        timestamp
+    );
     let signature = generate_signature(&query_string, secret);
 
     let url = format!(
@@ -584,9 +668,9 @@ pub async fn set_dual_position_mode(api_key: &str, secret: &str) -> Result<()> {
             .text()
             .await
             .unwrap_or_else(|_| "未知错误".to_string());
-        // 如果已经是双向模式,会返回错误但可以忽略
+        // 如果已经是目标模式,会返回错误但可以忽略
         if error_text.contains("-4059") {
-            return Ok(()); // 已经是双向模式
+            return Ok(()); // 已经是目标模式
         }
         return Err(anyhow::anyhow!(
             "设置持仓模式失败 [{}]: {}",
@@ -633,21 +717,124 @@ pub async fn set_leverage(symbol: &str, leverage: u32, api_key: &str, secret: &s
     Ok(())
 }
 
-// Task 5.2: 订单执行函数
-async fn place_order(
+#[derive(Debug, Deserialize)]
+struct SymbolBrackets {
+    symbol: String,
+    brackets: Vec<LeverageBracket>,
+}
+
+// 指定 symbol 时接口可能返回单个对象或数组
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum LeverageBracketResponse {
+    One(SymbolBrackets),
+    Many(Vec<SymbolBrackets>),
+}
+
+// 查询标的的杠杆分层（维持保证金率与速算数）
+pub async fn fetch_leverage_brackets(
     symbol: &str,
-    side: &str,          // BUY or SELL
-    position_side: &str, // LONG or SHORT
-    quantity: f64,
     api_key: &str,
     secret: &str,
-) -> Result<String> {
+) -> Result<Vec<LeverageBracket>> {
     let base_url = get_binance_base_url();
     let timestamp = get_timestamp();
-    let query_string = format!(
-        "symbol={}&side={}&positionSide={}&type=MARKET&quantity={}&timestamp={}",
-        symbol, side, position_side, quantity, timestamp
+    let query_string = format!("symbol={}&timestamp={}", symbol, timestamp);
+    let signature = generate_signature(&query_string, secret);
+
+    let url = format!(
+        "{}/fapi/v1/leverageBracket?{}&signature={}",
+        base_url, query_string, signature
+    );
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(&url)
+        .header("X-MBX-APIKEY", api_key)
+        .send()
+        .await
+        .context("查询杠杆分层失败")?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "未知错误".to_string());
+        return Err(anyhow!("查询杠杆分层失败 [{}]: {}", status, error_text));
+    }
+
+    let entries = match response
+        .json::<LeverageBracketResponse>()
+        .await
+        .context("解析杠杆分层失败")?
+    {
+        LeverageBracketResponse::One(entry) => vec![entry],
+        LeverageBracketResponse::Many(entries) => entries,
+    };
+    let mut brackets = entries
+        .into_iter()
+        .find(|entry| entry.symbol == symbol)
+        .map(|entry| entry.brackets)
+        .with_context(|| format!("杠杆分层中缺少标的: {}", symbol))?;
+    brackets.sort_by(|a, b| a.notional_floor.total_cmp(&b.notional_floor));
+    Ok(brackets)
+}
+
+// Task 5.2: 订单执行函数
+pub async fn place_order(
+    order: &OrderRequest,
+    constraints: Option<&SymbolConstraints>,
+    mode: PositionMode,
+    api_key: &str,
+    secret: &str,
+) -> Result<OrderState> {
+    let base_url = get_binance_base_url();
+    let timestamp = get_timestamp();
+    let (step_size, tick_size) = constraints.map_or((0.0, 0.0), |c| (c.step_size, c.tick_size));
+    let position_side = match (mode, &order.position_side) {
+        (PositionMode::OneWay, _) => "BOTH",
+        (PositionMode::Hedge, PositionSide::Long) => "LONG",
+        (PositionMode::Hedge, PositionSide::Short) => "SHORT",
+    };
+    let mut query_string = format!(
+        "symbol={}&side={}&positionSide={}&type={}",
+        order.symbol,
+        order.side.as_str(),
+        position_side,
+        order.order_type.as_str()
     );
+    if order.close_position {
+        query_string.push_str("&closePosition=true");
+    } else {
+        query_string.push_str(&format!(
+            "&quantity={}",
+            format_with_step(order.quantity, step_size)
+        ));
+        // 单向持仓下平仓单不得反向开仓；双向持仓不接受 reduceOnly
+        if mode == PositionMode::OneWay && order.reduces_position() {
+            query_string.push_str("&reduceOnly=true");
+        }
+    }
+    if let Some(price) = order.price {
+        let time_in_force = order.time_in_force.unwrap_or(TimeInForce::Gtc);
+        query_string.push_str(&format!(
+            "&price={}&timeInForce={}",
+            format_with_step(price, tick_size),
+            time_in_force.as_str()
+        ));
+    }
+    if let Some(stop_price) = order.stop_price {
+        // 以标记价格触发，避免插针误触发
+        query_string.push_str(&format!(
+            "&stopPrice={}&workingType=MARK_PRICE",
+            format_with_step(stop_price, tick_size)
+        ));
+    }
+    if let Some(client_order_id) = &order.client_order_id {
+        query_string.push_str(&format!("&newClientOrderId={}", client_order_id));
+    }
+    query_string.push_str(&format!("&timestamp={}", timestamp));
     let signature = generate_signature(&query_string, secret);
 
     let url = format!(
@@ -667,209 +854,1347 @@ async fn place_order(
     let response_text = response.text().await.context("读取响应失败")?;
 
     if !status.is_success() {
-        // 尝试解析错误信息
-        if let Ok(error) = serde_json::from_str::<BinanceError>(&response_text) {
-            return Err(anyhow::anyhow!(
-                "订单失败 [code:{}]: {}",
-                error.code.unwrap_or(-1),
-                error.msg.unwrap_or_else(|| "未知错误".to_string())
-            ));
-        } else {
-            return Err(anyhow::anyhow!("订单失败 [{}]: {}", status, response_text));
+        return Err(binance_error("订单失败", status, &response_text));
+    }
+
+    let order: BinanceOrderResponse =
+        serde_json::from_str(&response_text).context("解析订单响应失败")?;
+    Ok(order.into_state())
+}
+
+// 查询订单状态
+pub async fn get_order(
+    symbol: &str,
+    order_id: i64,
+    api_key: &str,
+    secret: &str,
+) -> Result<OrderState> {
+    query_order(symbol, &format!("orderId={}", order_id), api_key, secret)
+        .await?
+        .ok_or_else(|| anyhow!("查询订单失败 [code:-2013]: Order does not exist."))
+}
+
+// 按 newClientOrderId 查询订单，不存在时返回 None
+pub async fn find_order(
+    symbol: &str,
+    client_order_id: &str,
+    api_key: &str,
+    secret: &str,
+) -> Result<Option<OrderState>> {
+    query_order(
+        symbol,
+        &format!("origClientOrderId={}", client_order_id),
+        api_key,
+        secret,
+    )
+    .await
+}
+
+async fn query_order(
+    symbol: &str,
+    id_param: &str,
+    api_key: &str,
+    secret: &str,
+) -> Result<Option<OrderState>> {
+    let base_url = get_binance_base_url();
+    let timestamp = get_timestamp();
+    let query_string = format!("symbol={}&{}&timestamp={}", symbol, id_param, timestamp);
+    let signature = generate_signature(&query_string, secret);
+
+    let url = format!(
+        "{}/fapi/v1/order?{}&signature={}",
+        base_url, query_string, signature
+    );
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(&url)
+        .header("X-MBX-APIKEY", api_key)
+        .send()
+        .await
+        .context("查询订单失败")?;
+
+    let status = response.status();
+    let response_text = response.text().await.context("读取响应失败")?;
+    if !status.is_success() {
+        // -2013: 订单不存在
+        if let Ok(BinanceError {
+            code: Some(-2013), ..
+        }) = serde_json::from_str::<BinanceError>(&response_text)
+        {
+            return Ok(None);
+        }
+        return Err(binance_error("查询订单失败", status, &response_text));
+    }
+
+    let order: BinanceOrderResponse =
+        serde_json::from_str(&response_text).context("解析订单数据失败")?;
+    Ok(Some(order.into_state()))
+}
+
+#[derive(Debug, Deserialize)]
+struct BinanceUserTrade {
+    id: i64,
+    #[serde(rename = "orderId", default)]
+    order_id: i64,
+    #[serde(default)]
+    side: String,
+    #[serde(rename = "positionSide", default)]
+    position_side: String,
+    price: String,
+    qty: String,
+    commission: String,
+    #[serde(rename = "commissionAsset")]
+    commission_asset: String,
+    #[serde(rename = "realizedPnl")]
+    realized_pnl: String,
+    maker: bool,
+    time: i64,
+}
+
+impl BinanceUserTrade {
+    fn fill(&self) -> Fill {
+        Fill {
+            trade_id: self.id,
+            price: parse_float(&self.price),
+            quantity: parse_float(&self.qty),
+            commission: parse_float(&self.commission),
+            commission_asset: self.commission_asset.clone(),
+            realized_pnl: parse_float(&self.realized_pnl),
+            maker: self.maker,
+            time: self.time,
+        }
+    }
+}
+
+async fn fetch_user_trades(
+    params: &str,
+    api_key: &str,
+    secret: &str,
+) -> Result<Vec<BinanceUserTrade>> {
+    let base_url = get_binance_base_url();
+    let timestamp = get_timestamp();
+    let query_string = format!("{}&timestamp={}", params, timestamp);
+    let signature = generate_signature(&query_string, secret);
+
+    let url = format!(
+        "{}/fapi/v1/userTrades?{}&signature={}",
+        base_url, query_string, signature
+    );
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(&url)
+        .header("X-MBX-APIKEY", api_key)
+        .send()
+        .await
+        .context("查询成交明细失败")?;
+
+    let status = response.status();
+    let response_text = response.text().await.context("读取响应失败")?;
+    if !status.is_success() {
+        return Err(binance_error("查询成交明细失败", status, &response_text));
+    }
+
+    serde_json::from_str(&response_text).context("解析成交明细失败")
+}
+
+// 查询订单的成交明细（含手续费与已实现盈亏）
+pub async fn fetch_order_fills(
+    symbol: &str,
+    order_id: i64,
+    api_key: &str,
+    secret: &str,
+) -> Result<Vec<Fill>> {
+    let params = format!("symbol={}&orderId={}", symbol, order_id);
+    let trades = fetch_user_trades(&params, api_key, secret).await?;
+    Ok(trades.iter().map(BinanceUserTrade::fill).collect())
+}
+
+const USER_TRADES_LIMIT: usize = 1000;
+const USER_TRADES_MAX_WINDOW_MS: i64 = 7 * 24 * 3600 * 1000; // 接口单次查询的最大时间跨度
+
+// 查询标的在时间区间内的成交，区间超过 7 天时只取最近 7 天
+pub async fn fetch_account_trades(
+    symbol: &str,
+    start_ms: i64,
+    end_ms: i64,
+    api_key: &str,
+    secret: &str,
+) -> Result<Vec<AccountTrade>> {
+    let start_ms = start_ms.max(end_ms - USER_TRADES_MAX_WINDOW_MS + 1);
+    let params = format!(
+        "symbol={}&startTime={}&endTime={}&limit={}",
+        symbol, start_ms, end_ms, USER_TRADES_LIMIT
+    );
+    let trades = fetch_user_trades(&params, api_key, secret).await?;
+    if trades.len() >= USER_TRADES_LIMIT {
+        warn!(
+            "{} 成交记录达到单页上限 {}，部分成交未取回",
+            symbol, USER_TRADES_LIMIT
+        );
+    }
+    Ok(trades
+        .iter()
+        .map(|t| {
+            let side = if t.side == "BUY" {
+                OrderSide::Buy
+            } else {
+                OrderSide::Sell
+            };
+            let fill = t.fill();
+            // 单向持仓的 BOTH：有已实现盈亏的成交为平仓，按成交方向推断被平的一侧
+            let position_side = match (t.position_side.as_str(), side) {
+                ("LONG", _) => PositionSide::Long,
+                ("SHORT", _) => PositionSide::Short,
+                (_, OrderSide::Buy) if fill.realized_pnl != 0.0 => PositionSide::Short,
+                (_, OrderSide::Sell) if fill.realized_pnl != 0.0 => PositionSide::Long,
+                (_, OrderSide::Buy) => PositionSide::Long,
+                (_, OrderSide::Sell) => PositionSide::Short,
+            };
+            AccountTrade {
+                symbol: symbol.to_string(),
+                order_id: t.order_id,
+                side,
+                position_side,
+                fill,
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct BinanceIncome {
+    symbol: String,
+    incomeType: String,
+    income: String,
+    asset: String,
+    time: i64,
+    tranId: i64,
+}
+
+const INCOME_PAGE_LIMIT: usize = 1000;
+
+// 查询账户资金流水，仅保留已实现盈亏、手续费与资金费；单页上限 1000 条，按时间向后翻页
+pub async fn fetch_income(
+    start_ms: i64,
+    end_ms: i64,
+    api_key: &str,
+    secret: &str,
+) -> Result<Vec<Income>> {
+    let base_url = get_binance_base_url();
+    let client = reqwest::Client::new();
+    let mut incomes = Vec::new();
+    let mut start = start_ms;
+
+    loop {
+        let query_string = format!(
+            "startTime={}&endTime={}&limit={}&timestamp={}",
+            start,
+            end_ms,
+            INCOME_PAGE_LIMIT,
+            get_timestamp()
+        );
+        let signature = generate_signature(&query_string, secret);
+        let url = format!(
+            "{}/fapi/v1/income?{}&signature={}",
+            base_url, query_string, signature
+        );
+
+        let response = client
+            .get(&url)
+            .header("X-MBX-APIKEY", api_key)
+            .send()
+            .await
+            .context("查询资金流水失败")?;
+
+        let status = response.status();
+        let response_text = response.text().await.context("读取响应失败")?;
+        if !status.is_success() {
+            return Err(binance_error("查询资金流水失败", status, &response_text));
+        }
+
+        let page: Vec<BinanceIncome> =
+            serde_json::from_str(&response_text).context("解析资金流水失败")?;
+        let full_page = page.len() >= INCOME_PAGE_LIMIT;
+        let last_time = page.last().map(|i| i.time);
+        incomes.extend(page.into_iter().filter_map(|i| {
+            Some(Income {
+                kind: IncomeKind::parse(&i.incomeType)?,
+                symbol: i.symbol,
+                amount: parse_float(&i.income),
+                asset: i.asset,
+                time: i.time,
+                tran_id: i.tranId,
+            })
+        }));
+
+        // 翻页起点包含上一页最后时刻，重复的流水由调用方按 tranId 去重
+        match last_time {
+            Some(time) if full_page => start = if time > start { time } else { time + 1 },
+            _ => break,
         }
     }
 
-    // 解析成功响应
-    if let Ok(order) = serde_json::from_str::<BinanceOrderResponse>(&response_text) {
-        Ok(format!(
-            "订单ID:{}, 状态:{}",
-            order.order_id.unwrap_or(0),
-            order.status.unwrap_or_else(|| "UNKNOWN".to_string())
-        ))
+    Ok(incomes)
+}
+
+// 尝试解析 Binance 错误码
+fn binance_error(action: &str, status: reqwest::StatusCode, response_text: &str) -> anyhow::Error {
+    if let Ok(error) = serde_json::from_str::<BinanceError>(response_text) {
+        anyhow!(
+            "{} [code:{}]: {}",
+            action,
+            error.code.unwrap_or(-1),
+            error.msg.unwrap_or_else(|| "未知错误".to_string())
+        )
     } else {
-        Ok("订单已提交".to_string())
+        anyhow!("{} [{}]: {}", action, status, response_text)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct BinanceOpenOrder {
+    orderId: i64,
+    side: String,
+    positionSide: String,
+    #[serde(rename = "type")]
+    order_type: String,
+    origQty: String,
+    price: String,
+    stopPrice: String,
+    #[serde(default)]
+    closePosition: bool,
+    #[serde(default)]
+    reduceOnly: bool,
+}
+
+// 查询标的当前挂单
+pub async fn fetch_open_orders(
+    symbol: &str,
+    api_key: &str,
+    secret: &str,
+) -> Result<Vec<OpenOrder>> {
+    let base_url = get_binance_base_url();
+    let timestamp = get_timestamp();
+    let query_string = format!("symbol={}&timestamp={}", symbol, timestamp);
+    let signature = generate_signature(&query_string, secret);
+
+    let url = format!(
+        "{}/fapi/v1/openOrders?{}&signature={}",
+        base_url, query_string, signature
+    );
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(&url)
+        .header("X-MBX-APIKEY", api_key)
+        .send()
+        .await
+        .context("查询挂单失败")?;
+
+    let status = response.status();
+    let response_text = response.text().await.context("读取响应失败")?;
+    if !status.is_success() {
+        return Err(binance_error("查询挂单失败", status, &response_text));
+    }
+
+    let orders: Vec<BinanceOpenOrder> =
+        serde_json::from_str(&response_text).context("解析挂单数据失败")?;
+
+    // 仅保留本系统可识别的订单类型
+    Ok(orders
+        .into_iter()
+        .filter_map(|o| {
+            let side = match o.side.as_str() {
+                "BUY" => OrderSide::Buy,
+                "SELL" => OrderSide::Sell,
+                _ => return None,
+            };
+            let reduces = o.reduceOnly || o.closePosition;
+            let position_side = match (o.positionSide.as_str(), side) {
+                ("LONG", _) => PositionSide::Long,
+                ("SHORT", _) => PositionSide::Short,
+                // 单向持仓：减仓单作用于反方向持仓，开仓单作用于同方向
+                ("BOTH", OrderSide::Buy) if reduces => PositionSide::Short,
+                ("BOTH", OrderSide::Sell) if reduces => PositionSide::Long,
+                ("BOTH", OrderSide::Buy) => PositionSide::Long,
+                ("BOTH", OrderSide::Sell) => PositionSide::Short,
+                _ => return None,
+            };
+            Some(OpenOrder {
+                order_id: o.orderId,
+                side,
+                position_side,
+                order_type: OrderType::parse(&o.order_type)?,
+                quantity: parse_float(&o.origQty),
+                price: parse_float(&o.price),
+                stop_price: parse_float(&o.stopPrice),
+                close_position: o.closePosition,
+            })
+        })
+        .collect())
+}
+
+// 撤销订单
+pub async fn cancel_order(symbol: &str, order_id: i64, api_key: &str, secret: &str) -> Result<()> {
+    let base_url = get_binance_base_url();
+    let timestamp = get_timestamp();
+    let query_string = format!(
+        "symbol={}&orderId={}&timestamp={}",
+        symbol, order_id, timestamp
+    );
+    let signature = generate_signature(&query_string, secret);
+
+    let url = format!(
+        "{}/fapi/v1/order?{}&signature={}",
+        base_url, query_string, signature
+    );
+
+    let client = reqwest::Client::new();
+    let response = client
+        .delete(&url)
+        .header("X-MBX-APIKEY", api_key)
+        .send()
+        .await
+        .context("撤单请求失败")?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let response_text = response.text().await.unwrap_or_default();
+        return Err(binance_error("撤单失败", status, &response_text));
+    }
+
+    Ok(())
+}
+
+// 根据持仓均价计算止损/止盈触发价，百分比按价格变动计
+pub fn protective_price(
+    position: &Position,
+    order_type: OrderType,
+    pct: f64,
+    tick_size: f64,
+) -> f64 {
+    let pct = pct.abs();
+    let factor = match (&position.side, order_type) {
+        (PositionSide::Long, OrderType::StopMarket) => 1.0 - pct,
+        (PositionSide::Long, _) => 1.0 + pct,
+        (PositionSide::Short, OrderType::StopMarket) => 1.0 + pct,
+        (PositionSide::Short, _) => 1.0 - pct,
+    };
+    quantize_price(position.entry_price * factor, tick_size)
+}
+
+// 同步交易所端止损止盈单（多空两腿分别处理）：
+// - 无持仓的一腿撤销全部保护单
+// - replace_with 指定的一腿（刚开仓/加仓/反手）或按数量挂出的保护单与持仓不一致时重挂，
+//   未给出新百分比的一侧沿用原触发价
+// - close_position=false 时以当前持仓数量挂单
+pub async fn sync_protective_orders(
+    exchange: &dyn Exchange,
+    symbol: &str,
+    positions: &PositionBook,
+    replace_with: Option<(PositionSide, &StrategyAdvice)>,
+    tick_size: f64,
+    close_position: bool,
+    ids: &ClientOrderIds,
+) -> Result<()> {
+    let protective: Vec<OpenOrder> = exchange
+        .open_orders(symbol)
+        .await?
+        .into_iter()
+        .filter(|o| o.order_type.is_protective())
+        .collect();
+
+    for side in [PositionSide::Long, PositionSide::Short] {
+        let orders: Vec<&OpenOrder> = protective
+            .iter()
+            .filter(|o| o.position_side == side)
+            .collect();
+        let strategy = replace_with
+            .as_ref()
+            .filter(|(replace_side, _)| *replace_side == side)
+            .map(|(_, strategy)| *strategy);
+        let leg = positions.leg(&side);
+        let refresh = match leg {
+            Some(pos) => {
+                strategy.is_some()
+                    || orders.iter().any(|o| {
+                        !o.close_position && (o.quantity - pos.amount).abs() > f64::EPSILON
+                    })
+            }
+            None => false,
+        };
+
+        for order in &orders {
+            if leg.is_some() && !refresh {
+                continue;
+            }
+            match exchange.cancel_order(symbol, order.order_id).await {
+                Ok(()) => info!(
+                    "撤销保护单: {} {:?} {} {} @ {:.6}",
+                    symbol,
+                    order.position_side,
+                    order.side.as_str(),
+                    order.order_type.as_str(),
+                    order.stop_price
+                ),
+                Err(e) => warn!("撤销保护单 {} 失败: {:#}", order.order_id, e),
+            }
+        }
+
+        let Some(pos) = leg.filter(|_| refresh) else {
+            continue;
+        };
+        place_protective_orders(
+            exchange,
+            symbol,
+            pos,
+            strategy,
+            &orders,
+            tick_size,
+            close_position,
+            ids,
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn place_protective_orders(
+    exchange: &dyn Exchange,
+    symbol: &str,
+    pos: &Position,
+    strategy: Option<&StrategyAdvice>,
+    previous: &[&OpenOrder],
+    tick_size: f64,
+    close_position: bool,
+    ids: &ClientOrderIds,
+) {
+    for order_type in [OrderType::StopMarket, OrderType::TakeProfitMarket] {
+        let pct = strategy.and_then(|strategy| match order_type {
+            OrderType::StopMarket => strategy.stop_loss_pct,
+            _ => strategy.take_profit_pct,
+        });
+        let stop_price = match pct {
+            Some(pct) if pct != 0.0 => protective_price(pos, order_type, pct, tick_size),
+            _ => match previous.iter().find(|o| o.order_type == order_type) {
+                Some(previous) => previous.stop_price,
+                None => continue,
+            },
+        };
+
+        let quantity = if close_position {
+            None
+        } else {
+            Some(pos.amount)
+        };
+        let tag = match (order_type, &pos.side) {
+            (OrderType::StopMarket, PositionSide::Long) => "SLL",
+            (OrderType::StopMarket, PositionSide::Short) => "SLS",
+            (_, PositionSide::Long) => "TPL",
+            (_, PositionSide::Short) => "TPS",
+        };
+        let order =
+            OrderRequest::protective(symbol, order_type, pos.side.clone(), stop_price, quantity)
+                .with_client_order_id(ids.id(tag));
+        match order_tracker::submit(exchange, &order).await {
+            Ok(info) => info!(
+                "挂出保护单: {} {:?} {} @ {:.6} | {}",
+                symbol,
+                pos.side,
+                order_type.as_str(),
+                stop_price,
+                info
+            ),
+            Err(e) => warn!(
+                "挂出保护单失败: {} {} @ {:.6}: {:#}",
+                symbol,
+                order_type.as_str(),
+                stop_price,
+                e
+            ),
+        }
+    }
+}
+
+// 开仓执行方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionStyle {
+    Market,
+    PostOnly, // 买一/卖一挂 GTX 单，超时未成交部分改市价
+    Ioc,      // 限价 IOC，价格为对手价加容忍滑点，未成交部分放弃
+    Fok,      // 同 IOC，但必须全部成交
+}
+
+#[derive(Debug, Clone)]
+pub struct ExecutionPolicy {
+    pub style: ExecutionStyle,
+    pub maker_wait_secs: u64,    // post-only 等待成交时间
+    pub limit_slippage_bps: f64, // IOC/FOK 限价相对对手价的容忍度
+}
+
+impl ExecutionPolicy {
+    // 读取 EXECUTION_POLICY / MAKER_WAIT_SECS / LIMIT_SLIPPAGE_BPS，
+    // 可用 <KEY>_<SYMBOL> 按标的覆盖
+    pub fn from_env(symbol: &str) -> Result<Self> {
+        let read = |key: &str| {
+            env::var(format!("{}_{}", key, symbol))
+                .or_else(|_| env::var(key))
+                .ok()
+        };
+
+        let style = match read("EXECUTION_POLICY")
+            .unwrap_or_else(|| "market".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "market" => ExecutionStyle::Market,
+            "post_only" => ExecutionStyle::PostOnly,
+            "ioc" => ExecutionStyle::Ioc,
+            "fok" => ExecutionStyle::Fok,
+            other => return Err(anyhow!("{} 未知的 EXECUTION_POLICY: {}", symbol, other)),
+        };
+
+        Ok(ExecutionPolicy {
+            style,
+            maker_wait_secs: read("MAKER_WAIT_SECS")
+                .unwrap_or_else(|| "10".to_string())
+                .parse()
+                .context("MAKER_WAIT_SECS 格式错误")?,
+            limit_slippage_bps: read("LIMIT_SLIPPAGE_BPS")
+                .unwrap_or_else(|| "5".to_string())
+                .parse()
+                .context("LIMIT_SLIPPAGE_BPS 格式错误")?,
+        })
+    }
+}
+
+// 一次开平仓动作产生的全部订单
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionReport {
+    pub orders: Vec<TrackedOrder>,
+}
+
+impl ExecutionReport {
+    fn single(order: TrackedOrder) -> Self {
+        ExecutionReport {
+            orders: vec![order],
+        }
+    }
+
+    pub fn executed_qty(&self) -> f64 {
+        self.orders.iter().map(|o| o.executed_qty()).sum()
+    }
+
+    pub fn avg_price(&self) -> Option<f64> {
+        let qty = self.executed_qty();
+        if qty <= 0.0 {
+            return None;
+        }
+        let notional: f64 = self.orders.iter().map(|o| o.notional()).sum();
+        Some(notional / qty)
+    }
+
+    pub fn order_ids(&self) -> Vec<i64> {
+        self.orders.iter().map(|o| o.state.order_id).collect()
+    }
+
+    pub fn last_fill_time(&self) -> Option<i64> {
+        self.orders
+            .iter()
+            .flat_map(|o| o.fills.iter().map(|f| f.time))
+            .max()
+    }
+
+    // 任一订单缺少成交明细时为 None
+    pub fn commission(&self) -> Option<f64> {
+        self.orders
+            .iter()
+            .filter(|o| o.executed_qty() > 0.0)
+            .map(|o| o.commission())
+            .sum()
+    }
+
+    pub fn realized_pnl(&self) -> Option<f64> {
+        self.orders
+            .iter()
+            .filter(|o| o.executed_qty() > 0.0)
+            .map(|o| o.realized_pnl())
+            .sum()
     }
 }
 
-async fn open_long(symbol: &str, amount: f64, api_key: &str, secret: &str) -> Result<String> {
-    place_order(symbol, "BUY", "LONG", amount, api_key, secret).await
+impl std::fmt::Display for ExecutionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = self.orders.iter().map(|o| o.to_string()).collect();
+        write!(f, "{}", parts.join(" + "))
+    }
+}
+
+// 下市价单并跟踪至成交
+async fn market_order(
+    exchange: &dyn Exchange,
+    symbol: &str,
+    side: OrderSide,
+    position_side: PositionSide,
+    amount: f64,
+    client_order_id: String,
+) -> Result<TrackedOrder> {
+    let order = OrderRequest::market(symbol, side, position_side, amount)
+        .with_client_order_id(client_order_id);
+    let state = order_tracker::submit(exchange, &order).await?;
+    Ok(order_tracker::track(exchange, symbol, state).await)
+}
+
+// 开平仓动作在客户端订单号中的标记
+fn action_tag(opening: bool, position_side: &PositionSide) -> &'static str {
+    match (opening, position_side) {
+        (true, PositionSide::Long) => "OL",
+        (true, PositionSide::Short) => "OS",
+        (false, PositionSide::Long) => "CL",
+        (false, PositionSide::Short) => "CS",
+    }
+}
+
+// 撤销上一轮撤单失败而遗留的限价开仓单，避免与新订单叠加
+async fn cancel_stale_limit_orders(exchange: &dyn Exchange, symbol: &str) {
+    let orders = match exchange.open_orders(symbol).await {
+        Ok(orders) => orders,
+        Err(e) => {
+            warn!("查询 {} 挂单失败: {:#}", symbol, e);
+            return;
+        }
+    };
+    for order in orders.iter().filter(|o| o.order_type == OrderType::Limit) {
+        match exchange.cancel_order(symbol, order.order_id).await {
+            Ok(()) => info!(
+                "撤销遗留限价单: {} {:?} {} {} @ {:.6}",
+                symbol,
+                order.position_side,
+                order.side.as_str(),
+                order.quantity,
+                order.price
+            ),
+            Err(e) => warn!("撤销遗留限价单 {} 失败: {:#}", order.order_id, e),
+        }
+    }
+}
+
+// 按执行策略开仓；限价单与剩余部分的市价单分别以 1/2 结尾的订单号区分
+#[allow(clippy::too_many_arguments)]
+async fn execute_entry(
+    exchange: &dyn Exchange,
+    symbol: &str,
+    side: OrderSide,
+    position_side: PositionSide,
+    amount: f64,
+    policy: &ExecutionPolicy,
+    constraints: &SymbolConstraints,
+    ids: &ClientOrderIds,
+) -> Result<ExecutionReport> {
+    let tag = action_tag(true, &position_side);
+    let time_in_force = match policy.style {
+        ExecutionStyle::Market => {
+            let order =
+                market_order(exchange, symbol, side, position_side, amount, ids.id(tag)).await?;
+            return Ok(ExecutionReport::single(order));
+        }
+        ExecutionStyle::PostOnly => TimeInForce::Gtx,
+        ExecutionStyle::Ioc => TimeInForce::Ioc,
+        ExecutionStyle::Fok => TimeInForce::Fok,
+    };
+
+    cancel_stale_limit_orders(exchange, symbol).await;
+
+    let book = exchange.fetch_book_ticker(symbol).await?;
+    let raw_price = match (time_in_force, side) {
+        (TimeInForce::Gtx, OrderSide::Buy) => book.bid_price,
+        (TimeInForce::Gtx, OrderSide::Sell) => book.ask_price,
+        (_, OrderSide::Buy) => book.ask_price * (1.0 + policy.limit_slippage_bps / 10_000.0),
+        (_, OrderSide::Sell) => book.bid_price * (1.0 - policy.limit_slippage_bps / 10_000.0),
+    };
+    let price = quantize_price(raw_price, constraints.tick_size);
+
+    let request = OrderRequest::limit(
+        symbol,
+        side,
+        position_side.clone(),
+        amount,
+        price,
+        time_in_force,
+    )
+    .with_client_order_id(ids.id(&format!("{}1", tag)));
+    let mut report = ExecutionReport::default();
+    match order_tracker::submit(exchange, &request).await {
+        Ok(mut state) => {
+            info!(
+                "限价单 {} {} @ {:.6}: {}",
+                time_in_force.as_str(),
+                side.as_str(),
+                price,
+                state
+            );
+            if time_in_force == TimeInForce::Gtx {
+                let deadline = Instant::now() + Duration::from_secs(policy.maker_wait_secs);
+                while !state.status.is_final() && Instant::now() < deadline {
+                    sleep(Duration::from_secs(1)).await;
+                    match exchange.get_order(symbol, state.order_id).await {
+                        Ok(latest) => state = latest,
+                        // 查询失败时提前撤单，不让挂单继续留在盘口
+                        Err(e) => {
+                            warn!("查询挂单 {} 状态失败: {:#}", state.order_id, e);
+                            break;
+                        }
+                    }
+                }
+                if !state.status.is_final() {
+                    // 撤单与成交可能同时发生，以撤单后的查询结果为准
+                    if let Err(e) = exchange.cancel_order(symbol, state.order_id).await {
+                        warn!("撤销超时挂单失败: {:#}", e);
+                    }
+                }
+            }
+            report
+                .orders
+                .push(order_tracker::track(exchange, symbol, state).await);
+        }
+        // post-only 会与对手盘立即成交时被拒绝，直接改市价
+        Err(e) if time_in_force == TimeInForce::Gtx => {
+            warn!("Post-only 挂单被拒绝，改为市价: {:#}", e);
+        }
+        Err(e) => return Err(e),
+    }
+
+    // 挂单状态未确定时不补单，以免超出目标数量
+    let limit_settled = report
+        .orders
+        .last()
+        .is_none_or(|order| order.state.status.is_final());
+    if time_in_force == TimeInForce::Gtx && limit_settled {
+        let remaining = quantize_down(amount - report.executed_qty(), constraints.step_size);
+        if remaining >= constraints.min_qty
+            && remaining > 0.0
+            && remaining * price >= constraints.min_notional
+        {
+            let client_order_id = ids.id(&format!("{}2", tag));
+            // 补单失败时保留限价单已成交的部分
+            match market_order(
+                exchange,
+                symbol,
+                side,
+                position_side,
+                remaining,
+                client_order_id,
+            )
+            .await
+            {
+                Ok(order) => report.orders.push(order),
+                Err(e) => warn!("剩余 {:.6} 市价补单失败: {:#}", remaining, e),
+            }
+        } else if remaining > 0.0 {
+            info!(
+                "剩余 {:.6} 低于最小下单量或最小名义价值，不再补单",
+                remaining
+            );
+        }
+    } else if !limit_settled {
+        warn!("限价单状态未确定，跳过市价补单: {}", report);
+    }
+
+    if report.executed_qty() <= 0.0 {
+        return Err(anyhow!("限价单未成交: {}", report));
+    }
+    Ok(report)
+}
+
+// 按执行策略开仓或加仓
+async fn open_position(
+    exchange: &dyn Exchange,
+    symbol: &str,
+    side: &PositionSide,
+    amount: f64,
+    policy: &ExecutionPolicy,
+    constraints: &SymbolConstraints,
+    ids: &ClientOrderIds,
+) -> Result<ExecutionReport> {
+    let order_side = match side {
+        PositionSide::Long => OrderSide::Buy,
+        PositionSide::Short => OrderSide::Sell,
+    };
+    execute_entry(
+        exchange,
+        symbol,
+        order_side,
+        side.clone(),
+        amount,
+        policy,
+        constraints,
+        ids,
+    )
+    .await
+}
+
+// 平仓/减仓始终使用市价，确保离场
+async fn close_position(
+    exchange: &dyn Exchange,
+    symbol: &str,
+    side: &PositionSide,
+    quantity: f64,
+    ids: &ClientOrderIds,
+) -> Result<ExecutionReport> {
+    let order_side = match side {
+        PositionSide::Long => OrderSide::Sell,
+        PositionSide::Short => OrderSide::Buy,
+    };
+    let order = market_order(
+        exchange,
+        symbol,
+        order_side,
+        side.clone(),
+        quantity,
+        ids.id(action_tag(false, side)),
+    )
+    .await?;
+    Ok(ExecutionReport::single(order))
+}
+
+// 熔断平仓：市价平掉该标的全部持仓，保护单由调用方随后撤销
+pub async fn flatten_positions(
+    exchange: &dyn Exchange,
+    symbol: &str,
+    positions: &PositionBook,
+    ids: &ClientOrderIds,
+) -> Result<Vec<TradeResult>> {
+    let mut results = Vec::new();
+    for pos in positions.legs() {
+        let order_side = match pos.side {
+            PositionSide::Long => OrderSide::Sell,
+            PositionSide::Short => OrderSide::Buy,
+        };
+        let client_order_id = ids.id(&format!("K{}", action_tag(false, &pos.side)));
+        let order = market_order(
+            exchange,
+            symbol,
+            order_side,
+            pos.side.clone(),
+            pos.amount,
+            client_order_id,
+        )
+        .await?;
+        let report = ExecutionReport::single(order);
+        let pnl = close_pnl(pos, pos.amount, pos.mark_price, &report);
+        results.push(TradeResult {
+            symbol: symbol.to_string(),
+            action: close_action(&pos.side),
+            price: report.avg_price().unwrap_or(pos.mark_price),
+            amount: report.executed_qty(),
+            timestamp: report
+                .last_fill_time()
+                .unwrap_or_else(|| exchange.now_millis()),
+            reason: format!("熔断平{}仓 (盈亏: {:.2})", side_label(&pos.side), pnl),
+            pnl: Some(pnl),
+            commission: report.commission(),
+            order_details: Some(format!("熔断平{}:{}", side_label(&pos.side), report)),
+            order_ids: report.order_ids(),
+        });
+    }
+    Ok(results)
 }
 
-async fn close_long(symbol: &str, amount: f64, api_key: &str, secret: &str) -> Result<String> {
-    place_order(symbol, "SELL", "LONG", amount, api_key, secret).await
+fn side_label(side: &PositionSide) -> &'static str {
+    match side {
+        PositionSide::Long => "多",
+        PositionSide::Short => "空",
+    }
 }
 
-async fn open_short(symbol: &str, amount: f64, api_key: &str, secret: &str) -> Result<String> {
-    place_order(symbol, "SELL", "SHORT", amount, api_key, secret).await
+fn open_action(side: &PositionSide) -> TradeAction {
+    match side {
+        PositionSide::Long => TradeAction::OpenLong,
+        PositionSide::Short => TradeAction::OpenShort,
+    }
 }
 
-async fn close_short(symbol: &str, amount: f64, api_key: &str, secret: &str) -> Result<String> {
-    place_order(symbol, "BUY", "SHORT", amount, api_key, secret).await
+fn close_action(side: &PositionSide) -> TradeAction {
+    match side {
+        PositionSide::Long => TradeAction::CloseLong,
+        PositionSide::Short => TradeAction::CloseShort,
+    }
+}
+
+// 已实现盈亏优先取成交明细，缺失时按报价估算
+fn close_pnl(position: &Position, quantity: f64, price: f64, report: &ExecutionReport) -> f64 {
+    let estimate = match position.side {
+        PositionSide::Long => (price - position.entry_price) * quantity,
+        PositionSide::Short => (position.entry_price - price) * quantity,
+    };
+    report.realized_pnl().unwrap_or(estimate)
 }
 
 // Task 5.3: 执行交易决策
+// 交易信号给出方向，策略动作决定执行方式：
+// - hold：不下单
+// - reduce_position / close_position：按数量减仓或全部平掉与信号相反的一腿，不开新仓
+// - reverse：先平反向腿再开信号方向
+//   （单向持仓下为一笔净额订单；双向持仓下开仓失败则按原数量恢复已平的一腿）；
+//   同向腿已达上限时只平反向腿
+// - open_long / open_short / add_position：双向持仓下只在信号方向开仓/加仓，不触碰反向腿；
+//   单向持仓下持有反向仓位时跳过
+#[allow(clippy::too_many_arguments)]
 pub async fn execute_decision(
+    exchange: &dyn Exchange,
     symbol: &str,
     decision: &TradingDecision,
-    current_position: &Option<Position>,
+    action: &StrategyAction,
+    positions: &PositionBook,
     execution_price: f64,
     trade_amount: f64,
     max_position: f64,
-    api_key: &str,
-    secret: &str,
+    policy: &ExecutionPolicy,
+    constraints: &SymbolConstraints,
+    ids: &ClientOrderIds,
 ) -> Result<TradeResult> {
-    let timestamp = get_timestamp() as i64;
+    let timestamp = exchange.now_millis();
+    let hold = |reason: String| TradeResult {
+        symbol: symbol.to_string(),
+        action: TradeAction::Hold,
+        price: execution_price,
+        amount: 0.0,
+        timestamp,
+        reason,
+        pnl: None,
+        commission: None,
+        order_details: None,
+        order_ids: Vec::new(),
+    };
+
+    let (target, opposite) = match decision.signal {
+        Signal::Hold => return Ok(hold(decision.reason.clone())),
+        Signal::Buy => (PositionSide::Long, PositionSide::Short),
+        Signal::Sell => (PositionSide::Short, PositionSide::Long),
+    };
+    if *action == StrategyAction::Hold {
+        return Ok(hold(decision.reason.clone()));
+    }
 
-    match decision.signal {
-        Signal::Hold => {
+    // 减仓/平仓：只处理与信号相反的一腿
+    if action.reduces_only() {
+        let Some(pos) = positions.leg(&opposite) else {
+            return Ok(hold(format!(
+                "{} (无{}仓可平)",
+                decision.reason,
+                side_label(&opposite)
+            )));
+        };
+        let quantity = match action {
+            StrategyAction::ReducePosition => {
+                quantize_down(trade_amount.min(pos.amount), constraints.step_size)
+            }
+            _ => pos.amount,
+        };
+        if quantity <= 0.0 || quantity < constraints.min_qty {
+            return Ok(hold(format!(
+                "{} (减仓数量 {:.6} 低于最小下单量)",
+                decision.reason, quantity
+            )));
+        }
+
+        let report = close_position(exchange, symbol, &opposite, quantity, ids).await?;
+        let pnl = close_pnl(pos, quantity, execution_price, &report);
+        let remaining = (pos.amount - report.executed_qty()).max(0.0);
+        let reason = if remaining > 0.0 {
+            format!(
+                "{} (减{}仓: {:.4} → {:.4}, 盈亏: {:.2})",
+                decision.reason,
+                side_label(&opposite),
+                pos.amount,
+                remaining,
+                pnl
+            )
+        } else {
+            format!(
+                "{} (平{}仓盈亏: {:.2})",
+                decision.reason,
+                side_label(&opposite),
+                pnl
+            )
+        };
+        return Ok(TradeResult {
+            symbol: symbol.to_string(),
+            action: close_action(&opposite),
+            price: report.avg_price().unwrap_or(execution_price),
+            amount: report.executed_qty(),
+            timestamp: report.last_fill_time().unwrap_or(timestamp),
+            reason,
+            pnl: Some(pnl),
+            commission: report.commission(),
+            order_details: Some(format!("平{}:{}", side_label(&opposite), report)),
+            order_ids: report.order_ids(),
+        });
+    }
+
+    let reversing = action.reverses();
+    if !reversing
+        && exchange.position_mode() == PositionMode::OneWay
+        && positions.leg(&opposite).is_some()
+    {
+        info!(
+            "{} 单向持仓下持有{}仓，{:?} 不反手，跳过",
+            symbol,
+            side_label(&opposite),
+            action
+        );
+        return Ok(hold(format!(
+            "{} (单向持仓下持有{}仓，仅反手动作可开{}仓)",
+            decision.reason,
+            side_label(&opposite),
+            side_label(&target)
+        )));
+    }
+
+    let held = positions.leg(&target).map_or(0.0, |pos| pos.amount);
+    let new_total = held + trade_amount;
+    let at_limit = held > 0.0 && new_total > max_position;
+
+    // 单向持仓下反手合并为一笔市价单：先冲减原净头寸，超出部分开出新方向
+    if reversing && exchange.position_mode() == PositionMode::OneWay {
+        if let Some(pos) = positions.leg(&opposite) {
+            let order_side = match target {
+                PositionSide::Long => OrderSide::Buy,
+                PositionSide::Short => OrderSide::Sell,
+            };
+            let client_order_id = ids.id(&format!("R{}", action_tag(true, &target)));
+            // 两者均已对齐步长，按最近步长取整消除浮点误差
+            let quantity = quantize_price(pos.amount + trade_amount, constraints.step_size);
+            let order = market_order(
+                exchange,
+                symbol,
+                order_side,
+                target.clone(),
+                quantity,
+                client_order_id,
+            )
+            .await?;
+            let report = ExecutionReport::single(order);
+            let pnl = close_pnl(pos, pos.amount, execution_price, &report);
             return Ok(TradeResult {
                 symbol: symbol.to_string(),
-                action: TradeAction::Hold,
-                price: execution_price,
-                amount: 0.0,
-                timestamp,
-                reason: decision.reason.clone(),
-                pnl: None,
-                order_details: None,
+                action: open_action(&target),
+                price: report.avg_price().unwrap_or(execution_price),
+                amount: (report.executed_qty() - pos.amount).max(0.0),
+                timestamp: report.last_fill_time().unwrap_or(timestamp),
+                reason: format!(
+                    "{} (平{}仓盈亏: {:.2})",
+                    decision.reason,
+                    side_label(&opposite),
+                    pnl
+                ),
+                pnl: Some(pnl),
+                commission: report.commission(),
+                order_details: Some(format!(
+                    "反手 平{}{:.4}+开{}{:.4}:{}",
+                    side_label(&opposite),
+                    pos.amount,
+                    side_label(&target),
+                    trade_amount,
+                    report
+                )),
+                order_ids: report.order_ids(),
             });
         }
-        Signal::Buy => {
-            match current_position {
-                None => {
-                    // 空仓 → 开多
-                    let order_info = open_long(symbol, trade_amount, api_key, secret).await?;
-                    Ok(TradeResult {
-                        symbol: symbol.to_string(),
-                        action: TradeAction::OpenLong,
-                        price: execution_price,
-                        amount: trade_amount,
-                        timestamp,
-                        reason: decision.reason.clone(),
-                        pnl: None,
-                        order_details: Some(order_info),
-                    })
-                }
-                Some(pos) if pos.side == PositionSide::Short => {
-                    // 持有空仓 → 平空 → 开多
-                    let close_info = close_short(symbol, pos.amount, api_key, secret).await?;
-                    let pnl = (pos.entry_price - execution_price) * pos.amount;
-                    let open_info = open_long(symbol, trade_amount, api_key, secret).await?;
-                    Ok(TradeResult {
-                        symbol: symbol.to_string(),
-                        action: TradeAction::OpenLong,
-                        price: execution_price,
-                        amount: trade_amount,
-                        timestamp,
-                        reason: format!("{} (平空仓盈亏: {:.2})", decision.reason, pnl),
-                        pnl: Some(pnl),
-                        order_details: Some(format!("平空:{}, 开多:{}", close_info, open_info)),
-                    })
-                }
-                Some(pos) => {
-                    // 已持有多仓 → 检查是否可以加仓
-                    let new_total = pos.amount + trade_amount;
-                    if new_total > max_position {
-                        Ok(TradeResult {
-                            symbol: symbol.to_string(),
-                            action: TradeAction::Hold,
-                            price: execution_price,
-                            amount: 0.0,
-                            timestamp,
-                            reason: format!(
-                                "已达最大持仓 {:.4}/{:.4}，无法加仓",
-                                pos.amount, max_position
-                            ),
-                            pnl: None,
-                            order_details: None,
-                        })
-                    } else {
-                        // 加仓
-                        let order_info = open_long(symbol, trade_amount, api_key, secret).await?;
-                        Ok(TradeResult {
-                            symbol: symbol.to_string(),
-                            action: TradeAction::OpenLong,
-                            price: execution_price,
-                            amount: trade_amount,
-                            timestamp,
-                            reason: format!(
-                                "{} (加仓: {:.4} → {:.4})",
-                                decision.reason, pos.amount, new_total
-                            ),
-                            pnl: None,
-                            order_details: Some(order_info),
-                        })
-                    }
-                }
-            }
+    }
+
+    // 反手时先平掉反向腿
+    let closed = match positions.leg(&opposite).filter(|_| reversing) {
+        Some(pos) => {
+            let report = close_position(exchange, symbol, &opposite, pos.amount, ids).await?;
+            let pnl = close_pnl(pos, pos.amount, execution_price, &report);
+            Some((pos, report, pnl))
         }
-        Signal::Sell => {
-            match current_position {
-                None => {
-                    // 空仓 → 开空
-                    let order_info = open_short(symbol, trade_amount, api_key, secret).await?;
-                    Ok(TradeResult {
-                        symbol: symbol.to_string(),
-                        action: TradeAction::OpenShort,
-                        price: execution_price,
-                        amount: trade_amount,
-                        timestamp,
-                        reason: decision.reason.clone(),
-                        pnl: None,
-                        order_details: Some(order_info),
-                    })
-                }
-                Some(pos) if pos.side == PositionSide::Long => {
-                    // 持有多仓 → 平多 → 开空
-                    let close_info = close_long(symbol, pos.amount, api_key, secret).await?;
-                    let pnl = (execution_price - pos.entry_price) * pos.amount;
-                    let open_info = open_short(symbol, trade_amount, api_key, secret).await?;
-                    Ok(TradeResult {
-                        symbol: symbol.to_string(),
-                        action: TradeAction::OpenShort,
-                        price: execution_price,
-                        amount: trade_amount,
-                        timestamp,
-                        reason: format!("{} (平多仓盈亏: {:.2})", decision.reason, pnl),
-                        pnl: Some(pnl),
-                        order_details: Some(format!("平多:{}, 开空:{}", close_info, open_info)),
-                    })
+        None => None,
+    };
+
+    if at_limit {
+        let limit_reason = format!("已达最大持仓 {:.4}/{:.4}，无法加仓", held, max_position);
+        let Some((_, close_info, pnl)) = closed else {
+            return Ok(hold(limit_reason));
+        };
+        return Ok(TradeResult {
+            symbol: symbol.to_string(),
+            action: close_action(&opposite),
+            price: close_info.avg_price().unwrap_or(execution_price),
+            amount: close_info.executed_qty(),
+            timestamp: close_info.last_fill_time().unwrap_or(timestamp),
+            reason: format!(
+                "{} (平{}仓盈亏: {:.2}; {})",
+                decision.reason,
+                side_label(&opposite),
+                pnl,
+                limit_reason
+            ),
+            pnl: Some(pnl),
+            commission: close_info.commission(),
+            order_details: Some(format!("平{}:{}", side_label(&opposite), close_info)),
+            order_ids: close_info.order_ids(),
+        });
+    }
+
+    let open_result = open_position(
+        exchange,
+        symbol,
+        &target,
+        trade_amount,
+        policy,
+        constraints,
+        ids,
+    )
+    .await;
+    let open_info = match (open_result, &closed) {
+        (Ok(open_info), _) => open_info,
+        (Err(e), None) => return Err(e),
+        // 反手第二腿失败：按已平数量恢复原持仓，避免意外空仓
+        (Err(e), Some((pos, close_info, pnl))) => {
+            error!(
+                "反手开{}仓失败，回滚恢复{}仓: {:#}",
+                side_label(&target),
+                side_label(&opposite),
+                e
+            );
+            let quantity = close_info.executed_qty();
+            let rollback = market_order(
+                exchange,
+                symbol,
+                match opposite {
+                    PositionSide::Long => OrderSide::Buy,
+                    PositionSide::Short => OrderSide::Sell,
+                },
+                opposite.clone(),
+                quantity,
+                ids.id(&format!("RB{}", action_tag(true, &opposite))),
+            )
+            .await;
+            let (action, details, commission, order_ids) = match rollback {
+                Ok(order) => {
+                    let restored = ExecutionReport::single(order);
+                    let commission = close_info
+                        .commission()
+                        .zip(restored.commission())
+                        .map(|(close, reopen)| close + reopen);
+                    (
+                        open_action(&opposite),
+                        format!(
+                            "平{}:{}, 开{}失败: {:#}, 回滚开{}:{}",
+                            side_label(&opposite),
+                            close_info,
+                            side_label(&target),
+                            e,
+                            side_label(&opposite),
+                            restored
+                        ),
+                        commission,
+                        close_info
+                            .order_ids()
+                            .into_iter()
+                            .chain(restored.order_ids())
+                            .collect(),
+                    )
                 }
-                Some(pos) => {
-                    // 已持有空仓 → 检查是否可以加仓
-                    let new_total = pos.amount + trade_amount;
-                    if new_total > max_position {
-                        Ok(TradeResult {
-                            symbol: symbol.to_string(),
-                            action: TradeAction::Hold,
-                            price: execution_price,
-                            amount: 0.0,
-                            timestamp,
-                            reason: format!(
-                                "已达最大持仓 {:.4}/{:.4}，无法加仓",
-                                pos.amount, max_position
-                            ),
-                            pnl: None,
-                            order_details: None,
-                        })
-                    } else {
-                        // 加仓
-                        let order_info = open_short(symbol, trade_amount, api_key, secret).await?;
-                        Ok(TradeResult {
-                            symbol: symbol.to_string(),
-                            action: TradeAction::OpenShort,
-                            price: execution_price,
-                            amount: trade_amount,
-                            timestamp,
-                            reason: format!(
-                                "{} (加仓: {:.4} → {:.4})",
-                                decision.reason, pos.amount, new_total
-                            ),
-                            pnl: None,
-                            order_details: Some(order_info),
-                        })
-                    }
+                Err(rollback_err) => {
+                    error!(
+                        "回滚失败，{}仓已平且未反手: {:#}",
+                        side_label(&opposite),
+                        rollback_err
+                    );
+                    (
+                        close_action(&opposite),
+                        format!(
+                            "平{}:{}, 开{}失败: {:#}, 回滚失败: {:#}",
+                            side_label(&opposite),
+                            close_info,
+                            side_label(&target),
+                            e,
+                            rollback_err
+                        ),
+                        close_info.commission(),
+                        close_info.order_ids(),
+                    )
                 }
-            }
+            };
+            return Ok(TradeResult {
+                symbol: symbol.to_string(),
+                action,
+                price: close_info.avg_price().unwrap_or(execution_price),
+                amount: quantity,
+                timestamp: close_info.last_fill_time().unwrap_or(timestamp),
+                reason: format!(
+                    "{} (反手失败; 平{}仓盈亏: {:.2}, 原持仓 {:.4})",
+                    decision.reason,
+                    side_label(&opposite),
+                    pnl,
+                    pos.amount
+                ),
+                pnl: Some(*pnl),
+                commission,
+                order_details: Some(details),
+                order_ids,
+            });
         }
-    }
+    };
+
+    let (reason, pnl, commission, order_details) = match &closed {
+        Some((_, close_info, pnl)) => (
+            format!(
+                "{} (平{}仓盈亏: {:.2})",
+                decision.reason,
+                side_label(&opposite),
+                pnl
+            ),
+            Some(*pnl),
+            close_info
+                .commission()
+                .zip(open_info.commission())
+                .map(|(close, open)| close + open),
+            format!(
+                "平{}:{}, 开{}:{}",
+                side_label(&opposite),
+                close_info,
+                side_label(&target),
+                open_info
+            ),
+        ),
+        None if held > 0.0 => (
+            format!("{} (加仓: {:.4} → {:.4})", decision.reason, held, new_total),
+            None,
+            open_info.commission(),
+            open_info.to_string(),
+        ),
+        None => (
+            decision.reason.clone(),
+            None,
+            open_info.commission(),
+            open_info.to_string(),
+        ),
+    };
+
+    Ok(TradeResult {
+        symbol: symbol.to_string(),
+        action: open_action(&target),
+        price: open_info.avg_price().unwrap_or(execution_price),
+        amount: open_info.executed_qty(),
+        timestamp: open_info.last_fill_time().unwrap_or(timestamp),
+        reason,
+        pnl,
+        commission,
+        order_details: Some(order_details),
+        order_ids: closed
+            .iter()
+            .flat_map(|(_, close_info, _)| close_info.order_ids())
+            .chain(open_info.order_ids())
+            .collect(),
+    })
 }
@@ -0,0 +1,821 @@
+// 技术指标库：EMA、Wilder RSI/ATR、MACD、布林带、ADX/DI、随机指标、日内 VWAP、OBV、唐奇安通道。
+// 均以K线切片计算最新值，数据不足时返回 None；各智能体的数据中包含哪些指标由 INDICATORS_<ROLE> 配置
+
+use crate::llm::AgentRole;
+use crate::types::Kline;
+use anyhow::{bail, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+
+const DAY_MS: i64 = 86_400_000;
+
+// ===== 序列计算 =====
+
+// 指数移动平均，以前 period 个值的简单均值为种子；结果第 i 项对应 values[period - 1 + i]
+pub fn ema(values: &[f64], period: usize) -> Vec<f64> {
+    smooth(values, period, 2.0 / (period as f64 + 1.0))
+}
+
+// Wilder 平滑 (RMA)，即 alpha = 1/period 的指数平均，对齐方式同 ema
+pub fn rma(values: &[f64], period: usize) -> Vec<f64> {
+    smooth(values, period, 1.0 / period as f64)
+}
+
+fn smooth(values: &[f64], period: usize, alpha: f64) -> Vec<f64> {
+    if period == 0 || values.len() < period {
+        return Vec::new();
+    }
+    let seed = values[..period].iter().sum::<f64>() / period as f64;
+    let mut out = Vec::with_capacity(values.len() - period + 1);
+    out.push(seed);
+    let mut prev = seed;
+    for value in &values[period..] {
+        prev += alpha * (value - prev);
+        out.push(prev);
+    }
+    out
+}
+
+// 真实波幅，从第二根K线开始（需要前收盘价）
+pub fn true_ranges(klines: &[Kline]) -> Vec<f64> {
+    klines
+        .windows(2)
+        .map(|pair| {
+            let (prev, current) = (&pair[0], &pair[1]);
+            (current.high - current.low)
+                .max((current.high - prev.close).abs())
+                .max((current.low - prev.close).abs())
+        })
+        .collect()
+}
+
+// 能量潮：首根为 0，收涨累加成交量，收跌累减
+pub fn obv_series(klines: &[Kline]) -> Vec<f64> {
+    let mut out = Vec::with_capacity(klines.len());
+    let mut total = 0.0;
+    for (i, kline) in klines.iter().enumerate() {
+        if i > 0 {
+            let prev = klines[i - 1].close;
+            if kline.close > prev {
+                total += kline.volume;
+            } else if kline.close < prev {
+                total -= kline.volume;
+            }
+        }
+        out.push(total);
+    }
+    out
+}
+
+// ===== 最新值 =====
+
+// Wilder RSI
+pub fn rsi(closes: &[f64], period: usize) -> Option<f64> {
+    let changes: Vec<f64> = closes.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let gains: Vec<f64> = changes.iter().map(|c| c.max(0.0)).collect();
+    let losses: Vec<f64> = changes.iter().map(|c| (-c).max(0.0)).collect();
+    let avg_gain = *rma(&gains, period).last()?;
+    let avg_loss = *rma(&losses, period).last()?;
+    Some(if avg_loss == 0.0 {
+        if avg_gain == 0.0 {
+            50.0
+        } else {
+            100.0
+        }
+    } else {
+        100.0 - 100.0 / (1.0 + avg_gain / avg_loss)
+    })
+}
+
+// Wilder ATR，需要 period + 1 根K线
+pub fn atr(klines: &[Kline], period: usize) -> Option<f64> {
+    rma(&true_ranges(klines), period).last().copied()
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct Macd {
+    pub macd: f64,      // 快线 EMA - 慢线 EMA
+    pub signal: f64,    // MACD 的 EMA
+    pub histogram: f64, // MACD - 信号线
+}
+
+pub fn macd(closes: &[f64], fast: usize, slow: usize, signal: usize) -> Option<Macd> {
+    if fast >= slow {
+        return None;
+    }
+    let fast_ema = ema(closes, fast);
+    let slow_ema = ema(closes, slow);
+    // 两条 EMA 按收盘价下标对齐
+    let offset = slow - fast;
+    let line: Vec<f64> = slow_ema
+        .iter()
+        .enumerate()
+        .map(|(i, slow)| fast_ema[i + offset] - slow)
+        .collect();
+    let signal = *ema(&line, signal).last()?;
+    let macd = *line.last()?;
+    Some(Macd {
+        macd,
+        signal,
+        histogram: macd - signal,
+    })
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct Bollinger {
+    pub upper: f64,
+    pub middle: f64,
+    pub lower: f64,
+    pub width: f64,     // (上轨 - 下轨) / 中轨
+    pub percent_b: f64, // 收盘价在通道中的位置，0 为下轨，1 为上轨
+}
+
+// 布林带，标准差按总体口径计算
+pub fn bollinger(closes: &[f64], period: usize, multiplier: f64) -> Option<Bollinger> {
+    if period == 0 || closes.len() < period {
+        return None;
+    }
+    let window = &closes[closes.len() - period..];
+    let middle = window.iter().sum::<f64>() / period as f64;
+    let variance = window.iter().map(|c| (c - middle).powi(2)).sum::<f64>() / period as f64;
+    let deviation = variance.sqrt() * multiplier;
+    let (upper, lower) = (middle + deviation, middle - deviation);
+    let close = *window.last()?;
+    Some(Bollinger {
+        upper,
+        middle,
+        lower,
+        width: if middle.abs() < f64::EPSILON {
+            0.0
+        } else {
+            (upper - lower) / middle
+        },
+        percent_b: if upper > lower {
+            (close - lower) / (upper - lower)
+        } else {
+            0.5
+        },
+    })
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct Adx {
+    pub adx: f64,
+    pub plus_di: f64,
+    pub minus_di: f64,
+}
+
+// Wilder ADX/DI，需要 2 × period 根K线
+pub fn adx(klines: &[Kline], period: usize) -> Option<Adx> {
+    let (plus_dm, minus_dm): (Vec<f64>, Vec<f64>) = klines
+        .windows(2)
+        .map(|pair| {
+            let up = pair[1].high - pair[0].high;
+            let down = pair[0].low - pair[1].low;
+            (
+                if up > down && up > 0.0 { up } else { 0.0 },
+                if down > up && down > 0.0 { down } else { 0.0 },
+            )
+        })
+        .unzip();
+    let tr = rma(&true_ranges(klines), period);
+    let plus = rma(&plus_dm, period);
+    let minus = rma(&minus_dm, period);
+    let di = |dm: f64, tr: f64| if tr > 0.0 { dm / tr * 100.0 } else { 0.0 };
+    let dx: Vec<f64> = tr
+        .iter()
+        .zip(plus.iter().zip(&minus))
+        .map(|(tr, (plus, minus))| {
+            let (plus_di, minus_di) = (di(*plus, *tr), di(*minus, *tr));
+            let sum = plus_di + minus_di;
+            if sum > 0.0 {
+                (plus_di - minus_di).abs() / sum * 100.0
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    let adx = *rma(&dx, period).last()?;
+    let tr = *tr.last()?;
+    Some(Adx {
+        adx,
+        plus_di: di(*plus.last()?, tr),
+        minus_di: di(*minus.last()?, tr),
+    })
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct Stochastic {
+    pub k: f64, // 最新 %K
+    pub d: f64, // %K 的 d_period 简单均值
+}
+
+pub fn stochastic(klines: &[Kline], k_period: usize, d_period: usize) -> Option<Stochastic> {
+    if k_period == 0 || d_period == 0 || klines.len() < k_period + d_period - 1 {
+        return None;
+    }
+    let k_values: Vec<f64> = klines
+        .windows(k_period)
+        .map(|window| {
+            let high = window.iter().map(|k| k.high).fold(f64::MIN, f64::max);
+            let low = window.iter().map(|k| k.low).fold(f64::MAX, f64::min);
+            let close = window[k_period - 1].close;
+            if high > low {
+                (close - low) / (high - low) * 100.0
+            } else {
+                50.0
+            }
+        })
+        .collect();
+    let recent = &k_values[k_values.len() - d_period..];
+    Some(Stochastic {
+        k: *k_values.last()?,
+        d: recent.iter().sum::<f64>() / d_period as f64,
+    })
+}
+
+// 当日（UTC）成交量加权均价，典型价格 = (高 + 低 + 收) / 3
+pub fn session_vwap(klines: &[Kline]) -> Option<f64> {
+    let last = klines.last()?;
+    let session_start = last.timestamp - last.timestamp.rem_euclid(DAY_MS);
+    let (value, volume) = klines
+        .iter()
+        .rev()
+        .take_while(|k| k.timestamp >= session_start)
+        .fold((0.0, 0.0), |(value, volume), k| {
+            let typical = (k.high + k.low + k.close) / 3.0;
+            (value + typical * k.volume, volume + k.volume)
+        });
+    (volume > 0.0).then(|| value / volume)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct Donchian {
+    pub upper: f64,
+    pub middle: f64,
+    pub lower: f64,
+}
+
+// 唐奇安通道，含当前K线
+pub fn donchian(klines: &[Kline], period: usize) -> Option<Donchian> {
+    if period == 0 || klines.len() < period {
+        return None;
+    }
+    let window = &klines[klines.len() - period..];
+    let upper = window.iter().map(|k| k.high).fold(f64::MIN, f64::max);
+    let lower = window.iter().map(|k| k.low).fold(f64::MAX, f64::min);
+    Some(Donchian {
+        upper,
+        middle: (upper + lower) / 2.0,
+        lower,
+    })
+}
+
+// ===== 指标集合 =====
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct EmaSet {
+    pub ema_12: f64,
+    pub ema_26: f64,
+    pub ema_50: f64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct Volatility {
+    pub atr_14: f64,
+    pub atr_percent: f64, // ATR 占收盘价的比例 (%)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct Vwap {
+    pub vwap: f64,
+    pub close_vs_vwap: f64, // 收盘价相对 VWAP (%)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct Obv {
+    pub obv: f64,
+    pub change_20: f64, // 近 20 根K线的 OBV 变化
+}
+
+// 字段名即 INDICATORS_<ROLE> 中使用的指标名
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IndicatorSet {
+    pub ema: Option<EmaSet>,
+    pub rsi: Option<f64>, // RSI14
+    pub atr: Option<Volatility>,
+    pub macd: Option<Macd>,             // 12/26/9
+    pub bollinger: Option<Bollinger>,   // 20, 2σ
+    pub adx: Option<Adx>,               // 14
+    pub stochastic: Option<Stochastic>, // 14/3
+    pub vwap: Option<Vwap>,
+    pub obv: Option<Obv>,
+    pub donchian: Option<Donchian>, // 20
+}
+
+impl IndicatorSet {
+    pub fn compute(klines: &[Kline]) -> Self {
+        let closes: Vec<f64> = klines.iter().map(|k| k.close).collect();
+        let close = closes.last().copied().unwrap_or(0.0);
+        let pct_of_close = |value: f64| {
+            if close.abs() < f64::EPSILON {
+                0.0
+            } else {
+                value / close * 100.0
+            }
+        };
+        let obv = obv_series(klines);
+        IndicatorSet {
+            ema: match (
+                ema(&closes, 12).last(),
+                ema(&closes, 26).last(),
+                ema(&closes, 50).last(),
+            ) {
+                (Some(&ema_12), Some(&ema_26), Some(&ema_50)) => Some(EmaSet {
+                    ema_12,
+                    ema_26,
+                    ema_50,
+                }),
+                _ => None,
+            },
+            rsi: rsi(&closes, 14),
+            atr: atr(klines, 14).map(|atr_14| Volatility {
+                atr_14,
+                atr_percent: pct_of_close(atr_14),
+            }),
+            macd: macd(&closes, 12, 26, 9),
+            bollinger: bollinger(&closes, 20, 2.0),
+            adx: adx(klines, 14),
+            stochastic: stochastic(klines, 14, 3),
+            vwap: session_vwap(klines).map(|vwap| Vwap {
+                vwap,
+                close_vs_vwap: if vwap > 0.0 {
+                    (close - vwap) / vwap * 100.0
+                } else {
+                    0.0
+                },
+            }),
+            obv: obv.last().map(|&last| Obv {
+                obv: last,
+                change_20: last - obv[obv.len().saturating_sub(21)],
+            }),
+            donchian: donchian(klines, 20),
+        }
+    }
+
+    // 仅保留选中的指标
+    pub fn select(&self, selection: &[Indicator]) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or(Value::Null);
+        if let Value::Object(map) = &mut value {
+            map.retain(|key, _| selection.iter().any(|i| i.as_str() == key));
+        }
+        value
+    }
+}
+
+impl fmt::Display for IndicatorSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(e) = &self.ema {
+            parts.push(format!(
+                "EMA12/26/50={:.2}/{:.2}/{:.2}",
+                e.ema_12, e.ema_26, e.ema_50
+            ));
+        }
+        if let Some(rsi) = self.rsi {
+            parts.push(format!("RSI14={:.1}", rsi));
+        }
+        if let Some(m) = &self.macd {
+            parts.push(format!("MACD={:.4}/{:.4}", m.macd, m.signal));
+        }
+        if let Some(b) = &self.bollinger {
+            parts.push(format!("%B={:.2} 带宽={:.4}", b.percent_b, b.width));
+        }
+        if let Some(a) = &self.adx {
+            parts.push(format!(
+                "ADX={:.1} +DI={:.1} -DI={:.1}",
+                a.adx, a.plus_di, a.minus_di
+            ));
+        }
+        if let Some(s) = &self.stochastic {
+            parts.push(format!("KD={:.1}/{:.1}", s.k, s.d));
+        }
+        if let Some(v) = &self.vwap {
+            parts.push(format!("VWAP={:.2} ({:+.2}%)", v.vwap, v.close_vs_vwap));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+// ===== 按智能体选择 =====
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indicator {
+    Ema,
+    Rsi,
+    Atr,
+    Macd,
+    Bollinger,
+    Adx,
+    Stochastic,
+    Vwap,
+    Obv,
+    Donchian,
+}
+
+impl Indicator {
+    pub const ALL: [Indicator; 10] = [
+        Indicator::Ema,
+        Indicator::Rsi,
+        Indicator::Atr,
+        Indicator::Macd,
+        Indicator::Bollinger,
+        Indicator::Adx,
+        Indicator::Stochastic,
+        Indicator::Vwap,
+        Indicator::Obv,
+        Indicator::Donchian,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Indicator::Ema => "ema",
+            Indicator::Rsi => "rsi",
+            Indicator::Atr => "atr",
+            Indicator::Macd => "macd",
+            Indicator::Bollinger => "bollinger",
+            Indicator::Adx => "adx",
+            Indicator::Stochastic => "stochastic",
+            Indicator::Vwap => "vwap",
+            Indicator::Obv => "obv",
+            Indicator::Donchian => "donchian",
+        }
+    }
+
+    // 逗号分隔的指标名，all 表示全部，none 或留空表示不提供
+    pub fn parse_list(value: &str) -> Result<Vec<Indicator>> {
+        let mut list = Vec::new();
+        for name in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match name.to_lowercase().as_str() {
+                "all" => return Ok(Indicator::ALL.to_vec()),
+                "none" => {}
+                other => match Indicator::ALL.iter().find(|i| i.as_str() == other) {
+                    Some(indicator) if !list.contains(indicator) => list.push(*indicator),
+                    Some(_) => {}
+                    None => bail!("未知指标: {}", name),
+                },
+            }
+        }
+        Ok(list)
+    }
+}
+
+// 各智能体数据中附带的指标
+#[derive(Debug, Clone)]
+pub struct IndicatorSelection {
+    by_role: HashMap<AgentRole, Vec<Indicator>>,
+}
+
+impl IndicatorSelection {
+    pub fn from_env() -> Result<Self> {
+        let mut by_role = HashMap::new();
+        for role in AgentRole::ALL {
+            let key = format!("INDICATORS_{}", role.as_str().to_uppercase());
+            let selection = match env::var(&key) {
+                Ok(value) => Indicator::parse_list(&value)
+                    .map_err(|e| e.context(format!("{} 格式错误", key)))?,
+                Err(_) => Self::default_for(role),
+            };
+            by_role.insert(role, selection);
+        }
+        Ok(IndicatorSelection { by_role })
+    }
+
+    fn default_for(role: AgentRole) -> Vec<Indicator> {
+        match role {
+            AgentRole::MarketAnalyst => Indicator::ALL.to_vec(),
+            AgentRole::StrategyResearcher => {
+                vec![Indicator::Rsi, Indicator::Macd, Indicator::Adx]
+            }
+            AgentRole::RiskManager => {
+                vec![Indicator::Atr, Indicator::Bollinger, Indicator::Donchian]
+            }
+            AgentRole::TradeExecutor | AgentRole::PortfolioCoordinator => Vec::new(),
+        }
+    }
+
+    // 该智能体的指标数据，未选择任何指标时返回 None
+    pub fn payload(&self, role: AgentRole, set: &IndicatorSet) -> Option<Value> {
+        self.by_role
+            .get(&role)
+            .filter(|selection| !selection.is_empty())
+            .map(|selection| set.select(selection))
+    }
+}
+
+impl fmt::Display for IndicatorSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = AgentRole::ALL
+            .iter()
+            .map(|role| {
+                let names: Vec<&str> = self
+                    .by_role
+                    .get(role)
+                    .map(|list| list.iter().map(Indicator::as_str).collect())
+                    .unwrap_or_default();
+                let names = if names.is_empty() {
+                    "-".to_string()
+                } else {
+                    names.join(",")
+                };
+                format!("{}={}", role.as_str(), names)
+            })
+            .collect();
+        write!(f, "{}", parts.join(" | "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 参考值由独立的教科书式实现（Wilder 累加平滑、逐项 EMA）对同一组K线计算得出
+    fn sample_klines() -> Vec<Kline> {
+        (0..80)
+            .map(|i| {
+                let x = i as f64;
+                let base = 100.0 + 10.0 * (x / 7.0).sin() + 0.3 * x;
+                let open = base + 1.5 * (x * 1.3).sin();
+                let close = base + 1.5 * (x * 0.9).cos();
+                Kline {
+                    // 小时K线，跨越 UTC 零点
+                    timestamp: 1_735_689_600_000 - 20 * 3_600_000 + i * 3_600_000,
+                    open,
+                    high: open.max(close) + 0.5 + 0.4 * (x * 2.1).sin().abs(),
+                    low: open.min(close) - 0.5 - 0.4 * (x * 1.7).cos().abs(),
+                    close,
+                    volume: 1000.0 + 300.0 * (x * 0.5).sin() + 10.0 * x,
+                    taker_buy_volume: 0.0,
+                    taker_buy_quote_volume: 0.0,
+                }
+            })
+            .collect()
+    }
+
+    fn closes(klines: &[Kline]) -> Vec<f64> {
+        klines.iter().map(|k| k.close).collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "实际 {} 与参考值 {} 相差超过 {}",
+            actual,
+            expected,
+            tolerance
+        );
+    }
+
+    #[test]
+    fn ema_seeds_with_sma() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let out = ema(&values, 3);
+        // 种子 2.0，alpha 0.5: 3.0, 4.0, 5.0
+        assert_eq!(out, vec![2.0, 3.0, 4.0, 5.0]);
+        assert!(ema(&values, 7).is_empty());
+    }
+
+    #[test]
+    fn ema_matches_reference() {
+        let c = closes(&sample_klines());
+        assert_close(*ema(&c, 12).last().unwrap(), 115.04379682374291, 1e-9);
+        assert_close(*ema(&c, 26).last().unwrap(), 116.48639029197031, 1e-9);
+        assert_close(*ema(&c, 50).last().unwrap(), 115.31183182333974, 1e-9);
+    }
+
+    #[test]
+    fn rsi_matches_wilder_example() {
+        // StockCharts RSI 示例数据，公布值基于四舍五入的平均涨跌幅，允许 0.1 误差
+        let closes = [
+            44.34, 44.09, 44.15, 43.61, 44.33, 44.83, 45.10, 45.42, 45.84, 46.08, 45.89, 46.03,
+            45.61, 46.28, 46.28, 46.00, 46.03, 46.41, 46.22, 45.64, 46.21, 46.25, 45.71, 46.45,
+            45.78, 45.35, 44.03, 44.18, 44.22, 44.57, 43.42, 42.66, 43.13,
+        ];
+        let published = [
+            70.53, 66.32, 66.55, 69.41, 66.36, 57.97, 62.93, 63.26, 56.06, 62.38, 54.71, 50.42,
+            39.99, 41.46, 41.87, 45.46, 37.30, 33.08, 37.77,
+        ];
+        for (i, expected) in published.iter().enumerate() {
+            let value = rsi(&closes[..15 + i], 14).unwrap();
+            assert_close(value, *expected, 0.1);
+        }
+        assert!(rsi(&closes[..14], 14).is_none());
+    }
+
+    #[test]
+    fn rsi_bounds() {
+        let rising: Vec<f64> = (0..20).map(f64::from).collect();
+        assert_eq!(rsi(&rising, 14), Some(100.0));
+        assert_eq!(rsi(&[5.0; 20], 14), Some(50.0));
+        let c = closes(&sample_klines());
+        assert_close(rsi(&c, 14).unwrap(), 38.401163606223726, 1e-9);
+    }
+
+    // 由最高、最低、收盘价构造K线，用于公开的日线示例数据
+    fn bar(high: f64, low: f64, close: f64) -> Kline {
+        Kline {
+            timestamp: 0,
+            open: close,
+            high,
+            low,
+            close,
+            volume: 0.0,
+            taker_buy_volume: 0.0,
+            taker_buy_quote_volume: 0.0,
+        }
+    }
+
+    // 逐根上移 1 的K线：低点 i、高点 i + 1、收盘 i + close_offset
+    fn rising_bars(count: usize, close_offset: f64) -> Vec<Kline> {
+        (0..count)
+            .map(|i| bar(i as f64 + 1.0, i as f64, i as f64 + close_offset))
+            .collect()
+    }
+
+    #[test]
+    fn ema_matches_stockcharts_example() {
+        // StockCharts 移动平均示例的 10 日 EMA，公布值保留两位小数
+        let closes = [
+            22.27, 22.19, 22.08, 22.17, 22.18, 22.13, 22.23, 22.43, 22.24, 22.29, 22.15, 22.39,
+            22.38, 22.61, 23.36, 24.05, 23.75, 23.83, 23.95, 23.63, 23.82, 23.87, 23.65, 23.19,
+            23.10, 23.33, 22.68, 23.10, 22.40, 22.17,
+        ];
+        let published = [
+            22.22, 22.21, 22.24, 22.27, 22.33, 22.52, 22.80, 22.97, 23.13, 23.28, 23.34, 23.43,
+            23.51, 23.54, 23.47, 23.40, 23.39, 23.26, 23.23, 23.08, 22.92,
+        ];
+        let out = ema(&closes, 10);
+        assert_eq!(out.len(), published.len());
+        for (value, expected) in out.iter().zip(published) {
+            assert_close(*value, expected, 0.01);
+        }
+    }
+
+    #[test]
+    fn atr_matches_stockcharts_example() {
+        // StockCharts ATR 示例 (QQQ) 的 14 日 ATR。StockCharts 以首根的高低差作为首个真实波幅，
+        // 前置一根收于首根区间中点的K线后两种口径一致；公布值保留两位小数
+        let highs = [
+            48.70, 48.72, 48.90, 48.87, 48.82, 49.05, 49.20, 49.35, 49.92, 50.19, 50.12, 49.66,
+            49.88, 50.19, 50.36, 50.57, 50.65, 50.43, 49.63, 50.33, 50.29, 50.17, 49.32, 48.50,
+            48.32, 46.80, 47.80, 48.39, 48.66, 48.79,
+        ];
+        let lows = [
+            47.79, 48.14, 48.39, 48.37, 48.24, 48.64, 48.94, 48.86, 49.50, 49.87, 49.20, 48.90,
+            49.43, 49.73, 49.26, 50.09, 50.30, 49.21, 48.98, 49.61, 49.20, 49.43, 48.08, 47.64,
+            41.55, 44.28, 47.31, 47.20, 47.90, 47.73,
+        ];
+        let closes = [
+            48.16, 48.61, 48.75, 48.63, 48.74, 49.03, 49.07, 49.32, 49.91, 50.13, 49.53, 49.50,
+            49.75, 50.03, 50.31, 50.52, 50.41, 49.34, 49.37, 50.23, 49.24, 49.93, 48.43, 48.18,
+            46.57, 45.41, 47.77, 47.72, 48.62, 47.85,
+        ];
+        let published = [
+            0.56, 0.59, 0.59, 0.57, 0.62, 0.62, 0.64, 0.67, 0.69, 0.78, 0.78, 1.21, 1.30, 1.38,
+            1.37, 1.34, 1.32,
+        ];
+        let mut klines = vec![bar(0.0, 0.0, (highs[0] + lows[0]) / 2.0)];
+        klines.extend((0..highs.len()).map(|i| bar(highs[i], lows[i], closes[i])));
+        for (i, expected) in published.iter().enumerate() {
+            let value = atr(&klines[..15 + i], 14).unwrap();
+            assert_close(value, *expected, 0.01);
+        }
+        assert!(atr(&klines[..14], 14).is_none());
+    }
+
+    #[test]
+    fn macd_of_linear_trend() {
+        // SMA 为种子的 EMA 在斜率为 s 的直线上恰好滞后 s × (n - 1) / 2，
+        // 因此 MACD(12, 26, 9) = s × (25 - 11) / 2 = 7s，信号线相同，柱为 0
+        let closes: Vec<f64> = (0..60).map(|i| 100.0 + 0.5 * i as f64).collect();
+        let m = macd(&closes, 12, 26, 9).unwrap();
+        assert_close(m.macd, 3.5, 1e-9);
+        assert_close(m.signal, 3.5, 1e-9);
+        assert_close(m.histogram, 0.0, 1e-9);
+        let falling: Vec<f64> = closes.iter().rev().copied().collect();
+        assert_close(macd(&falling, 12, 26, 9).unwrap().macd, -3.5, 1e-9);
+        // 慢线 26 根 + 信号线 9 根
+        assert!(macd(&closes[..33], 12, 26, 9).is_none());
+        assert!(macd(&closes[..34], 12, 26, 9).is_some());
+    }
+
+    #[test]
+    fn adx_of_steady_trend() {
+        // 每根上移 1：+DM = 1、-DM = 0，真实波幅 = 高点 - 前收 = 1.5，
+        // 故 +DI = 100 / 1.5、-DI = 0、DX 恒为 100
+        let rising = rising_bars(40, 0.5);
+        let a = adx(&rising, 14).unwrap();
+        assert_close(a.plus_di, 100.0 / 1.5, 1e-9);
+        assert_close(a.minus_di, 0.0, 1e-9);
+        assert_close(a.adx, 100.0, 1e-9);
+
+        let falling: Vec<Kline> = rising.iter().rev().cloned().collect();
+        let a = adx(&falling, 14).unwrap();
+        assert_close(a.plus_di, 0.0, 1e-9);
+        assert_close(a.minus_di, 100.0 / 1.5, 1e-9);
+        assert_close(a.adx, 100.0, 1e-9);
+
+        assert!(adx(&rising[..27], 14).is_none());
+        assert!(adx(&rising[..28], 14).is_some());
+    }
+
+    #[test]
+    fn stochastic_of_steady_trend() {
+        // 14 根窗口的最低价为 i - 13、最高价为 i + 1，区间宽 14；
+        // 收于 i + 0.5 时 %K = 13.5 / 14 × 100，各期相同故 %D 相等
+        let s = stochastic(&rising_bars(30, 0.5), 14, 3).unwrap();
+        assert_close(s.k, 1350.0 / 14.0, 1e-9);
+        assert_close(s.d, 1350.0 / 14.0, 1e-9);
+        // 收于最高价与最低价
+        assert_close(
+            stochastic(&rising_bars(30, 1.0), 14, 3).unwrap().k,
+            100.0,
+            1e-9,
+        );
+        let falling: Vec<Kline> = rising_bars(30, 0.0).into_iter().rev().collect();
+        assert_close(stochastic(&falling, 14, 3).unwrap().k, 0.0, 1e-9);
+        // %D 为最近 3 个 %K 的均值：最后一根收于最高价
+        let mut klines = rising_bars(30, 0.5);
+        klines.last_mut().unwrap().close = 30.0;
+        let s = stochastic(&klines, 14, 3).unwrap();
+        assert_close(s.k, 100.0, 1e-9);
+        assert_close(s.d, (100.0 + 2.0 * 1350.0 / 14.0) / 3.0, 1e-9);
+        assert!(stochastic(&klines[..15], 14, 3).is_none());
+        assert!(stochastic(&klines[..16], 14, 3).is_some());
+    }
+
+    #[test]
+    fn bollinger_matches_reference() {
+        let b = bollinger(&closes(&sample_klines()), 20, 2.0).unwrap();
+        assert_close(b.upper, 126.4274838390716, 1e-9);
+        assert_close(b.middle, 117.57027238839142, 1e-9);
+        assert_close(b.lower, 108.71306093771123, 1e-9);
+        assert_close(b.width, 0.1506709352755522, 1e-12);
+        assert_close(b.percent_b, 0.27103687486243977, 1e-12);
+    }
+
+    #[test]
+    fn vwap_resets_each_utc_day() {
+        let klines = sample_klines();
+        assert_close(session_vwap(&klines).unwrap(), 114.52785782804095, 1e-9);
+        // 单根K线的 VWAP 即其典型价格
+        let first = &klines[20];
+        assert_close(
+            session_vwap(&klines[..21]).unwrap(),
+            (first.high + first.low + first.close) / 3.0,
+            1e-12,
+        );
+    }
+
+    #[test]
+    fn obv_and_donchian_match_reference() {
+        let klines = sample_klines();
+        let obv = obv_series(&klines);
+        assert_eq!(obv[0], 0.0);
+        assert_close(*obv.last().unwrap(), 16217.838795358166, 1e-6);
+        let d = donchian(&klines, 20).unwrap();
+        assert_close(d.upper, 126.93783128864719, 1e-9);
+        assert_close(d.lower, 110.54561817321422, 1e-9);
+        assert_close(d.middle, (d.upper + d.lower) / 2.0, 1e-12);
+    }
+
+    #[test]
+    fn indicator_set_selects_requested_keys() {
+        let set = IndicatorSet::compute(&sample_klines());
+        assert_close(set.obv.unwrap().change_20, -10238.864303096965, 1e-6);
+        let payload = set.select(&[Indicator::Rsi, Indicator::Macd]);
+        let keys: Vec<&String> = payload.as_object().unwrap().keys().collect();
+        assert_eq!(keys.len(), 2);
+        assert!(payload.get("rsi").is_some() && payload.get("macd").is_some());
+
+        // 数据不足时对应指标为 null
+        let short = IndicatorSet::compute(&sample_klines()[..10]);
+        assert!(short.rsi.is_none() && short.donchian.is_none());
+        assert!(short.vwap.is_some());
+    }
+
+    #[test]
+    fn parse_indicator_list() {
+        assert_eq!(
+            Indicator::parse_list("rsi, MACD,rsi").unwrap(),
+            vec![Indicator::Rsi, Indicator::Macd]
+        );
+        assert_eq!(Indicator::parse_list("all").unwrap().len(), 10);
+        assert!(Indicator::parse_list("none").unwrap().is_empty());
+        assert!(Indicator::parse_list("").unwrap().is_empty());
+        assert!(Indicator::parse_list("rsi,kdj").is_err());
+    }
+}
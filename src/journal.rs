@@ -0,0 +1,495 @@
+// 结构化交易日志 (SQLite)：交易周期、各智能体输出（含 LLM 原文）、决策、订单、成交与账户快照，
+// 以 cycle_id 关联，用于追溯某笔交易的决策过程。写入失败只记录警告，不影响交易流程
+
+use crate::exchange::{Fill, OrderRequest, OrderState};
+use crate::executor::AccountInfo;
+use crate::llm::{AgentRole, LlmBackend, LlmRequest};
+use crate::logging;
+use crate::risk::{RiskCheck, RiskEvent};
+use crate::types::{PositionBook, Signal, TradeResult, TradingDecision};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use log::warn;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs::create_dir_all;
+use std::path::Path;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+const JOURNAL_FILE: &str = "journal.db";
+const SCHEMA_VERSION: i64 = 2; // 记录在 PRAGMA user_version；2 新增 risk_events
+
+// 首次写入时在日志目录下打开；JOURNAL_ENABLED=false 或打开失败时为 None
+static JOURNAL: OnceLock<Option<Mutex<Connection>>> = OnceLock::new();
+// 当前交易周期的 cycles.id，0 表示不在周期内
+static CURRENT_CYCLE: AtomicI64 = AtomicI64::new(0);
+
+// 时间字段均为毫秒时间戳：started_at/time 为交易所时间（回测为K线时间），created_at 为写入时的系统时间
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS cycles (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    cycle INTEGER NOT NULL,
+    started_at INTEGER NOT NULL,
+    finished_at INTEGER,
+    exchange TEXT NOT NULL,
+    symbols TEXT NOT NULL,
+    status TEXT NOT NULL,
+    error TEXT
+);
+CREATE TABLE IF NOT EXISTS llm_calls (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    cycle_id INTEGER REFERENCES cycles(id),
+    role TEXT NOT NULL,
+    symbol TEXT,
+    backend TEXT NOT NULL,
+    system_prompt TEXT NOT NULL,
+    user_prompt TEXT NOT NULL,
+    response TEXT,
+    error TEXT,
+    latency_ms INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS agent_outputs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    cycle_id INTEGER REFERENCES cycles(id),
+    role TEXT NOT NULL,
+    symbol TEXT,
+    output TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS decisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    cycle_id INTEGER REFERENCES cycles(id),
+    symbol TEXT NOT NULL,
+    signal TEXT NOT NULL,
+    amount REAL NOT NULL,
+    confidence TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    decision TEXT NOT NULL,
+    positions TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS orders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    cycle_id INTEGER REFERENCES cycles(id),
+    symbol TEXT NOT NULL,
+    order_id INTEGER,
+    client_order_id TEXT,
+    side TEXT NOT NULL,
+    position_side TEXT NOT NULL,
+    order_type TEXT NOT NULL,
+    quantity REAL NOT NULL,
+    price REAL,
+    stop_price REAL,
+    close_position INTEGER NOT NULL,
+    status TEXT,
+    executed_qty REAL,
+    avg_price REAL,
+    error TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_orders_order_id ON orders(symbol, order_id);
+CREATE TABLE IF NOT EXISTS fills (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    cycle_id INTEGER REFERENCES cycles(id),
+    symbol TEXT NOT NULL,
+    order_id INTEGER NOT NULL,
+    trade_id INTEGER NOT NULL,
+    price REAL NOT NULL,
+    quantity REAL NOT NULL,
+    commission REAL NOT NULL,
+    commission_asset TEXT NOT NULL,
+    realized_pnl REAL NOT NULL,
+    maker INTEGER NOT NULL,
+    time INTEGER NOT NULL,
+    UNIQUE (symbol, trade_id)
+);
+CREATE TABLE IF NOT EXISTS trades (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    cycle_id INTEGER REFERENCES cycles(id),
+    symbol TEXT NOT NULL,
+    action TEXT NOT NULL,
+    price REAL NOT NULL,
+    amount REAL NOT NULL,
+    pnl REAL,
+    commission REAL,
+    reason TEXT NOT NULL,
+    order_details TEXT,
+    time INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS risk_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    cycle_id INTEGER REFERENCES cycles(id),
+    symbol TEXT NOT NULL,
+    signal TEXT NOT NULL,
+    rule TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    requested REAL NOT NULL,
+    approved REAL NOT NULL,
+    detail TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS account_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    cycle_id INTEGER REFERENCES cycles(id),
+    wallet_balance REAL NOT NULL,
+    available_balance REAL NOT NULL,
+    equity REAL NOT NULL,
+    positions TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+";
+
+fn open() -> Option<Mutex<Connection>> {
+    if env::var("JOURNAL_ENABLED").is_ok_and(|v| v == "false") {
+        return None;
+    }
+    match open_connection() {
+        Ok(conn) => Some(Mutex::new(conn)),
+        Err(e) => {
+            warn!("打开交易日志库失败，停用结构化日志: {:#}", e);
+            None
+        }
+    }
+}
+
+fn open_connection() -> Result<Connection> {
+    let base_dir = Path::new(logging::logs_directory());
+    create_dir_all(base_dir).context("创建logs目录失败")?;
+    let conn = Connection::open(base_dir.join(JOURNAL_FILE)).context("打开journal.db失败")?;
+    conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")
+        .context("设置journal.db参数失败")?;
+
+    let version: i64 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .context("读取journal.db版本失败")?;
+    if version > SCHEMA_VERSION {
+        bail!(
+            "journal.db 版本 {} 高于当前支持的版本 {}",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+    conn.execute_batch(SCHEMA)
+        .context("创建journal.db表结构失败")?;
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION)
+        .context("写入journal.db版本失败")?;
+    Ok(conn)
+}
+
+fn with_connection(what: &str, f: impl FnOnce(&Connection) -> rusqlite::Result<()>) {
+    let Some(journal) = JOURNAL.get_or_init(open) else {
+        return;
+    };
+    let conn = journal.lock().unwrap();
+    if let Err(e) = f(&conn) {
+        warn!("写入交易日志库失败 ({}): {}", what, e);
+    }
+}
+
+fn cycle_id() -> Option<i64> {
+    match CURRENT_CYCLE.load(Ordering::SeqCst) {
+        0 => None,
+        id => Some(id),
+    }
+}
+
+fn now_ms() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+// ===== 交易周期 =====
+
+pub fn begin_cycle(cycle: i64, started_at: i64, exchange: &str, symbols: &[String]) {
+    CURRENT_CYCLE.store(0, Ordering::SeqCst);
+    with_connection("交易周期", |conn| {
+        conn.execute(
+            "INSERT INTO cycles (cycle, started_at, exchange, symbols, status)
+             VALUES (?1, ?2, ?3, ?4, 'running')",
+            params![cycle, started_at, exchange, symbols.join(",")],
+        )?;
+        CURRENT_CYCLE.store(conn.last_insert_rowid(), Ordering::SeqCst);
+        Ok(())
+    });
+}
+
+// error 为 None 表示周期正常完成
+pub fn finish_cycle(finished_at: i64, error: Option<&str>) {
+    let Some(id) = cycle_id() else {
+        return;
+    };
+    let status = if error.is_some() {
+        "failed"
+    } else {
+        "completed"
+    };
+    with_connection("交易周期", |conn| {
+        conn.execute(
+            "UPDATE cycles SET finished_at = ?1, status = ?2, error = ?3 WHERE id = ?4",
+            params![finished_at, status, error, id],
+        )?;
+        Ok(())
+    });
+    CURRENT_CYCLE.store(0, Ordering::SeqCst);
+}
+
+// ===== 智能体输出 =====
+
+// 记录智能体解析后的结构化输出；对应的 LLM 原文在 llm_calls 中按 cycle_id/role/symbol 关联
+pub fn record_agent_output<T: Serialize>(role: AgentRole, symbol: Option<&str>, output: &T) {
+    with_connection("智能体输出", |conn| {
+        conn.execute(
+            "INSERT INTO agent_outputs (cycle_id, role, symbol, output, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![cycle_id(), role.as_str(), symbol, to_json(output), now_ms()],
+        )?;
+        Ok(())
+    });
+}
+
+fn record_llm_call(
+    backend: &str,
+    request: &LlmRequest<'_>,
+    result: &Result<String>,
+    latency_ms: i64,
+) {
+    let (response, error) = match result {
+        Ok(response) => (Some(response.as_str()), None),
+        Err(e) => (None, Some(format!("{:#}", e))),
+    };
+    with_connection("LLM 调用", |conn| {
+        conn.execute(
+            "INSERT INTO llm_calls (cycle_id, role, symbol, backend, system_prompt, user_prompt,
+                                    response, error, latency_ms, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                cycle_id(),
+                request.role.as_str(),
+                request.symbol,
+                backend,
+                request.system_prompt,
+                request.user_prompt,
+                response,
+                error,
+                latency_ms,
+                now_ms()
+            ],
+        )?;
+        Ok(())
+    });
+}
+
+// 记录每次 LLM 调用的提示词与原始响应，再转发给内部后端
+pub struct JournalingBackend {
+    inner: Arc<dyn LlmBackend>,
+}
+
+impl JournalingBackend {
+    pub fn new(inner: Arc<dyn LlmBackend>) -> Self {
+        JournalingBackend { inner }
+    }
+}
+
+#[async_trait]
+impl LlmBackend for JournalingBackend {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn complete(&self, request: &LlmRequest<'_>) -> Result<String> {
+        let started = Instant::now();
+        let result = self.inner.complete(request).await;
+        record_llm_call(
+            self.inner.name(),
+            request,
+            &result,
+            started.elapsed().as_millis() as i64,
+        );
+        result
+    }
+}
+
+// ===== 决策与交易 =====
+
+pub fn record_decision(symbol: &str, decision: &TradingDecision, positions: &PositionBook) {
+    with_connection("决策", |conn| {
+        conn.execute(
+            "INSERT INTO decisions (cycle_id, symbol, signal, amount, confidence, reason,
+                                    decision, positions, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                cycle_id(),
+                symbol,
+                format!("{:?}", decision.signal),
+                decision.amount,
+                format!("{:?}", decision.confidence),
+                decision.reason,
+                to_json(decision),
+                to_json(positions),
+                now_ms()
+            ],
+        )?;
+        Ok(())
+    });
+}
+
+pub fn record_trade(trade: &TradeResult) {
+    with_connection("交易", |conn| {
+        conn.execute(
+            "INSERT INTO trades (cycle_id, symbol, action, price, amount, pnl, commission,
+                                 reason, order_details, time, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                cycle_id(),
+                trade.symbol,
+                format!("{:?}", trade.action),
+                trade.price,
+                trade.amount,
+                trade.pnl,
+                trade.commission,
+                trade.reason,
+                trade.order_details,
+                trade.timestamp,
+                now_ms()
+            ],
+        )?;
+        Ok(())
+    });
+}
+
+// 风控否决或缩减记录，approved 为风控放行的最终数量
+pub fn record_risk_event(symbol: &str, signal: &Signal, event: &RiskEvent, check: &RiskCheck) {
+    with_connection("风控记录", |conn| {
+        conn.execute(
+            "INSERT INTO risk_events (cycle_id, symbol, signal, rule, kind, requested, approved,
+                                      detail, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                cycle_id(),
+                symbol,
+                format!("{:?}", signal),
+                event.rule,
+                event.kind.as_str(),
+                check.requested,
+                check.amount,
+                event.detail,
+                now_ms()
+            ],
+        )?;
+        Ok(())
+    });
+}
+
+// ===== 订单与成交 =====
+
+// 记录下单请求及交易所回报；被拒绝的订单记录错误信息
+pub fn record_order(order: &OrderRequest, result: &Result<OrderState>) {
+    let (state, error) = match result {
+        Ok(state) => (Some(state), None),
+        Err(e) => (None, Some(format!("{:#}", e))),
+    };
+    let now = now_ms();
+    with_connection("订单", |conn| {
+        conn.execute(
+            "INSERT INTO orders (cycle_id, symbol, order_id, client_order_id, side, position_side,
+                                 order_type, quantity, price, stop_price, close_position, status,
+                                 executed_qty, avg_price, error, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?16)",
+            params![
+                cycle_id(),
+                order.symbol,
+                state.map(|s| s.order_id),
+                order.client_order_id,
+                order.side.as_str(),
+                format!("{:?}", order.position_side),
+                order.order_type.as_str(),
+                order.quantity,
+                order.price,
+                order.stop_price,
+                order.close_position,
+                state.map(|s| s.status.as_str()),
+                state.map(|s| s.executed_qty),
+                state.map(|s| s.avg_price),
+                error,
+                now
+            ],
+        )?;
+        Ok(())
+    });
+}
+
+// 订单跟踪结束后更新最终状态并写入成交明细
+pub fn record_order_result(symbol: &str, state: &OrderState, fills: &[Fill]) {
+    let now = now_ms();
+    with_connection("成交", |conn| {
+        conn.execute(
+            "UPDATE orders SET status = ?1, executed_qty = ?2, avg_price = ?3, updated_at = ?4
+             WHERE symbol = ?5 AND order_id = ?6",
+            params![
+                state.status.as_str(),
+                state.executed_qty,
+                state.avg_price,
+                now,
+                symbol,
+                state.order_id
+            ],
+        )?;
+        for fill in fills {
+            conn.execute(
+                "INSERT OR IGNORE INTO fills (cycle_id, symbol, order_id, trade_id, price, quantity,
+                                              commission, commission_asset, realized_pnl, maker, time)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    cycle_id(),
+                    symbol,
+                    state.order_id,
+                    fill.trade_id,
+                    fill.price,
+                    fill.quantity,
+                    fill.commission,
+                    fill.commission_asset,
+                    fill.realized_pnl,
+                    fill.maker,
+                    fill.time
+                ],
+            )?;
+        }
+        Ok(())
+    });
+}
+
+// ===== 账户快照 =====
+
+pub fn record_account(
+    account: &AccountInfo,
+    equity: f64,
+    positions: &HashMap<String, PositionBook>,
+) {
+    with_connection("账户快照", |conn| {
+        conn.execute(
+            "INSERT INTO account_snapshots (cycle_id, wallet_balance, available_balance, equity,
+                                            positions, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                cycle_id(),
+                account.totalWalletBalance.parse::<f64>().unwrap_or(0.0),
+                account.availableBalance.parse::<f64>().unwrap_or(0.0),
+                equity,
+                to_json(positions),
+                now_ms()
+            ],
+        )?;
+        Ok(())
+    });
+}
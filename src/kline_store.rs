@@ -0,0 +1,506 @@
+// 本地K线存储：按标的与周期持久化，支持导入 Binance 公共CSV/Parquet与增量补齐
+
+use crate::market;
+use crate::types::Kline;
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use log::{info, warn};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const DEFAULT_STORE_DIR: &str = "data/klines";
+const CSV_HEADER: &str =
+    "open_time,open,high,low,close,volume,taker_buy_volume,taker_buy_quote_volume";
+
+type Series = BTreeMap<i64, Kline>;
+type Ranges = Vec<(i64, i64)>; // 闭区间 [开始, 结束] 的开盘时间
+
+pub struct KlineStore {
+    root: PathBuf,
+    cache: Mutex<HashMap<(String, String), Series>>,
+    // 已向交易所确认无数据的区间（上市前、维护停机），补齐时跳过；仅保存在内存中
+    empty_ranges: Mutex<HashMap<(String, String), Ranges>>,
+}
+
+impl KlineStore {
+    pub fn new(root: &str) -> Self {
+        KlineStore {
+            root: PathBuf::from(root),
+            cache: Mutex::new(HashMap::new()),
+            empty_ranges: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn file_path(&self, symbol: &str, interval: &str) -> PathBuf {
+        self.root.join(symbol).join(format!("{}.csv", interval))
+    }
+
+    // 在已加载（必要时从磁盘读取）的序列上执行操作
+    fn with_series<R>(
+        &self,
+        symbol: &str,
+        interval: &str,
+        f: impl FnOnce(&mut Series) -> R,
+    ) -> Result<R> {
+        let mut cache = self.cache.lock().unwrap();
+        let key = (symbol.to_string(), interval.to_string());
+        if !cache.contains_key(&key) {
+            let series = read_series(&self.file_path(symbol, interval))?;
+            cache.insert(key.clone(), series);
+        }
+        Ok(f(cache.get_mut(&key).expect("series loaded")))
+    }
+
+    // 按开盘时间查询 [start_ms, end_ms] 区间内的K线
+    pub fn range(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start_ms: i64,
+        end_ms: i64,
+    ) -> Result<Vec<Kline>> {
+        self.with_series(symbol, interval, |series| {
+            series
+                .range(start_ms..=end_ms)
+                .map(|(_, k)| k.clone())
+                .collect()
+        })
+    }
+
+    // 合并写入，返回新增的K线数量
+    pub fn insert(&self, symbol: &str, interval: &str, klines: Vec<Kline>) -> Result<usize> {
+        let path = self.file_path(symbol, interval);
+        self.with_series(symbol, interval, |series| -> Result<usize> {
+            let previous_last = series.keys().next_back().copied();
+            let mut added = Vec::new();
+            for kline in klines {
+                if let Entry::Vacant(slot) = series.entry(kline.timestamp) {
+                    slot.insert(kline.clone());
+                    added.push(kline);
+                }
+            }
+            if added.is_empty() {
+                return Ok(0);
+            }
+
+            // 仅追加到末尾时直接追加写入，否则整体重写
+            let append_only = match previous_last {
+                Some(last) => added.iter().all(|k| k.timestamp > last),
+                None => false,
+            };
+            if append_only {
+                added.sort_by_key(|k| k.timestamp);
+                append_series(&path, &added)?;
+            } else {
+                write_series(&path, series)?;
+            }
+            Ok(added.len())
+        })?
+    }
+
+    // 导入 Binance 公共数据（data.binance.vision）解压后的CSV
+    pub fn import_csv(&self, symbol: &str, interval: &str, path: &Path) -> Result<usize> {
+        let file = File::open(path).with_context(|| format!("打开CSV失败: {}", path.display()))?;
+        let mut klines = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.context("读取CSV失败")?;
+            if let Some(kline) = parse_csv_line(&line) {
+                klines.push(kline);
+            }
+        }
+        self.insert(symbol, interval, klines)
+    }
+
+    // 导入 Parquet（列名 open_time/open/high/low/close/volume，可选 taker_buy_volume/taker_buy_quote_volume），需启用 parquet 特性
+    #[cfg(feature = "parquet")]
+    pub fn import_parquet(&self, symbol: &str, interval: &str, path: &Path) -> Result<usize> {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use parquet::record::Field;
+
+        fn as_f64(field: &Field) -> Option<f64> {
+            match field {
+                Field::Double(v) => Some(*v),
+                Field::Float(v) => Some(*v as f64),
+                Field::Long(v) => Some(*v as f64),
+                Field::Int(v) => Some(*v as f64),
+                Field::Str(v) => v.parse().ok(),
+                Field::TimestampMillis(v) => Some(*v as f64),
+                Field::TimestampMicros(v) => Some((*v / 1000) as f64),
+                _ => None,
+            }
+        }
+
+        let file =
+            File::open(path).with_context(|| format!("打开Parquet失败: {}", path.display()))?;
+        let reader = SerializedFileReader::new(file).context("解析Parquet失败")?;
+        let mut klines = Vec::new();
+        for row in reader.get_row_iter(None).context("读取Parquet失败")? {
+            let row = row.context("读取Parquet行失败")?;
+            let mut values: HashMap<&str, f64> = HashMap::new();
+            for (name, field) in row.get_column_iter() {
+                if let Some(value) = as_f64(field) {
+                    values.insert(name.as_str(), value);
+                }
+            }
+            let get = |key: &str| values.get(key).copied();
+            if let (Some(ts), Some(open), Some(high), Some(low), Some(close), Some(volume)) = (
+                get("open_time"),
+                get("open"),
+                get("high"),
+                get("low"),
+                get("close"),
+                get("volume"),
+            ) {
+                klines.push(Kline {
+                    timestamp: normalize_timestamp(ts as i64),
+                    open,
+                    high,
+                    low,
+                    close,
+                    volume,
+                    taker_buy_volume: get("taker_buy_volume").unwrap_or(0.0),
+                    taker_buy_quote_volume: get("taker_buy_quote_volume").unwrap_or(0.0),
+                });
+            }
+        }
+        self.insert(symbol, interval, klines)
+    }
+
+    // 补齐 [start_ms, end_ms] 区间内缺失的已收盘K线，返回新增数量
+    pub async fn sync(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start_ms: i64,
+        end_ms: i64,
+    ) -> Result<usize> {
+        let step = market::interval_to_millis(interval)?;
+        let last_closed = Utc::now().timestamp_millis() - step;
+        let start = start_ms - start_ms.rem_euclid(step);
+        let end = end_ms.min(last_closed);
+        if end < start {
+            return Ok(0);
+        }
+
+        let key = (symbol.to_string(), interval.to_string());
+        let checked = self
+            .empty_ranges
+            .lock()
+            .unwrap()
+            .get(&key)
+            .cloned()
+            .unwrap_or_default();
+        let gaps = self.with_series(symbol, interval, |series| {
+            find_gaps(series, &checked, start, end, step)
+        })?;
+        let mut added = 0;
+        for (from, to) in gaps {
+            let klines: Vec<Kline> = market::fetch_klines_range(symbol, interval, from, to)
+                .await?
+                .into_iter()
+                .filter(|k| k.timestamp <= last_closed)
+                .collect();
+            added += self.insert(symbol, interval, klines)?;
+
+            // 补齐后仍有缺口且其后已有K线时，缺口不是尚未发布的最新K线，记为无数据不再请求
+            let unfillable = self.with_series(symbol, interval, |series| {
+                series.range(to + 1..).next().is_some()
+                    && !find_gaps(series, &[], from, to, step).is_empty()
+            })?;
+            if unfillable {
+                info!(
+                    "{} {} 区间 {} ~ {} 交易所无完整数据，后续补齐时跳过",
+                    symbol, interval, from, to
+                );
+                let mut empty_ranges = self.empty_ranges.lock().unwrap();
+                let ranges = empty_ranges.entry(key.clone()).or_default();
+                ranges.push((from, to));
+                ranges.sort_unstable();
+            }
+        }
+        Ok(added)
+    }
+
+    // 实盘使用：补齐最近 limit 根已收盘K线，并附加当前未收盘K线（不落盘）
+    pub async fn recent(&self, symbol: &str, interval: &str, limit: u32) -> Result<Vec<Kline>> {
+        let step = market::interval_to_millis(interval)?;
+        let now = Utc::now().timestamp_millis();
+        let start = now - step * limit as i64;
+        self.sync(symbol, interval, start, now).await?;
+
+        let mut klines = self.range(symbol, interval, start, now)?;
+        let forming = market::fetch_klines(symbol, interval, 1).await?;
+        if let Some(current) = forming.into_iter().last() {
+            if klines
+                .last()
+                .is_none_or(|k| current.timestamp > k.timestamp)
+            {
+                klines.push(current);
+            }
+        }
+
+        let excess = klines.len().saturating_sub(limit as usize);
+        Ok(klines.split_off(excess))
+    }
+}
+
+// 找出区间内缺失的连续时间段，跳过已确认无数据的区间（按开盘时间升序、与周期对齐）
+fn find_gaps(
+    series: &Series,
+    checked: &[(i64, i64)],
+    start: i64,
+    end: i64,
+    step: i64,
+) -> Vec<(i64, i64)> {
+    let mut missing = Vec::new();
+    let mut expected = start;
+    for &ts in series.range(start..=end).map(|(ts, _)| ts) {
+        if ts > expected {
+            missing.push((expected, ts - step));
+        }
+        expected = ts + step;
+    }
+    if expected <= end {
+        missing.push((expected, end));
+    }
+
+    let mut gaps = Vec::new();
+    for (from, to) in missing {
+        let mut cursor = from;
+        for &(skip_from, skip_to) in checked {
+            if skip_to < cursor {
+                continue;
+            }
+            if skip_from > to {
+                break;
+            }
+            if skip_from > cursor {
+                gaps.push((cursor, skip_from - step));
+            }
+            cursor = skip_to + step;
+        }
+        if cursor <= to {
+            gaps.push((cursor, to));
+        }
+    }
+    gaps
+}
+
+// 兼容带表头与不带表头的CSV；新版现货数据的微秒时间戳换算为毫秒
+// 支持本地存储格式（6 或 8 列）与币安公共数据格式（12 列，主动买入量在第 10、11 列）
+fn parse_csv_line(line: &str) -> Option<Kline> {
+    let fields: Vec<&str> = line.trim().split(',').collect();
+    if fields.len() < 6 {
+        return None;
+    }
+    let taker_columns = match fields.len() {
+        n if n >= 11 => Some((9, 10)),
+        n if n >= 8 => Some((6, 7)),
+        _ => None,
+    };
+    let taker = |column: usize| -> f64 { fields[column].trim().parse().unwrap_or(0.0) };
+    let (taker_buy_volume, taker_buy_quote_volume) =
+        taker_columns.map_or((0.0, 0.0), |(volume, quote)| (taker(volume), taker(quote)));
+    let timestamp = normalize_timestamp(fields[0].trim().parse().ok()?);
+    Some(Kline {
+        timestamp,
+        open: fields[1].trim().parse().ok()?,
+        high: fields[2].trim().parse().ok()?,
+        low: fields[3].trim().parse().ok()?,
+        close: fields[4].trim().parse().ok()?,
+        volume: fields[5].trim().parse().ok()?,
+        taker_buy_volume,
+        taker_buy_quote_volume,
+    })
+}
+
+// 超过 1e14 的开盘时间视为微秒（新版现货数据），换算为毫秒
+fn normalize_timestamp(timestamp: i64) -> i64 {
+    if timestamp > 100_000_000_000_000 {
+        timestamp / 1000
+    } else {
+        timestamp
+    }
+}
+
+fn format_csv_line(k: &Kline) -> String {
+    format!(
+        "{},{},{},{},{},{},{},{}",
+        k.timestamp,
+        k.open,
+        k.high,
+        k.low,
+        k.close,
+        k.volume,
+        k.taker_buy_volume,
+        k.taker_buy_quote_volume
+    )
+}
+
+fn read_series(path: &Path) -> Result<Series> {
+    let mut series = Series::new();
+    if !path.exists() {
+        return Ok(series);
+    }
+    let file = File::open(path).with_context(|| format!("打开K线文件失败: {}", path.display()))?;
+    for line in BufReader::new(file).lines() {
+        let line = line.context("读取K线文件失败")?;
+        if let Some(kline) = parse_csv_line(&line) {
+            series.insert(kline.timestamp, kline);
+        }
+    }
+    Ok(series)
+}
+
+fn write_series(path: &Path, series: &Series) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).context("创建K线存储目录失败")?;
+    }
+    let tmp_path = path.with_extension("csv.tmp");
+    {
+        let mut file = File::create(&tmp_path).context("写入K线文件失败")?;
+        writeln!(file, "{}", CSV_HEADER)?;
+        for kline in series.values() {
+            writeln!(file, "{}", format_csv_line(kline))?;
+        }
+    }
+    fs::rename(&tmp_path, path).context("替换K线文件失败")?;
+    Ok(())
+}
+
+fn append_series(path: &Path, klines: &[Kline]) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(path)
+        .with_context(|| format!("追加K线文件失败: {}", path.display()))?;
+    for kline in klines {
+        writeln!(file, "{}", format_csv_line(kline))?;
+    }
+    Ok(())
+}
+
+// 命令行入口:
+//   klines import <SYMBOL> <INTERVAL> <FILE>...
+//   klines sync <SYMBOL> <INTERVAL> <START> [END]
+pub async fn run_cli(args: &[String]) -> Result<()> {
+    let dir = std::env::var("KLINE_STORE_DIR").unwrap_or_else(|_| DEFAULT_STORE_DIR.to_string());
+    let store = KlineStore::new(&dir);
+
+    match args {
+        [cmd, symbol, interval, files @ ..] if cmd == "import" && !files.is_empty() => {
+            for file in files {
+                let path = Path::new(file);
+                let added = match path.extension().and_then(|e| e.to_str()) {
+                    #[cfg(feature = "parquet")]
+                    Some("parquet") => store.import_parquet(symbol, interval, path)?,
+                    #[cfg(not(feature = "parquet"))]
+                    Some("parquet") => bail!("导入 Parquet 需要以 --features parquet 编译"),
+                    _ => store.import_csv(symbol, interval, path)?,
+                };
+                info!("{} {} 导入 {}: 新增 {} 根", symbol, interval, file, added);
+            }
+        }
+        [cmd, symbol, interval, start, rest @ ..] if cmd == "sync" && rest.len() <= 1 => {
+            let start_ms = market::parse_time(start)?;
+            let end_ms = match rest.first() {
+                Some(end) => market::parse_time(end)?,
+                None => Utc::now().timestamp_millis(),
+            };
+            let added = store.sync(symbol, interval, start_ms, end_ms).await?;
+            info!("{} {} 同步完成: 新增 {} 根", symbol, interval, added);
+        }
+        _ => {
+            warn!("用法: klines import <SYMBOL> <INTERVAL> <FILE>... | klines sync <SYMBOL> <INTERVAL> <START> [END]");
+            bail!("无效的 klines 子命令");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: i64 = 60_000;
+
+    fn series(timestamps: &[i64]) -> Series {
+        timestamps
+            .iter()
+            .map(|&ts| {
+                let line = format!("{},1,1,1,1,1", ts);
+                (ts, parse_csv_line(&line).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_csv_line_formats() {
+        // 表头与列数不足的行被跳过
+        assert!(parse_csv_line(CSV_HEADER).is_none());
+        assert!(parse_csv_line("1717200000000,1,2,3").is_none());
+
+        // 本地存储 8 列
+        let k = parse_csv_line("1717200000000,100,110,90,105,12.5,7.5,780\n").unwrap();
+        assert_eq!(k.timestamp, 1_717_200_000_000);
+        assert_eq!(
+            (k.open, k.high, k.low, k.close),
+            (100.0, 110.0, 90.0, 105.0)
+        );
+        assert_eq!(k.volume, 12.5);
+        assert_eq!((k.taker_buy_volume, k.taker_buy_quote_volume), (7.5, 780.0));
+
+        // 旧版本地存储 6 列，主动买入量缺失记为 0
+        let k = parse_csv_line("1717200000000,100,110,90,105,12.5").unwrap();
+        assert_eq!((k.taker_buy_volume, k.taker_buy_quote_volume), (0.0, 0.0));
+
+        // 币安公共数据 12 列，新版现货数据的微秒时间戳换算为毫秒
+        let k = parse_csv_line(
+            "1717200000000000,100,110,90,105,12.5,1717200059999999,1300,42,7.5,780,0",
+        )
+        .unwrap();
+        assert_eq!(k.timestamp, 1_717_200_000_000);
+        assert_eq!((k.taker_buy_volume, k.taker_buy_quote_volume), (7.5, 780.0));
+    }
+
+    #[test]
+    fn find_gaps_reports_missing_runs() {
+        assert_eq!(
+            find_gaps(&Series::new(), &[], 0, 4 * MINUTE, MINUTE),
+            vec![(0, 4 * MINUTE)]
+        );
+
+        // 开头、中间与结尾的缺口，区间外的K线不影响结果
+        let stored = series(&[-MINUTE, MINUTE, 2 * MINUTE, 5 * MINUTE, 9 * MINUTE]);
+        assert_eq!(
+            find_gaps(&stored, &[], 0, 7 * MINUTE, MINUTE),
+            vec![(0, 0), (3 * MINUTE, 4 * MINUTE), (6 * MINUTE, 7 * MINUTE)]
+        );
+
+        let full = series(&[0, MINUTE, 2 * MINUTE]);
+        assert!(find_gaps(&full, &[], 0, 2 * MINUTE, MINUTE).is_empty());
+    }
+
+    #[test]
+    fn find_gaps_skips_confirmed_empty_ranges() {
+        // 上市前 [0, 3] 与维护停机 [6, 7] 已确认无数据
+        let stored = series(&[4 * MINUTE, 5 * MINUTE, 8 * MINUTE]);
+        let checked = [(0, 3 * MINUTE), (6 * MINUTE, 7 * MINUTE)];
+        assert!(find_gaps(&stored, &checked, 0, 8 * MINUTE, MINUTE).is_empty());
+
+        // 已确认区间只覆盖缺口的一部分时，其余部分仍需补齐
+        assert_eq!(
+            find_gaps(&stored, &checked, 0, 10 * MINUTE, MINUTE),
+            vec![(9 * MINUTE, 10 * MINUTE)]
+        );
+        let partial = [(MINUTE, 2 * MINUTE)];
+        assert_eq!(
+            find_gaps(&stored, &partial, 0, 5 * MINUTE, MINUTE),
+            vec![(0, 0), (3 * MINUTE, 3 * MINUTE)]
+        );
+    }
+}
@@ -1,6 +1,6 @@
-use crate::executor::AccountInfo;
-use crate::types::{Kline, Position, TechnicalIndicators, TradingDecision};
-use anyhow::{Context, Result};
+// LLM 调用层：统一后端接口，支持多服务商按角色路由与离线脚本化实现
+
+use anyhow::{anyhow, bail, Context, Result};
 use async_openai::{
     config::OpenAIConfig,
     types::{
@@ -9,206 +9,945 @@ use async_openai::{
     },
     Client,
 };
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-// System Prompt - 激进交易策略
-fn get_system_prompt() -> &'static str {
-    r#"你是激进的加密货币交易员，专注于捕捉市场机会：
-
-## 核心哲学
-1. **机会稍纵即逝** - 趋势启动时必须果断进场，犹豫就会错过
-2. **动量为王** - 价格动量是最强信号，顺势而为才能获利
-3. **主动交易** - 市场是用来交易的，不是用来观望的
-4. **敢于试错** - 小亏损可以接受，错过大行情才是真正的损失
-
-## 分析框架
-**趋势识别**: 快速判断多空方向，优先跟随主趋势
-**动量捕捉**: 价格加速、均线发散是最强入场信号
-**量能确认**: 放量突破果断跟进，缩量整理准备入场
-**快进快出**: 持仓不追求完美，有利润就是好交易
-
-## 输出要求
-严格返回JSON格式，包含：
-- signal: "BUY"(看多开多/平空) | "SELL"(看空开空/平多) | "HOLD"(仅在极度矛盾时使用)
-- reason: 核心分析逻辑（50字内，强调动量和趋势）
-- confidence: "HIGH"(明确信号) | "MEDIUM"(可交易信号) | "LOW"(信号较弱但可尝试)
-
-交易原则：宁愿多做错，不要错过。HOLD仅在信号完全矛盾时使用。
-
-禁止输出任何JSON之外的内容。"#
-}
-
-// Task 4.1: 构建提示词
-fn build_prompt(
-    klines: &[Kline],
-    indicators: &TechnicalIndicators,
-    position: &Option<Position>,
-    account: &AccountInfo,
-    min_amount: f64,
-    max_amount: f64,
-) -> String {
-    // 最近5根K线（从旧到新）
-    let recent_klines: Vec<&Kline> = klines.iter().rev().take(5).rev().collect();
-
-    // 判断K线形态
-    let last_kline = recent_klines.last().unwrap();
-    let is_bullish = last_kline.close > last_kline.open;
-    let body_size = (last_kline.close - last_kline.open).abs();
-    let range = last_kline.high - last_kline.low;
-    let body_ratio = if range > 0.0 { body_size / range } else { 0.0 };
-
-    let kline_summary = format!(
-        "K线形态: {} (实体占比{:.1}%), 收盘价{:.2}",
-        if is_bullish { "阳线" } else { "阴线" },
-        body_ratio * 100.0,
-        last_kline.close
-    );
-
-    // 均线状态
-    let ma_trend = if indicators.sma_5 > indicators.sma_20 {
-        "多头排列(短期强势)"
-    } else {
-        "空头排列(短期弱势)"
-    };
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentRole {
+    MarketAnalyst,
+    StrategyResearcher,
+    RiskManager,
+    TradeExecutor,
+    PortfolioCoordinator,
+}
 
-    let price_vs_ma5 = ((last_kline.close - indicators.sma_5) / indicators.sma_5) * 100.0;
-    let price_vs_ma20 = ((last_kline.close - indicators.sma_20) / indicators.sma_20) * 100.0;
+impl AgentRole {
+    pub const ALL: [AgentRole; 5] = [
+        AgentRole::MarketAnalyst,
+        AgentRole::StrategyResearcher,
+        AgentRole::RiskManager,
+        AgentRole::TradeExecutor,
+        AgentRole::PortfolioCoordinator,
+    ];
 
-    // 动量与成交量
-    let momentum_status = if indicators.price_change_1 > 0.5 {
-        "短期上涨加速"
-    } else if indicators.price_change_1 < -0.5 {
-        "短期下跌加速"
-    } else {
-        "震荡整理"
-    };
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AgentRole::MarketAnalyst => "market_analyst",
+            AgentRole::StrategyResearcher => "strategy_researcher",
+            AgentRole::RiskManager => "risk_manager",
+            AgentRole::TradeExecutor => "trade_executor",
+            AgentRole::PortfolioCoordinator => "portfolio_coordinator",
+        }
+    }
+}
 
-    let volume_status = if indicators.volume_ratio > 1.5 {
-        "放量(信号可信度高)"
-    } else if indicators.volume_ratio < 0.7 {
-        "缩量(观望情绪)"
-    } else {
-        "正常量能"
+pub struct LlmRequest<'a> {
+    pub role: AgentRole,
+    pub symbol: Option<&'a str>,
+    pub system_prompt: &'a str,
+    pub user_prompt: &'a str,
+}
+
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    fn name(&self) -> &str;
+    async fn complete(&self, request: &LlmRequest<'_>) -> Result<String>;
+}
+
+// 根据 LLM_BACKEND 构建后端: api | scripted | record | replay
+pub fn backend_from_env() -> Result<Arc<dyn LlmBackend>> {
+    let kind = env::var("LLM_BACKEND").unwrap_or_else(|_| "api".to_string());
+    let fixtures = env::var("LLM_FIXTURES").ok();
+
+    let backend: Arc<dyn LlmBackend> = match kind.to_lowercase().as_str() {
+        // deepseek 为旧配置值，等同于 api
+        "api" | "deepseek" => Arc::new(RoutedBackend::from_env()?),
+        "scripted" => Arc::new(match fixtures {
+            Some(path) => ScriptedBackend::fixed(Path::new(&path))?,
+            None => ScriptedBackend::rules(),
+        }),
+        "record" => Arc::new(RecordingBackend::new(
+            Arc::new(RoutedBackend::from_env()?),
+            fixtures.as_deref().unwrap_or(DEFAULT_RECORD_PATH),
+        )),
+        "replay" => Arc::new(ScriptedBackend::replay(Path::new(
+            fixtures.as_deref().unwrap_or(DEFAULT_RECORD_PATH),
+        ))?),
+        other => bail!("未知的 LLM_BACKEND: {}", other),
     };
+    Ok(backend)
+}
 
-    // 持仓状态
-    let position_risk = match position {
-        None => "空仓，可开仓".to_string(),
-        Some(pos) => {
-            let risk_desc = if pos.unrealized_pnl > 0.0 {
-                "盈利中"
-            } else if pos.unrealized_pnl < -10.0 {
-                "亏损较大，考虑止损"
-            } else {
-                "微亏"
-            };
-            format!(
-                "{:?}仓持仓{:.4}，开仓价{:.2}，盈亏{:.2}U ({})",
-                pos.side, pos.amount, pos.entry_price, pos.unrealized_pnl, risk_desc
-            )
+// ===== 模型服务商 =====
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Provider {
+    DeepSeek,
+    OpenAi, // OpenAI 及任意兼容接口 (vLLM、OpenRouter 等)
+    Ollama,
+    LlamaCpp,
+    Anthropic,
+}
+
+impl Provider {
+    fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "deepseek" => Ok(Provider::DeepSeek),
+            "openai" | "openai-compatible" => Ok(Provider::OpenAi),
+            "ollama" => Ok(Provider::Ollama),
+            "llamacpp" | "llama.cpp" => Ok(Provider::LlamaCpp),
+            "anthropic" => Ok(Provider::Anthropic),
+            other => bail!("未知的 LLM 服务商: {}", other),
         }
-    };
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Provider::DeepSeek => "deepseek",
+            Provider::OpenAi => "openai",
+            Provider::Ollama => "ollama",
+            Provider::LlamaCpp => "llamacpp",
+            Provider::Anthropic => "anthropic",
+        }
+    }
+
+    fn default_base_url(&self) -> &'static str {
+        match self {
+            Provider::DeepSeek => "https://api.deepseek.com",
+            Provider::OpenAi => "https://api.openai.com/v1",
+            Provider::Ollama => "http://localhost:11434/v1",
+            Provider::LlamaCpp => "http://localhost:8080/v1",
+            Provider::Anthropic => "https://api.anthropic.com",
+        }
+    }
+
+    // llama.cpp 只加载单个模型，忽略请求中的模型名
+    fn default_model(&self) -> Option<&'static str> {
+        match self {
+            Provider::DeepSeek => Some("deepseek-chat"),
+            Provider::LlamaCpp => Some("local"),
+            _ => None,
+        }
+    }
+
+    fn api_key_env(&self) -> Option<&'static str> {
+        match self {
+            Provider::DeepSeek => Some("DEEPSEEK_API_KEY"),
+            Provider::OpenAi => Some("OPENAI_API_KEY"),
+            Provider::Anthropic => Some("ANTHROPIC_API_KEY"),
+            Provider::Ollama | Provider::LlamaCpp => None,
+        }
+    }
+
+    // 官方托管接口必须提供密钥，自建兼容接口可省略
+    fn requires_key(&self, base_url: &str) -> bool {
+        match self {
+            Provider::DeepSeek | Provider::Anthropic => true,
+            Provider::OpenAi => base_url == self.default_base_url(),
+            Provider::Ollama | Provider::LlamaCpp => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ModelSettings {
+    provider: Provider,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+}
+
+impl ModelSettings {
+    // 全局读取 LLM_*，单个角色读取 LLM_<ROLE>_* 并继承全局配置；
+    // 角色单独指定服务商时，地址、密钥与模型改用该服务商的默认值
+    fn from_env(role: Option<AgentRole>, global: Option<&ModelSettings>) -> Result<Self> {
+        let key = |field: &str| match role {
+            Some(role) => format!("LLM_{}_{}", role.as_str().to_uppercase(), field),
+            None => format!("LLM_{}", field),
+        };
+        let var = |field: &str| env::var(key(field)).ok().filter(|v| !v.trim().is_empty());
+
+        let provider_override = var("PROVIDER").map(|p| Provider::parse(&p)).transpose()?;
+        let inherited = match provider_override {
+            Some(_) => None,
+            None => global,
+        };
+        let provider = provider_override
+            .or(inherited.map(|g| g.provider))
+            .unwrap_or(Provider::DeepSeek);
+
+        let base_url = var("BASE_URL")
+            .or_else(|| inherited.map(|g| g.base_url.clone()))
+            .unwrap_or_else(|| provider.default_base_url().to_string());
+        let api_key = var("API_KEY")
+            .or_else(|| inherited.and_then(|g| g.api_key.clone()))
+            .or_else(|| provider.api_key_env().and_then(|k| env::var(k).ok()));
+        if api_key.is_none() && provider.requires_key(&base_url) {
+            bail!(
+                "{} 缺少 API Key，请设置 {} 或 {}",
+                provider.as_str(),
+                key("API_KEY"),
+                provider.api_key_env().unwrap_or("")
+            );
+        }
+        let model = var("MODEL")
+            .or_else(|| inherited.map(|g| g.model.clone()))
+            .or_else(|| provider.default_model().map(str::to_string))
+            .with_context(|| {
+                format!("{} 未配置模型，请设置 {}", provider.as_str(), key("MODEL"))
+            })?;
+
+        let temperature = match var("TEMPERATURE") {
+            Some(v) => Some(
+                v.parse()
+                    .with_context(|| format!("{} 格式错误", key("TEMPERATURE")))?,
+            ),
+            None => global.and_then(|g| g.temperature),
+        };
+        let max_tokens = match var("MAX_TOKENS") {
+            Some(v) => Some(
+                v.parse()
+                    .with_context(|| format!("{} 格式错误", key("MAX_TOKENS")))?,
+            ),
+            None => global.and_then(|g| g.max_tokens),
+        };
+
+        Ok(ModelSettings {
+            provider,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+            temperature,
+            max_tokens,
+        })
+    }
+
+    fn label(&self) -> String {
+        format!("{}:{}", self.provider.as_str(), self.model)
+    }
+}
+
+// ===== 单个模型的聊天接口 =====
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u32 = 2048; // Anthropic 接口要求必填
 
-    format!(
-        r#"## 市场数据分析
-
-**K线**: {}
-**均线**: {} | 价格相对MA5: {:+.2}%, 相对MA20: {:+.2}%
-**动量**: {} | 1周期{:+.2}%, 3周期{:+.2}%
-**成交量**: {}
-**持仓**: {}
-**账户**: 可用余额 {} USDT
-**交易限制**: 单次最小 {:.4}, 最大 {:.4}
-
-## 任务
-基于以上数据，以激进交易员的视角给出决策：
-- 有动量信号（价格变化>±0.3%或均线发散）→ 立即BUY/SELL
-- 多头排列且非大幅回调 → 偏向BUY
-- 空头排列且非大幅反弹 → 偏向SELL
-- **支持加仓**: 已持有多仓时可继续BUY加多，已持有空仓时可继续SELL加空
-- 趋势强劲时果断加仓，弱势时观望或反向操作
-- 账户余额充足时更应主动交易，余额不足时谨慎观望
-- 仅在信号完全矛盾或余额不足时 → HOLD
-
-**交易数量决策**:
-- 你需要根据信号强度和账户余额决定交易数量(amount)
-- 信号强(HIGH) → 用接近最大值
-- 信号中(MEDIUM) → 用中等仓位
-- 信号弱(LOW) → 用最小值或接近最小值
-- 必须在 [{:.4}, {:.4}] 范围内
-
-记住：宁愿多交易，不要错过机会。小波动也可以是入场信号。支持加仓放大收益。
-
-严格返回JSON:
-{{"signal": "BUY"|"SELL"|"HOLD", "amount": 0.001, "reason": "核心逻辑", "confidence": "HIGH"|"MEDIUM"|"LOW"}}"#,
-        kline_summary,
-        ma_trend,
-        price_vs_ma5,
-        price_vs_ma20,
-        momentum_status,
-        indicators.price_change_1,
-        indicators.price_change_3,
-        volume_status,
-        position_risk,
-        account.availableBalance,
-        min_amount,
-        max_amount,
-        min_amount,
-        max_amount
-    )
-}
-
-// Task 4.2: 调用DeepSeek并解析决策
-pub async fn analyze(
-    klines: &[Kline],
-    indicators: &TechnicalIndicators,
-    position: &Option<Position>,
-    account: &AccountInfo,
-    min_amount: f64,
-    max_amount: f64,
-    api_key: &str,
-) -> Result<TradingDecision> {
-    let prompt = build_prompt(
-        klines, indicators, position, account, min_amount, max_amount,
-    );
-
-    let config = OpenAIConfig::new()
-        .with_api_key(api_key)
-        .with_api_base("https://api.deepseek.com");
-
-    let client = Client::with_config(config);
-
-    let request = CreateChatCompletionRequestArgs::default()
-        .model("deepseek-chat")
-        .messages(vec![
+#[derive(Deserialize)]
+struct AnthropicResponse {
+    content: Vec<AnthropicContent>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicContent {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+pub struct ChatBackend {
+    settings: ModelSettings,
+    label: String,
+    openai: Client<OpenAIConfig>,
+    http: reqwest::Client,
+}
+
+impl ChatBackend {
+    fn new(settings: ModelSettings) -> Self {
+        let config = OpenAIConfig::new()
+            .with_api_key(settings.api_key.clone().unwrap_or_default())
+            .with_api_base(&settings.base_url);
+        ChatBackend {
+            label: settings.label(),
+            openai: Client::with_config(config),
+            http: reqwest::Client::new(),
+            settings,
+        }
+    }
+
+    #[allow(deprecated)] // 兼容接口普遍只认 max_tokens
+    async fn complete_openai(&self, request: &LlmRequest<'_>) -> Result<String> {
+        let mut args = CreateChatCompletionRequestArgs::default();
+        args.model(&self.settings.model).messages(vec![
             ChatCompletionRequestMessage::System(
                 ChatCompletionRequestSystemMessageArgs::default()
-                    .content(get_system_prompt())
+                    .content(request.system_prompt)
                     .build()?,
             ),
             ChatCompletionRequestMessage::User(
                 ChatCompletionRequestUserMessageArgs::default()
-                    .content(prompt)
+                    .content(request.user_prompt)
                     .build()?,
             ),
-        ])
-        .build()?;
-
-    let response = client
-        .chat()
-        .create(request)
-        .await
-        .context("DeepSeek API 调用失败")?;
-
-    let content = response
-        .choices
-        .first()
-        .and_then(|c| c.message.content.as_ref())
-        .context("DeepSeek 返回为空")?;
-
-    // 提取JSON（处理markdown代码块）
-    let json_start = content.find('{').context("未找到JSON起始")?;
-    let json_end = content.rfind('}').context("未找到JSON结束")? + 1;
-    let json_str = &content[json_start..json_end];
-
-    serde_json::from_str(json_str).context("解析决策JSON失败")
+        ]);
+        if let Some(temperature) = self.settings.temperature {
+            args.temperature(temperature);
+        }
+        if let Some(max_tokens) = self.settings.max_tokens {
+            match self.settings.provider {
+                Provider::OpenAi => args.max_completion_tokens(max_tokens),
+                _ => args.max_tokens(max_tokens),
+            };
+        }
+
+        let response = self
+            .openai
+            .chat()
+            .create(args.build()?)
+            .await
+            .with_context(|| format!("{} API 调用失败", self.label))?;
+
+        let content = response
+            .choices
+            .first()
+            .and_then(|c| c.message.content.as_ref())
+            .with_context(|| format!("{} 返回为空", self.label))?;
+
+        Ok(content.clone())
+    }
+
+    async fn complete_anthropic(&self, request: &LlmRequest<'_>) -> Result<String> {
+        let mut body = json!({
+            "model": self.settings.model,
+            "max_tokens": self.settings.max_tokens.unwrap_or(ANTHROPIC_DEFAULT_MAX_TOKENS),
+            "system": request.system_prompt,
+            "messages": [{"role": "user", "content": request.user_prompt}],
+        });
+        if let Some(temperature) = self.settings.temperature {
+            body["temperature"] = json!(temperature);
+        }
+
+        let response = self
+            .http
+            .post(format!("{}/v1/messages", self.settings.base_url))
+            .header("x-api-key", self.settings.api_key.as_deref().unwrap_or(""))
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&body)
+            .send()
+            .await
+            .with_context(|| format!("{} API 调用失败", self.label))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            bail!("{} API 返回错误 {}: {}", self.label, status, text);
+        }
+
+        let parsed: AnthropicResponse = response
+            .json()
+            .await
+            .with_context(|| format!("{} 响应解析失败", self.label))?;
+        let content: String = parsed
+            .content
+            .into_iter()
+            .filter(|c| c.kind == "text")
+            .map(|c| c.text)
+            .collect();
+        if content.is_empty() {
+            bail!("{} 返回为空", self.label);
+        }
+        Ok(content)
+    }
+}
+
+#[async_trait]
+impl LlmBackend for ChatBackend {
+    fn name(&self) -> &str {
+        &self.label
+    }
+
+    async fn complete(&self, request: &LlmRequest<'_>) -> Result<String> {
+        match self.settings.provider {
+            Provider::Anthropic => self.complete_anthropic(request).await,
+            _ => self.complete_openai(request).await,
+        }
+    }
+}
+
+// ===== 按角色路由：未单独配置的角色使用全局模型 =====
+pub struct RoutedBackend {
+    default: Arc<dyn LlmBackend>,
+    roles: HashMap<AgentRole, Arc<dyn LlmBackend>>,
+    label: String,
+}
+
+impl RoutedBackend {
+    pub fn from_env() -> Result<Self> {
+        let global = ModelSettings::from_env(None, None).context("全局 LLM 配置错误")?;
+        let mut label = global.label();
+        let mut roles: HashMap<AgentRole, Arc<dyn LlmBackend>> = HashMap::new();
+
+        for role in AgentRole::ALL {
+            let settings = ModelSettings::from_env(Some(role), Some(&global))
+                .with_context(|| format!("{} LLM 配置错误", role.as_str()))?;
+            if settings != global {
+                label.push_str(&format!(", {}={}", role.as_str(), settings.label()));
+                roles.insert(role, Arc::new(ChatBackend::new(settings)));
+            }
+        }
+
+        Ok(RoutedBackend {
+            default: Arc::new(ChatBackend::new(global)),
+            roles,
+            label,
+        })
+    }
+}
+
+#[async_trait]
+impl LlmBackend for RoutedBackend {
+    fn name(&self) -> &str {
+        &self.label
+    }
+
+    async fn complete(&self, request: &LlmRequest<'_>) -> Result<String> {
+        self.roles
+            .get(&request.role)
+            .unwrap_or(&self.default)
+            .complete(request)
+            .await
+    }
+}
+
+// ===== 录制：透传真实后端并写入夹具文件，供 replay 回放 =====
+const DEFAULT_RECORD_PATH: &str = "logs/llm_fixtures.jsonl";
+
+#[derive(Serialize, Deserialize)]
+struct RecordedCall {
+    role: AgentRole,
+    symbol: Option<String>,
+    user_prompt: String,
+    response: String,
+}
+
+pub struct RecordingBackend {
+    inner: Arc<dyn LlmBackend>,
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl RecordingBackend {
+    pub fn new(inner: Arc<dyn LlmBackend>, path: &str) -> Self {
+        RecordingBackend {
+            inner,
+            path: PathBuf::from(path),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmBackend for RecordingBackend {
+    fn name(&self) -> &str {
+        "record"
+    }
+
+    async fn complete(&self, request: &LlmRequest<'_>) -> Result<String> {
+        let response = self.inner.complete(request).await?;
+
+        let record = RecordedCall {
+            role: request.role,
+            symbol: request.symbol.map(str::to_string),
+            user_prompt: request.user_prompt.to_string(),
+            response: response.clone(),
+        };
+        let line = serde_json::to_string(&record).context("序列化LLM记录失败")?;
+
+        let _guard = self.lock.lock().unwrap();
+        if let Some(parent) = self.path.parent() {
+            create_dir_all(parent).context("创建LLM夹具目录失败")?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("打开LLM夹具文件失败")?;
+        writeln!(file, "{}", line).context("写入LLM夹具失败")?;
+
+        Ok(response)
+    }
+}
+
+// ===== 脚本化后端：规则生成 / 固定响应 / 录制回放 =====
+// 按 (角色, 标的) 排队的录制响应
+type ReplayQueues = HashMap<(AgentRole, Option<String>), VecDeque<String>>;
+
+enum ScriptMode {
+    Rules,
+    Fixed(HashMap<AgentRole, String>),
+    Replay(Mutex<ReplayQueues>),
+}
+
+pub struct ScriptedBackend {
+    mode: ScriptMode,
+}
+
+impl ScriptedBackend {
+    // 基于输入数据的确定性规则，输出合法的各角色 JSON
+    pub fn rules() -> Self {
+        ScriptedBackend {
+            mode: ScriptMode::Rules,
+        }
+    }
+
+    // 固定响应：JSON 对象，键为角色名，值为响应 JSON；未配置的角色回落到规则
+    pub fn fixed(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("读取LLM夹具失败: {}", path.display()))?;
+        let raw: HashMap<AgentRole, Value> =
+            serde_json::from_str(&text).context("解析LLM夹具失败")?;
+        let responses = raw
+            .into_iter()
+            .map(|(role, value)| {
+                let text = match value {
+                    Value::String(s) => s,
+                    other => other.to_string(),
+                };
+                (role, text)
+            })
+            .collect();
+        Ok(ScriptedBackend {
+            mode: ScriptMode::Fixed(responses),
+        })
+    }
+
+    // 按录制顺序回放，每个 (角色, 标的) 独立排队
+    pub fn replay(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("打开LLM录制文件失败: {}", path.display()))?;
+        let mut queues = ReplayQueues::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.context("读取LLM录制文件失败")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let call: RecordedCall = serde_json::from_str(&line).context("解析LLM录制失败")?;
+            queues
+                .entry((call.role, call.symbol))
+                .or_default()
+                .push_back(call.response);
+        }
+        Ok(ScriptedBackend {
+            mode: ScriptMode::Replay(Mutex::new(queues)),
+        })
+    }
+}
+
+#[async_trait]
+impl LlmBackend for ScriptedBackend {
+    fn name(&self) -> &str {
+        match self.mode {
+            ScriptMode::Rules => "scripted-rules",
+            ScriptMode::Fixed(_) => "scripted-fixed",
+            ScriptMode::Replay(_) => "scripted-replay",
+        }
+    }
+
+    async fn complete(&self, request: &LlmRequest<'_>) -> Result<String> {
+        match &self.mode {
+            ScriptMode::Rules => rule_response(request),
+            ScriptMode::Fixed(responses) => match responses.get(&request.role) {
+                Some(text) => Ok(text.clone()),
+                None => rule_response(request),
+            },
+            ScriptMode::Replay(queues) => {
+                let key = (request.role, request.symbol.map(str::to_string));
+                queues
+                    .lock()
+                    .unwrap()
+                    .get_mut(&key)
+                    .and_then(|queue| queue.pop_front())
+                    .ok_or_else(|| anyhow!("LLM录制已耗尽: {:?} {:?}", key.0, key.1))
+            }
+        }
+    }
+}
+
+// 从 structured_prompt 格式的用户提示词中取出数据 JSON
+fn extract_payload(user_prompt: &str) -> Result<Value> {
+    let start = user_prompt.find("数据:\n").context("提示词缺少数据段")? + "数据:\n".len();
+    let end = user_prompt[start..]
+        .find("\n\n输出格式:")
+        .map(|offset| start + offset)
+        .unwrap_or(user_prompt.len());
+    serde_json::from_str(&user_prompt[start..end]).context("解析提示词数据失败")
+}
+
+fn num(value: &Value, pointer: &str) -> f64 {
+    value
+        .pointer(pointer)
+        .and_then(Value::as_f64)
+        .unwrap_or(0.0)
+}
+
+fn text<'a>(value: &'a Value, pointer: &str) -> &'a str {
+    value.pointer(pointer).and_then(Value::as_str).unwrap_or("")
+}
+
+fn rule_response(request: &LlmRequest<'_>) -> Result<String> {
+    let data = extract_payload(request.user_prompt)?;
+    let response = match request.role {
+        AgentRole::MarketAnalyst => rule_market_analyst(&data),
+        AgentRole::StrategyResearcher => rule_strategy_researcher(&data),
+        AgentRole::RiskManager => rule_risk_manager(&data),
+        AgentRole::TradeExecutor => rule_trade_executor(&data),
+        AgentRole::PortfolioCoordinator => rule_portfolio_coordinator(&data),
+    };
+    Ok(response.to_string())
+}
+
+// 均线与短期动量决定趋势，偏离幅度决定强度
+fn rule_market_analyst(data: &Value) -> Value {
+    let sma_5 = num(data, "/indicators/sma_5");
+    let sma_20 = num(data, "/indicators/sma_20");
+    let change_3 = num(data, "/indicators/price_change_pct_3");
+    let close = num(data, "/latest/close");
+    let high = num(data, "/long_window/high_max");
+    let low = num(data, "/long_window/low_min");
+
+    let spread = if sma_20.abs() > f64::EPSILON {
+        (sma_5 - sma_20) / sma_20 * 100.0
+    } else {
+        0.0
+    };
+    let trend = if spread > 0.05 && change_3 > 0.0 {
+        "bullish"
+    } else if spread < -0.05 && change_3 < 0.0 {
+        "bearish"
+    } else {
+        "neutral"
+    };
+    let strength = match spread.abs() {
+        s if s > 0.5 => "strong",
+        s if s > 0.15 => "medium",
+        _ => "weak",
+    };
+    let phase = match trend {
+        "bullish" => "markup",
+        "bearish" => "markdown",
+        _ if close < (high + low) / 2.0 => "accumulation",
+        _ => "distribution",
+    };
+
+    json!({
+        "trend": trend,
+        "strength": strength,
+        "market_phase": phase,
+        "support": low,
+        "resistance": high,
+        "analysis": format!("规则: MA5偏离MA20 {:+.2}%, 3周期 {:+.2}%", spread, change_3),
+    })
+}
+
+fn rule_strategy_researcher(data: &Value) -> Value {
+    let trend = text(data, "/market_report/trend");
+    let strength = text(data, "/market_report/strength");
+    let long = data
+        .pointer("/positions/long")
+        .is_some_and(|v| !v.is_null());
+    let short = data
+        .pointer("/positions/short")
+        .is_some_and(|v| !v.is_null());
+    // 多空同时持有时，把逆势的一腿视为当前持仓，促使其被平掉
+    let side = match (long, short) {
+        (true, false) => Some("Long"),
+        (false, true) => Some("Short"),
+        (true, true) if trend == "bullish" => Some("Short"),
+        (true, true) if trend == "bearish" => Some("Long"),
+        (true, true) => Some("Long"),
+        (false, false) => None,
+    };
+
+    let (action, target_side) = match (side, trend) {
+        (None, "bullish") if strength != "weak" => ("open_long", json!("Long")),
+        (None, "bearish") if strength != "weak" => ("open_short", json!("Short")),
+        (Some("Long"), "bullish") if strength == "strong" => ("add_position", json!("Long")),
+        (Some("Short"), "bearish") if strength == "strong" => ("add_position", json!("Short")),
+        // 逆势持仓：强趋势反手，中等趋势平仓，弱趋势减仓
+        (Some("Long"), "bearish") if strength == "strong" => ("reverse", json!("Short")),
+        (Some("Short"), "bullish") if strength == "strong" => ("reverse", json!("Long")),
+        (Some("Long"), "bearish") | (Some("Short"), "bullish") if strength == "medium" => {
+            ("close_position", Value::Null)
+        }
+        (Some("Long"), "bearish") | (Some("Short"), "bullish") => ("reduce_position", Value::Null),
+        (Some(s), _) => ("hold", json!(s)),
+        _ => ("hold", Value::Null),
+    };
+    let timing_score = match strength {
+        "strong" => 8,
+        "medium" => 6,
+        _ => 4,
+    };
+
+    json!({
+        "action": action,
+        "reasoning": format!("规则: 趋势 {} ({})", trend, strength),
+        "timing_score": timing_score,
+        "target_side": target_side,
+        "target_position_pct": 0.3,
+        "stop_loss_pct": -0.02,
+        "take_profit_pct": 0.04,
+    })
+}
+
+fn rule_risk_manager(data: &Value) -> Value {
+    let action = text(data, "/strategy/action");
+    let timing = num(data, "/strategy/timing_score");
+    let min_qty = num(data, "/limits/min_qty");
+    let allocated_max = num(data, "/limits/allocated_max_amount");
+    let suggested = (allocated_max * 0.5).max(min_qty);
+
+    let (approval, risk_level, reason) = if action == "hold" {
+        ("rejected", "low", "策略建议观望")
+    } else if action == "close_position" || action == "reduce_position" {
+        ("approved", "low", "平仓降低敞口")
+    } else if timing < 5.0 {
+        ("rejected", "high", "时机评分不足")
+    } else {
+        ("approved", "medium", "敞口在限额内")
+    };
+
+    json!({
+        "risk_level": risk_level,
+        "suggested_amount": suggested,
+        "approval": approval,
+        "warnings": [],
+        "reason": format!("规则: {}", reason),
+    })
+}
+
+fn rule_trade_executor(data: &Value) -> Value {
+    let approval = text(data, "/risk/approval");
+    let action = text(data, "/strategy/action");
+    let target = data
+        .pointer("/strategy/target_side")
+        .and_then(Value::as_str);
+    let trend = text(data, "/market_report/trend");
+    let amount = num(data, "/risk/suggested_amount");
+
+    let signal = if approval == "rejected" {
+        "HOLD"
+    } else {
+        match (action, target, trend) {
+            ("open_long", _, _) | ("add_position", Some("Long"), _) => "BUY",
+            ("open_short", _, _) | ("add_position", Some("Short"), _) => "SELL",
+            ("reverse", Some("Long"), _) => "BUY",
+            ("reverse", Some("Short"), _) => "SELL",
+            ("close_position" | "reduce_position", _, "bearish") => "SELL",
+            ("close_position" | "reduce_position", _, "bullish") => "BUY",
+            _ => "HOLD",
+        }
+    };
+    let confidence = match num(data, "/strategy/timing_score") as u8 {
+        8..=10 => "HIGH",
+        5..=7 => "MEDIUM",
+        _ => "LOW",
+    };
+
+    json!({
+        "signal": signal,
+        "amount": amount,
+        "confidence": confidence,
+        "reason": format!("规则: {} / 风控 {}", action, approval),
+    })
+}
+
+// 非中性标的等权分配，保留30%现金缓冲
+fn rule_portfolio_coordinator(data: &Value) -> Value {
+    let total = num(data, "/total_available");
+    let mode = text(data, "/strategy_mode").to_lowercase();
+    let reports = data
+        .get("reports")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+
+    let active = reports
+        .iter()
+        .filter(|r| text(r, "/market_report/trend") != "neutral")
+        .count();
+    let weight = if active > 0 { 1.0 / active as f64 } else { 0.0 };
+
+    let allocations: Vec<Value> = reports
+        .iter()
+        .map(|r| {
+            let neutral = text(r, "/market_report/trend") == "neutral";
+            let priority = if neutral {
+                "skip"
+            } else {
+                match text(r, "/market_report/strength") {
+                    "strong" => "high",
+                    "medium" => "medium",
+                    _ => "low",
+                }
+            };
+            let w = if neutral { 0.0 } else { weight };
+            json!({
+                "symbol": text(r, "/symbol"),
+                "allocated_balance": total * 0.7 * w,
+                "weight": w,
+                "priority": priority,
+                "max_amount_override": Value::Null,
+            })
+        })
+        .collect();
+
+    let strategy = match mode.as_str() {
+        "aggressive" | "conservative" => mode.as_str(),
+        _ => "balanced",
+    };
+
+    json!({
+        "allocations": allocations,
+        "total_available": total,
+        "strategy": strategy,
+        "reasoning": format!("规则: {} 个趋势标的等权分配", active),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        MarketReport, PortfolioAllocation, RiskAssessment, StrategyAction, StrategyAdvice,
+        TradingDecision,
+    };
+
+    // 与 multi_agent 的 structured_prompt 相同的提示词格式
+    fn prompt(data: &Value) -> String {
+        format!(
+            "测试\n数据:\n{}\n\n输出格式:\n{{}}",
+            serde_json::to_string_pretty(data).unwrap()
+        )
+    }
+
+    async fn ask<T: serde::de::DeserializeOwned>(
+        backend: &dyn LlmBackend,
+        role: AgentRole,
+        symbol: Option<&str>,
+        data: Value,
+    ) -> T {
+        let user_prompt = prompt(&data);
+        let response = backend
+            .complete(&LlmRequest {
+                role,
+                symbol,
+                system_prompt: "",
+                user_prompt: &user_prompt,
+            })
+            .await
+            .unwrap();
+        serde_json::from_str(&response)
+            .unwrap_or_else(|e| panic!("{} 输出无法解析: {} ({})", role.as_str(), response, e))
+    }
+
+    // (标的, MA5, 3周期涨跌幅, 持仓)，MA20 固定为 100，覆盖策略研究员的全部动作
+    fn scenarios() -> Vec<(&'static str, f64, f64, Value)> {
+        let long = json!({"long": {"amount": 1.0}, "short": null});
+        let short = json!({"long": null, "short": {"amount": 1.0}});
+        let flat = json!({"long": null, "short": null});
+        vec![
+            ("BTCUSDT", 101.0, 1.0, flat.clone()),
+            ("ETHUSDT", 99.0, -1.0, flat.clone()),
+            ("SOLUSDT", 101.0, 1.0, long.clone()),
+            ("BNBUSDT", 100.1, 0.2, short),
+            ("XRPUSDT", 99.7, -0.5, long.clone()),
+            ("ADAUSDT", 99.0, -1.0, long),
+            ("DOGEUSDT", 100.0, 0.0, flat),
+        ]
+    }
+
+    // 按 行情 -> 策略 -> 风控 -> 执行 -> 组合 的顺序调用，上游输出作为下游输入
+    async fn run_roles(backend: &dyn LlmBackend) -> Vec<StrategyAction> {
+        let mut actions = Vec::new();
+        let mut reports = Vec::new();
+        for (symbol, sma_5, change_3, positions) in scenarios() {
+            let market: MarketReport = ask(
+                backend,
+                AgentRole::MarketAnalyst,
+                Some(symbol),
+                json!({
+                    "indicators": {"sma_5": sma_5, "sma_20": 100.0, "price_change_pct_3": change_3},
+                    "latest": {"close": sma_5},
+                    "long_window": {"high_max": 105.0, "low_min": 95.0},
+                }),
+            )
+            .await;
+            let strategy: StrategyAdvice = ask(
+                backend,
+                AgentRole::StrategyResearcher,
+                Some(symbol),
+                json!({"market_report": market, "positions": positions}),
+            )
+            .await;
+            let risk: RiskAssessment = ask(
+                backend,
+                AgentRole::RiskManager,
+                Some(symbol),
+                json!({
+                    "strategy": strategy,
+                    "limits": {"min_qty": 0.001, "allocated_max_amount": 0.5},
+                }),
+            )
+            .await;
+            let _: TradingDecision = ask(
+                backend,
+                AgentRole::TradeExecutor,
+                Some(symbol),
+                json!({"market_report": market, "strategy": strategy, "risk": risk}),
+            )
+            .await;
+            actions.push(strategy.action);
+            reports.push(json!({"symbol": symbol, "market_report": market}));
+        }
+        for mode in ["Aggressive", "conservative", "balanced"] {
+            let _: PortfolioAllocation = ask(
+                backend,
+                AgentRole::PortfolioCoordinator,
+                None,
+                json!({"total_available": 1000.0, "strategy_mode": mode, "reports": reports}),
+            )
+            .await;
+        }
+        actions
+    }
+
+    #[tokio::test]
+    async fn rule_outputs_deserialize_into_agent_types() {
+        let actions = run_roles(&ScriptedBackend::rules()).await;
+        assert_eq!(
+            actions,
+            vec![
+                StrategyAction::OpenLong,
+                StrategyAction::OpenShort,
+                StrategyAction::AddPosition,
+                StrategyAction::ReducePosition,
+                StrategyAction::ClosePosition,
+                StrategyAction::Reverse,
+                StrategyAction::Hold,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn replayed_outputs_deserialize_into_agent_types() {
+        let path = std::env::temp_dir().join(format!("llm_fixtures_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let recorder =
+            RecordingBackend::new(Arc::new(ScriptedBackend::rules()), path.to_str().unwrap());
+        let recorded = run_roles(&recorder).await;
+
+        let replay = ScriptedBackend::replay(&path).unwrap();
+        let replayed = run_roles(&replay).await;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replayed, recorded);
+    }
 }
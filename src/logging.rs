@@ -1,11 +1,14 @@
 use anyhow::Result;
 use flexi_logger::{Cleanup, Criterion, Duplicate, FileSpec, Logger, Naming};
+use std::sync::OnceLock;
 
 const LOG_DIR: &str = "logs";
 const APP_LOG_BASENAME: &str = "app";
 const MAX_LOG_FILES: usize = 5;
 const ROTATION_SIZE_BYTES: u64 = 10 * 1024 * 1024; // 10 MB per file
 
+static LOGS_DIR_OVERRIDE: OnceLock<String> = OnceLock::new();
+
 pub fn init_logging() -> Result<()> {
     std::fs::create_dir_all(LOG_DIR)?;
 
@@ -28,6 +31,14 @@ pub fn init_logging() -> Result<()> {
     Ok(())
 }
 
+// 将交易、决策与绩效记录重定向到其他目录（如回测输出），须在首次写入前调用
+pub fn set_logs_directory(dir: &str) {
+    let _ = LOGS_DIR_OVERRIDE.set(dir.to_string());
+}
+
 pub fn logs_directory() -> &'static str {
-    LOG_DIR
+    LOGS_DIR_OVERRIDE
+        .get()
+        .map(String::as_str)
+        .unwrap_or(LOG_DIR)
 }
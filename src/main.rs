@@ -1,55 +1,85 @@
 // 多智能体加密货币自动交易系统
 
+mod backtest;
+mod circuit_breaker;
+mod depth;
+mod derivatives;
+mod exchange;
 mod executor;
+mod indicators;
+mod journal;
+mod kline_store;
 mod llm;
 mod logging;
+mod margin;
 mod market;
+mod market_stream;
 mod multi_agent;
+mod order_tracker;
+mod paper;
 mod performance;
+mod risk;
 mod state;
 mod types;
 
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
+use circuit_breaker::CircuitBreaker;
+use depth::DepthStream;
 use dotenvy::dotenv;
-use futures::future::try_join_all;
+use exchange::{BinanceExchange, Exchange, PositionMode};
+use futures::future::{join_all, try_join_all};
+use kline_store::KlineStore;
+use llm::{AgentRole, LlmBackend};
 use log::{error, info, warn};
+use market_stream::MarketStream;
+use paper::{PaperConfig, PaperExchange};
 use performance::PerformanceTracker;
+use serde::{Deserialize, Serialize};
 use std::env;
+use std::sync::Arc;
 use tokio::time::{interval, Duration};
 
 // 并行分析后的执行结果
 struct SymbolCycleResult {
     traded: bool, // 是否执行了交易
     account_snapshot: Option<executor::AccountInfo>,
-    position_snapshot: Option<types::Position>,
+    position_snapshot: Option<types::PositionBook>, // 成交后重新查询的持仓
     trade_result: Option<types::TradeResult>,
 }
 
 // 并行分析产物
 struct SymbolAnalysis {
     symbol: String,
-    position: Option<types::Position>,
+    positions: types::PositionBook,
+    positions_fetched: bool, // 本周期从交易所查询，而非取自缓存
     market_report: types::MarketReport,
+    indicators: types::TechnicalIndicators,
+    technicals: indicators::IndicatorSet, // 扩展技术指标，按智能体选择后放入数据
+    depth: Option<depth::OrderBook>,
+    derivatives: Option<derivatives::DerivativesSnapshot>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct SymbolCacheEntry {
-    position: Option<types::Position>,
+    positions: types::PositionBook,
     last_updated: Option<DateTime<Utc>>,
 }
 
 impl SymbolCacheEntry {
     fn empty() -> Self {
         SymbolCacheEntry {
-            position: None,
+            positions: types::PositionBook::default(),
             last_updated: None,
         }
     }
 
-    fn update(&mut self, position: Option<types::Position>) {
-        self.position = position;
-        self.last_updated = Some(Utc::now());
+    // 仅在持仓确实从交易所查询时刷新时间戳，沿用缓存不延长有效期
+    fn update(&mut self, positions: types::PositionBook, fetched: bool) {
+        self.positions = positions;
+        if fetched {
+            self.last_updated = Some(Utc::now());
+        }
     }
 
     fn should_use_cache(&self, now: DateTime<Utc>, max_age_secs: u64) -> bool {
@@ -60,11 +90,52 @@ impl SymbolCacheEntry {
     }
 }
 
+// 持仓缓存落盘，重启后在有效期内继续使用
+fn symbol_cache_path() -> std::path::PathBuf {
+    std::path::Path::new(logging::logs_directory()).join("symbol_cache.json")
+}
+
+fn load_symbol_cache() -> Result<std::collections::HashMap<String, SymbolCacheEntry>> {
+    let path = symbol_cache_path();
+    if !path.exists() {
+        return Ok(std::collections::HashMap::new());
+    }
+    let content = std::fs::read_to_string(&path).context("读取持仓缓存失败")?;
+    serde_json::from_str(&content).context("解析持仓缓存失败")
+}
+
+fn persist_symbol_cache(cache: &std::collections::HashMap<String, SymbolCacheEntry>) -> Result<()> {
+    let path = symbol_cache_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).context("创建logs目录失败")?;
+    }
+    let json = serde_json::to_string_pretty(cache).context("序列化持仓缓存失败")?;
+    std::fs::write(&path, json).context("写入持仓缓存失败")?;
+    Ok(())
+}
+
+// 查询各标的杠杆分层；失败时按默认维持保证金率估算强平价
+async fn load_leverage_brackets(
+    exchange: &dyn Exchange,
+    constraints_map: &mut std::collections::HashMap<String, executor::SymbolConstraints>,
+) {
+    for (symbol, constraints) in constraints_map.iter_mut() {
+        match exchange.leverage_brackets(symbol).await {
+            Ok(brackets) => constraints.leverage_brackets = brackets,
+            Err(e) => warn!(
+                "{} 杠杆分层获取失败，按默认维持保证金率估算: {:#}",
+                symbol, e
+            ),
+        }
+    }
+}
+
 fn adjust_trade_quantity(
     desired: f64,
     allocated_max: f64,
     allocated_balance: f64,
     price: f64,
+    leverage: u32,
     constraints: &executor::SymbolConstraints,
 ) -> Option<f64> {
     if allocated_max <= 0.0 {
@@ -73,7 +144,8 @@ fn adjust_trade_quantity(
 
     let mut effective_max = allocated_max;
     if allocated_balance > 0.0 && price > 0.0 {
-        let balance_limit = allocated_balance / price;
+        // 分配资金作为初始保证金，按杠杆折算可开数量
+        let balance_limit = allocated_balance * leverage.max(1) as f64 / price;
         if balance_limit > 0.0 {
             effective_max = effective_max.min(balance_limit);
         }
@@ -122,10 +194,11 @@ fn adjust_trade_quantity(
         return None;
     }
 
-    if constraints.min_notional > 0.0 && price > 0.0 {
-        if qty * price + f64::EPSILON < constraints.min_notional {
-            return None;
-        }
+    if constraints.min_notional > 0.0
+        && price > 0.0
+        && qty * price + f64::EPSILON < constraints.min_notional
+    {
+        return None;
     }
 
     if qty <= 0.0 {
@@ -135,16 +208,52 @@ fn adjust_trade_quantity(
     }
 }
 
+// 逗号分隔的K线周期列表，按周期长度升序并去重
+fn parse_timeframes(value: &str) -> Result<Vec<String>> {
+    let mut timeframes = value
+        .split(',')
+        .map(str::trim)
+        .filter(|tf| !tf.is_empty())
+        .map(|tf| Ok((market::interval_to_millis(tf)?, tf.to_string())))
+        .collect::<Result<Vec<_>>>()?;
+    timeframes.sort();
+    timeframes.dedup();
+    Ok(timeframes.into_iter().map(|(_, tf)| tf).collect())
+}
+
+// 交易所运行模式
+#[derive(Debug, Clone, PartialEq)]
+enum ExchangeMode {
+    Live,     // Binance 实盘/测试网
+    Paper,    // 本地模拟撮合
+    Backtest, // 历史K线回放
+}
+
 // 配置结构
 struct Config {
+    exchange_mode: ExchangeMode,
     binance_api_key: String,
     binance_secret: String,
-    deepseek_api_key: String,
+    llm: Arc<dyn LlmBackend>,   // 各智能体共用的 LLM 后端
     trade_symbols: Vec<String>, // 多标的交易
     trade_interval_secs: u64,
+    analysis_timeframes: Vec<String>, // 附加分析周期，按周期长度升序
     leverage: u32,
-    max_position: f64,      // 每个标的最大持仓量
-    portfolio_mode: String, // balanced/aggressive/conservative
+    max_position: f64,               // 每个标的最大持仓量
+    portfolio_mode: String,          // balanced/aggressive/conservative
+    kline_store_dir: Option<String>, // 本地K线存储目录，未设置时实盘直接拉取REST
+    protective_orders: bool,         // 是否在交易所挂出止损止盈单
+    protective_close_position: bool, // 保护单使用 closePosition，否则按持仓数量挂单
+    position_mode: PositionMode,     // 双向/单向持仓
+    performance_rebuild: bool,       // 启动时从 trades.jsonl 重建绩效统计
+    market_stream: bool,             // 行情走 WebSocket 缓冲，REST 仅作回退
+    depth_levels: usize,             // 盘口失衡统计的档数
+    liquidation_atr_multiple: f64,   // 开仓后强平价距入场价至少为 ATR 的倍数，0 表示不检查
+    // 各标的开仓执行方式（市价/post-only/IOC/FOK）
+    execution_policies: std::collections::HashMap<String, executor::ExecutionPolicy>,
+    risk_engine: risk::RiskEngine, // 决策与执行之间的硬性风控规则
+    circuit_breaker: circuit_breaker::BreakerConfig, // 日内/滚动亏损熔断与手动熔断
+    indicator_selection: indicators::IndicatorSelection, // 各智能体数据中附带的扩展指标
 }
 
 impl Config {
@@ -156,10 +265,51 @@ impl Config {
             .filter(|s| !s.is_empty())
             .collect();
 
+        // 命令行 `backtest` 子命令优先于 EXCHANGE_MODE
+        let exchange_mode = if env::args().nth(1).as_deref() == Some("backtest") {
+            ExchangeMode::Backtest
+        } else {
+            match env::var("EXCHANGE_MODE")
+                .unwrap_or_else(|_| "live".to_string())
+                .to_lowercase()
+                .as_str()
+            {
+                "paper" => ExchangeMode::Paper,
+                "backtest" => ExchangeMode::Backtest,
+                _ => ExchangeMode::Live,
+            }
+        };
+
+        // 回放时行情不随等待推进，post-only 挂单不等待直接转市价
+        let execution_policies = trade_symbols
+            .iter()
+            .map(|symbol| {
+                let mut policy = executor::ExecutionPolicy::from_env(symbol)?;
+                if exchange_mode == ExchangeMode::Backtest {
+                    policy.maker_wait_secs = 0;
+                }
+                Ok((symbol.clone(), policy))
+            })
+            .collect::<Result<_>>()
+            .context("执行策略配置加载失败")?;
+
+        // 模拟盘与回测只使用公开行情接口，无需密钥
+        let read_key = |key: &str| -> Result<String> {
+            match exchange_mode {
+                ExchangeMode::Live => env::var(key).with_context(|| format!("缺少 {}", key)),
+                ExchangeMode::Paper | ExchangeMode::Backtest => {
+                    Ok(env::var(key).unwrap_or_default())
+                }
+            }
+        };
+
         Ok(Config {
-            binance_api_key: env::var("BINANCE_API_KEY").context("缺少 BINANCE_API_KEY")?,
-            binance_secret: env::var("BINANCE_SECRET").context("缺少 BINANCE_SECRET")?,
-            deepseek_api_key: env::var("DEEPSEEK_API_KEY").context("缺少 DEEPSEEK_API_KEY")?,
+            binance_api_key: read_key("BINANCE_API_KEY")?,
+            binance_secret: read_key("BINANCE_SECRET")?,
+            exchange_mode,
+            llm: Arc::new(journal::JournalingBackend::new(
+                llm::backend_from_env().context("LLM 后端初始化失败")?,
+            )),
             trade_symbols,
             trade_interval_secs: match env::var("TRADE_INTERVAL")
                 .unwrap_or_else(|_| "1m".to_string())
@@ -171,6 +321,10 @@ impl Config {
                 "1h" => 60 * 60,
                 _ => 60,
             },
+            analysis_timeframes: parse_timeframes(
+                &env::var("ANALYSIS_TIMEFRAMES").unwrap_or_default(),
+            )
+            .context("ANALYSIS_TIMEFRAMES 格式错误")?,
             leverage: env::var("LEVERAGE")
                 .unwrap_or_else(|_| "10".to_string())
                 .parse()
@@ -180,9 +334,58 @@ impl Config {
                 .parse()
                 .context("MAX_POSITION 格式错误")?,
             portfolio_mode: env::var("PORTFOLIO_MODE").unwrap_or_else(|_| "balanced".to_string()),
+            kline_store_dir: env::var("KLINE_STORE_DIR").ok(),
+            protective_orders: env::var("PROTECTIVE_ORDERS")
+                .map(|v| v != "false")
+                .unwrap_or(true),
+            protective_close_position: env::var("PROTECTIVE_CLOSE_POSITION")
+                .map(|v| v != "false")
+                .unwrap_or(true),
+            position_mode: match env::var("POSITION_MODE") {
+                Ok(value) => PositionMode::parse(&value)
+                    .with_context(|| format!("POSITION_MODE 格式错误: {}", value))?,
+                Err(_) => PositionMode::Hedge,
+            },
+            performance_rebuild: env::var("PERFORMANCE_REBUILD")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            market_stream: env::var("MARKET_STREAM")
+                .map(|v| v != "false")
+                .unwrap_or(true),
+            depth_levels: env::var("DEPTH_LEVELS")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .context("DEPTH_LEVELS 格式错误")?,
+            liquidation_atr_multiple: env::var("LIQUIDATION_ATR_MULTIPLE")
+                .unwrap_or_else(|_| "3".to_string())
+                .parse()
+                .context("LIQUIDATION_ATR_MULTIPLE 格式错误")?,
+            execution_policies,
+            risk_engine: risk::RiskEngine::new(risk::RiskLimits::from_env()?),
+            circuit_breaker: circuit_breaker::BreakerConfig::from_env()?,
+            indicator_selection: indicators::IndicatorSelection::from_env()?,
         })
     }
 
+    // 交易周期之外的附加分析周期
+    fn context_timeframes(&self) -> impl Iterator<Item = &str> {
+        let trade_interval = self.interval_str();
+        self.analysis_timeframes
+            .iter()
+            .map(String::as_str)
+            .filter(move |tf| *tf != trade_interval)
+    }
+
+    fn interval_str(&self) -> &'static str {
+        match self.trade_interval_secs {
+            60 => "1m",
+            900 => "15m",
+            1800 => "30m",
+            3600 => "1h",
+            _ => "1m",
+        }
+    }
+
     fn desired_portfolio_strategy(&self) -> types::PortfolioStrategy {
         match self.portfolio_mode.to_lowercase().as_str() {
             "aggressive" => types::PortfolioStrategy::Aggressive,
@@ -194,17 +397,20 @@ impl Config {
 
 // 并行阶段：每个标的的行情与持仓分析
 async fn analyze_symbol(
+    exchange: &dyn Exchange,
     symbol: String,
     config: &Config,
     interval_str: &str,
     use_cache: bool,
-    cached_position: Option<types::Position>,
+    cached_positions: types::PositionBook,
 ) -> Result<SymbolAnalysis> {
     info!("--- 分析标的: {} ---", symbol);
 
     // 1. 获取K线数据
     const ANALYSIS_KLINE_LIMIT: u32 = 120;
-    let klines = market::fetch_klines(&symbol, interval_str, ANALYSIS_KLINE_LIMIT).await?;
+    let klines = exchange
+        .fetch_klines(&symbol, interval_str, ANALYSIS_KLINE_LIMIT)
+        .await?;
     info!("获取到 {} 根K线", klines.len());
 
     // 2. 计算技术指标
@@ -224,42 +430,92 @@ async fn analyze_symbol(
         indicators.volume_ratio
     );
 
-    // 3. 获取持仓（优先使用缓存）
-    let position = if use_cache {
-        match cached_position {
-            None => {
-                info!("当前持仓: 空仓 (缓存)");
-                None
-            }
-            Some(pos) => {
+    let technicals = indicators::IndicatorSet::compute(&klines);
+    info!("扩展指标: {}", technicals);
+
+    // 3. 附加周期（如 15m/4h/1d）的指标与趋势，单个周期失败时跳过
+    let symbol_ref = symbol.as_str();
+    let timeframe_results = join_all(config.context_timeframes().map(|tf| async move {
+        let klines = exchange
+            .fetch_klines(symbol_ref, tf, ANALYSIS_KLINE_LIMIT)
+            .await?;
+        market::timeframe_context(tf, &klines)
+    }))
+    .await;
+    let mut timeframes = Vec::new();
+    for (tf, result) in config.context_timeframes().zip(timeframe_results) {
+        match result {
+            Ok(context) => {
                 info!(
-                    "当前持仓: {:?}仓 {:.4}, 盈亏: {:.2} USDT (缓存)",
-                    pos.side, pos.amount, pos.unrealized_pnl
+                    "{} 周期: {:?}, 收盘 {:.2}, SMA20={:.2}, SMA50={:.2}, 距SMA50 {:+.2}%, Δ12={:.2}%, ATR14 {:.2}%",
+                    tf,
+                    context.trend,
+                    context.close,
+                    context.indicators.sma_20,
+                    context.indicators.sma_50,
+                    context.close_vs_sma_50,
+                    context.indicators.price_change_12,
+                    context.indicators.atr_percent
                 );
-                Some(pos)
+                timeframes.push(context);
             }
+            Err(e) => warn!("{} {} 周期分析失败: {:#}", symbol, tf, e),
         }
+    }
+
+    // 4. 获取持仓（优先使用缓存）
+    let positions_fetched = !use_cache;
+    let positions = if use_cache {
+        info!("当前持仓: {} (缓存)", cached_positions);
+        cached_positions
     } else {
-        let pos = executor::get_position(&symbol, &config.binance_api_key, &config.binance_secret)
-            .await?;
-        match &pos {
-            None => info!("当前持仓: 空仓"),
-            Some(p) => info!(
-                "当前持仓: {:?}仓 {:.4}, 盈亏: {:.2} USDT",
-                p.side, p.amount, p.unrealized_pnl
-            ),
+        let positions = exchange.get_positions(&symbol).await?;
+        info!("当前持仓: {}", positions);
+        positions
+    };
+
+    // 5. 盘口深度（按最大持仓估算滑点），获取失败不影响分析
+    let depth = match exchange.fetch_depth(&symbol).await {
+        Ok(book) => Some(book),
+        Err(e) => {
+            warn!("{} 盘口获取失败: {:#}", symbol, e);
+            None
         }
-        pos
     };
+    let depth_metrics = depth
+        .as_ref()
+        .and_then(|book| book.metrics(config.depth_levels, config.max_position));
+    if let Some(metrics) = &depth_metrics {
+        info!("盘口: {}", metrics);
+    }
 
-    // 4. 行情分析
+    // 6. 衍生品数据（资金费率/持仓量/主动买卖比），获取失败不影响分析
+    let derivatives = match exchange.fetch_derivatives(&symbol, interval_str).await {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            warn!("{} 衍生品数据获取失败: {:#}", symbol, e);
+            None
+        }
+    };
+    if let Some(snapshot) = &derivatives {
+        info!("衍生品: {}", snapshot);
+    }
+
+    // 7. 行情分析
     info!("--- 行情分析员决策 ---");
     let market_report = multi_agent::market_analyst_analyze(
         &symbol,
         interval_str,
         &klines,
         &indicators,
-        &config.deepseek_api_key,
+        config
+            .indicator_selection
+            .payload(AgentRole::MarketAnalyst, &technicals)
+            .as_ref(),
+        &timeframes,
+        depth_metrics.as_ref(),
+        derivatives.as_ref(),
+        config.llm.as_ref(),
     )
     .await?;
     info!(
@@ -269,34 +525,40 @@ async fn analyze_symbol(
         market_report.market_phase,
         market_report.analysis
     );
+    journal::record_agent_output(AgentRole::MarketAnalyst, Some(&symbol), &market_report);
 
     Ok(SymbolAnalysis {
         symbol,
-        position,
+        positions,
+        positions_fetched,
         market_report,
+        indicators,
+        technicals,
+        depth,
+        derivatives,
     })
 }
 
 // 决策与执行阶段：在所有分析完成后顺序执行
+#[allow(clippy::too_many_arguments)]
 async fn execute_symbol_cycle(
+    exchange: &dyn Exchange,
     analysis: &SymbolAnalysis,
     allocated_max_amount: f64,
     allocated_balance: f64,
     constraints: &executor::SymbolConstraints,
     account: &executor::AccountInfo,
+    portfolio: &std::collections::HashMap<String, types::PositionBook>,
+    halt_reason: Option<&str>,
     config: &Config,
+    cycle: i64,
 ) -> Result<SymbolCycleResult> {
     info!("--- 决策执行: {} ---", analysis.symbol);
+    let order_ids = order_tracker::ClientOrderIds::new(cycle, &analysis.symbol);
 
     info!("账户: 可用余额 {} USDT", account.availableBalance);
 
-    match &analysis.position {
-        None => info!("当前持仓: 空仓"),
-        Some(pos) => info!(
-            "当前持仓: {:?}仓 {:.4}, 盈亏: {:.2} USDT",
-            pos.side, pos.amount, pos.unrealized_pnl
-        ),
-    }
+    info!("当前持仓: {}", analysis.positions);
 
     info!("--- 多智能体决策开始 ---");
 
@@ -304,10 +566,20 @@ async fn execute_symbol_cycle(
     let strategy = multi_agent::strategy_researcher_suggest(
         &analysis.symbol,
         &analysis.market_report,
-        &analysis.position,
-        &config.deepseek_api_key,
+        &analysis.positions,
+        config
+            .indicator_selection
+            .payload(AgentRole::StrategyResearcher, &analysis.technicals)
+            .as_ref(),
+        analysis.derivatives.as_ref(),
+        config.llm.as_ref(),
     )
     .await?;
+    journal::record_agent_output(
+        AgentRole::StrategyResearcher,
+        Some(&analysis.symbol),
+        &strategy,
+    );
     info!(
         "策略研究员: {:?} | 时机评分: {}/10 | {}",
         strategy.action, strategy.timing_score, strategy.reasoning
@@ -322,20 +594,30 @@ async fn execute_symbol_cycle(
         info!("建议止盈: {:.2}%", tp * 100.0);
     }
 
-    // 风险管理员
+    // 风险管理员：滑点按本次允许的最大数量估算
+    let depth_metrics = analysis
+        .depth
+        .as_ref()
+        .and_then(|book| book.metrics(config.depth_levels, allocated_max_amount));
     let risk = multi_agent::risk_manager_assess(
         &analysis.symbol,
         &analysis.market_report,
         &strategy,
-        &account,
-        &analysis.position,
+        account,
+        &analysis.positions,
         constraints,
         allocated_balance,
         allocated_max_amount,
         config.max_position,
-        &config.deepseek_api_key,
+        depth_metrics.as_ref(),
+        config
+            .indicator_selection
+            .payload(AgentRole::RiskManager, &analysis.technicals)
+            .as_ref(),
+        config.llm.as_ref(),
     )
     .await?;
+    journal::record_agent_output(AgentRole::RiskManager, Some(&analysis.symbol), &risk);
     info!(
         "风险管理员: {:?} | 审批: {:?} | 建议数量: {:.4} | {}",
         risk.risk_level, risk.approval, risk.suggested_amount, risk.reason
@@ -350,24 +632,33 @@ async fn execute_symbol_cycle(
         &analysis.market_report,
         &strategy,
         &risk,
-        &config.deepseek_api_key,
+        config
+            .indicator_selection
+            .payload(AgentRole::TradeExecutor, &analysis.technicals)
+            .as_ref(),
+        config.llm.as_ref(),
     )
     .await?;
+    journal::record_agent_output(AgentRole::TradeExecutor, Some(&analysis.symbol), &decision);
     info!(
         "决策交易员: {:?}, 数量: {:.4}, 信心: {:?} | {}",
         decision.signal, decision.amount, decision.confidence, decision.reason
     );
     info!("--- 多智能体决策完成 ---");
 
-    state::log_decision(&analysis.symbol, &decision, &analysis.position)?;
+    state::log_decision(&analysis.symbol, &decision, &analysis.positions)?;
 
     let mut traded = false;
     let mut account_snapshot = Some(account.clone());
-    let mut position_snapshot = analysis.position.clone();
+    let mut position_snapshot = None;
     let mut latest_trade_result = None;
+    // 用于同步保护单的持仓；查询失败时为 None，跳过同步以免误撤
+    let mut protected_positions = None;
+    // 本周期开仓/加仓的一腿，其保护单按最新策略重挂
+    let mut opened_side = None;
 
     if decision.signal != types::Signal::Hold {
-        let raw_price = market::fetch_current_price(&analysis.symbol).await?;
+        let raw_price = exchange.fetch_current_price(&analysis.symbol).await?;
         let quoted_price = executor::quantize_price(raw_price, constraints.tick_size);
         info!("价格对齐: 原始 {:.6} → {:.6}", raw_price, quoted_price);
         let maybe_trade_amount = adjust_trade_quantity(
@@ -375,11 +666,14 @@ async fn execute_symbol_cycle(
             allocated_max_amount,
             allocated_balance,
             quoted_price,
+            config.leverage,
             constraints,
         );
 
+        // 减仓/平仓不受资金分配约束，数量由执行层按持仓截断
         let trade_amount = match maybe_trade_amount {
             Some(qty) => qty,
+            None if strategy.action.reduces_only() => decision.amount,
             None => {
                 warn!(
                     "无法满足交易约束，保持观望: 建议 {:.6}, 分配上限 {:.6}, 分配资金 {:.2} USDT, 价格 {:.6}",
@@ -388,7 +682,7 @@ async fn execute_symbol_cycle(
                 return Ok(SymbolCycleResult {
                     traded: false,
                     account_snapshot: Some(account.clone()),
-                    position_snapshot: analysis.position.clone(),
+                    position_snapshot: None,
                     trade_result: None,
                 });
             }
@@ -401,20 +695,140 @@ async fn execute_symbol_cycle(
             );
         }
 
+        // 强平距离：按杠杆分层估算成交后的强平价，距标记价格不足 k 倍 ATR 时缩减或放弃
+        let mut trade_amount = trade_amount;
+        if !strategy.action.reduces_only()
+            && config.liquidation_atr_multiple > 0.0
+            && analysis.indicators.atr_14 > 0.0
+        {
+            let side = match decision.signal {
+                types::Signal::Sell => types::PositionSide::Short,
+                _ => types::PositionSide::Long,
+            };
+            let mark_price = match exchange.fetch_mark_price(&analysis.symbol).await {
+                Ok(price) => price,
+                Err(e) => {
+                    warn!("获取标记价格失败，使用最新价: {:#}", e);
+                    quoted_price
+                }
+            };
+            let sizing = margin::size_for_liquidation(
+                &side,
+                analysis.positions.leg(&side),
+                quoted_price,
+                mark_price,
+                trade_amount,
+                config.leverage,
+                config.liquidation_atr_multiple * analysis.indicators.atr_14,
+                &constraints.leverage_brackets,
+                constraints.step_size,
+            );
+            info!(
+                "保证金估算: 初始保证金 {:.2} USDT ({}x), 估算强平价 {}",
+                margin::initial_margin(sizing.amount * quoted_price, config.leverage),
+                config.leverage,
+                sizing
+                    .liquidation_price
+                    .map_or_else(|| "-".to_string(), |p| format!("{:.4}", p))
+            );
+            if sizing.amount < trade_amount {
+                if sizing.amount <= 0.0
+                    || sizing.amount < constraints.min_qty
+                    || sizing.amount * quoted_price < constraints.min_notional
+                {
+                    warn!(
+                        "强平价距标记价格不足 {:.4} ({}×ATR)，放弃开仓: 数量 {:.6}",
+                        sizing.min_distance, config.liquidation_atr_multiple, trade_amount
+                    );
+                    return Ok(SymbolCycleResult {
+                        traded: false,
+                        account_snapshot: Some(account.clone()),
+                        position_snapshot: None,
+                        trade_result: None,
+                    });
+                }
+                warn!(
+                    "强平价距标记价格需不小于 {:.4} ({}×ATR)，数量缩减: {:.6} → {:.6}",
+                    sizing.min_distance,
+                    config.liquidation_atr_multiple,
+                    trade_amount,
+                    sizing.amount
+                );
+                trade_amount = sizing.amount;
+            }
+        }
+
+        // 硬性风控：按配置规则否决或缩减开仓/加仓
+        let risk_check = config.risk_engine.check(
+            &risk::RiskContext {
+                symbol: &analysis.symbol,
+                portfolio,
+                price: quoted_price,
+                wallet_balance: account.totalWalletBalance.parse().unwrap_or(0.0),
+                leverage: config.leverage,
+                step_size: constraints.step_size,
+                min_qty: constraints.min_qty,
+                now_ms: exchange.now_millis(),
+                halt_reason,
+            },
+            &decision.signal,
+            &strategy.action,
+            trade_amount,
+        );
+        for event in &risk_check.events {
+            warn!(
+                "风控{}: {} [{}] {}",
+                event.kind.label(),
+                analysis.symbol,
+                event.rule,
+                event.detail
+            );
+            journal::record_risk_event(&analysis.symbol, &decision.signal, event, &risk_check);
+        }
+        let action = if risk_check.close_only {
+            warn!("风控否决反手开仓，仅平掉反向持仓: {}", analysis.symbol);
+            types::StrategyAction::ClosePosition
+        } else if risk_check.vetoed() {
+            return Ok(SymbolCycleResult {
+                traded: false,
+                account_snapshot: Some(account.clone()),
+                position_snapshot: None,
+                trade_result: None,
+            });
+        } else {
+            strategy.action.clone()
+        };
+        let trade_amount = risk_check.amount;
+
         match executor::execute_decision(
+            exchange,
             &analysis.symbol,
             &decision,
-            &analysis.position,
+            &action,
+            &analysis.positions,
             quoted_price,
             trade_amount,
             config.max_position,
-            &config.binance_api_key,
-            &config.binance_secret,
+            &config.execution_policies[&analysis.symbol],
+            constraints,
+            &order_ids,
         )
         .await
         {
             Ok(result) => {
                 traded = !matches!(result.action, types::TradeAction::Hold);
+                if traded {
+                    config.risk_engine.record_order(result.timestamp);
+                    // 持仓已成交，保存失败不能中断后续的交易记录与保护单同步
+                    if let Err(e) = config.risk_engine.persist() {
+                        warn!("风控状态保存失败: {:#}", e);
+                    }
+                }
+                opened_side = match result.action {
+                    types::TradeAction::OpenLong => Some(types::PositionSide::Long),
+                    types::TradeAction::OpenShort => Some(types::PositionSide::Short),
+                    _ => None,
+                };
 
                 let trade_record = result.clone();
                 info!(
@@ -427,22 +841,17 @@ async fn execute_symbol_cycle(
                 if let Some(pnl) = result.pnl {
                     info!("平仓盈亏: {:.2} USDT", pnl);
                 }
+                if let Some(commission) = result.commission {
+                    info!("手续费: {:.4}", commission);
+                }
                 state::log_trade(&trade_record)?;
                 latest_trade_result = Some(trade_record);
 
                 if traded {
-                    account_snapshot =
-                        executor::get_account_info(&config.binance_api_key, &config.binance_secret)
-                            .await
-                            .ok();
-                    position_snapshot = executor::get_position(
-                        &analysis.symbol,
-                        &config.binance_api_key,
-                        &config.binance_secret,
-                    )
-                    .await
-                    .ok()
-                    .flatten();
+                    account_snapshot = exchange.get_account_info().await.ok();
+                    let latest_positions = exchange.get_positions(&analysis.symbol).await.ok();
+                    position_snapshot = latest_positions.clone();
+                    protected_positions = latest_positions;
                 }
             }
             Err(e) => {
@@ -452,10 +861,12 @@ async fn execute_symbol_cycle(
                     action: types::TradeAction::Hold,
                     price: quoted_price,
                     amount: 0.0,
-                    timestamp: chrono::Utc::now().timestamp(),
+                    timestamp: exchange.now_millis(),
                     reason: format!("交易失败: {:#}", e),
                     pnl: None,
+                    commission: None,
                     order_details: Some(format!("ERROR: {:#}", e)),
+                    order_ids: Vec::new(),
                 };
                 state::log_trade(&failed_result)?;
                 latest_trade_result = Some(failed_result);
@@ -465,6 +876,28 @@ async fn execute_symbol_cycle(
         info!("保持观望");
     }
 
+    if config.protective_orders && !traded {
+        // 交易所侧止损/止盈可能在周期间成交，同步前重新查询持仓，已平的一腿撤掉剩余保护单
+        protected_positions = exchange.get_positions(&analysis.symbol).await.ok();
+        position_snapshot = protected_positions.clone();
+    }
+    if let (true, Some(positions)) = (config.protective_orders, &protected_positions) {
+        let replace_with = opened_side.map(|side| (side, &strategy));
+        if let Err(e) = executor::sync_protective_orders(
+            exchange,
+            &analysis.symbol,
+            positions,
+            replace_with,
+            constraints.tick_size,
+            config.protective_close_position,
+            &order_ids,
+        )
+        .await
+        {
+            warn!("同步止损止盈单失败: {:#}", e);
+        }
+    }
+
     Ok(SymbolCycleResult {
         traded,
         account_snapshot,
@@ -473,41 +906,87 @@ async fn execute_symbol_cycle(
     })
 }
 
+// 同步资金流水，交易所侧止损止盈与强平的平仓记入盈亏归因
+async fn sync_income(
+    exchange: &dyn Exchange,
+    config: &Config,
+    performance_tracker: &mut PerformanceTracker,
+) -> Result<()> {
+    let closes = performance_tracker
+        .sync_income(exchange, &config.trade_symbols)
+        .await?;
+    for close in &closes {
+        info!(
+            "交易所侧平仓 {} {:?}: 已实现 {:.2}, 手续费 {:.4}, 资金费 {:.4}, 净额 {:.2} USDT",
+            close.symbol,
+            close.action,
+            close.realized_pnl,
+            close.commission,
+            close.funding,
+            close.net_pnl
+        );
+        state::log_trade_pnl(close)?;
+    }
+    Ok(())
+}
+
 // 多标的投资组合交易周期
 async fn run_portfolio_cycle(
+    exchange: &dyn Exchange,
     config: &Config,
     interval_str: &str,
     constraints_map: &std::collections::HashMap<String, executor::SymbolConstraints>,
     symbols_cache: &mut std::collections::HashMap<String, SymbolCacheEntry>,
     performance_tracker: &mut PerformanceTracker,
-) -> Result<bool> {
+    breaker: &mut CircuitBreaker,
+) -> Result<Vec<types::TradeResult>> {
     info!("============================================================");
+    let cycle_ms = exchange.now_millis();
+    // 周期序号用于生成确定性的客户端订单号，同一周期内重启不会换号
+    let cycle = cycle_ms / (config.trade_interval_secs as i64 * 1000);
+    journal::begin_cycle(cycle, cycle_ms, exchange.name(), &config.trade_symbols);
+    let halt_reason = breaker.halt_reason(cycle_ms)?;
+    if let Some(reason) = &halt_reason {
+        warn!("熔断中，暂停开仓/加仓: {}", reason);
+    }
+    let cycle_time = DateTime::from_timestamp_millis(cycle_ms).unwrap_or_else(Utc::now);
     info!(
         "执行时间: {}",
-        chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+        cycle_time
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S")
     );
     info!("============================================================");
 
+    // 同步上一周期以来的已实现盈亏、手续费与资金费，平仓交易据此归因资金费
+    match sync_income(exchange, config, performance_tracker).await {
+        Ok(()) => performance_tracker.persist()?,
+        Err(e) => warn!("资金流水同步失败: {:#}", e),
+    }
+
     // 1. 获取所有标的的行情分析
     info!("=== 第一阶段：行情分析 (并行) ===");
     let now = Utc::now();
     let mut analysis_futures = Vec::new();
     for symbol in &config.trade_symbols {
         let symbol_clone = symbol.clone();
-        let (cached_position, use_cache) = match symbols_cache.get(symbol) {
+        let (cached_positions, use_cache) = match symbols_cache.get(symbol) {
+            // 挂有交易所侧保护单时持仓随时可能被平，不使用缓存
             Some(entry) => (
-                entry.position.clone(),
-                entry.should_use_cache(now, config.trade_interval_secs),
+                entry.positions.clone(),
+                !config.protective_orders
+                    && entry.should_use_cache(now, config.trade_interval_secs),
             ),
-            None => (None, false),
+            None => (types::PositionBook::default(), false),
         };
 
         analysis_futures.push(analyze_symbol(
+            exchange,
             symbol_clone,
             config,
             interval_str,
             use_cache,
-            cached_position,
+            cached_positions,
         ));
     }
 
@@ -515,13 +994,20 @@ async fn run_portfolio_cycle(
 
     // 2. 投资组合协调员分配资金
     info!("=== 第二阶段：投资组合资金分配 ===");
-    let symbols_reports: Vec<(String, types::MarketReport)> = analyses
+    let symbols_reports: Vec<_> = analyses
         .iter()
-        .map(|a| (a.symbol.clone(), a.market_report.clone()))
+        .map(|a| {
+            (
+                a.symbol.clone(),
+                a.market_report.clone(),
+                config
+                    .indicator_selection
+                    .payload(AgentRole::PortfolioCoordinator, &a.technicals),
+            )
+        })
         .collect();
 
-    let mut current_account =
-        executor::get_account_info(&config.binance_api_key, &config.binance_secret).await?;
+    let mut current_account = exchange.get_account_info().await?;
     let total_balance: f64 = current_account.availableBalance.parse().unwrap_or(0.0);
     info!("总可用资金: {} USDT", total_balance);
 
@@ -529,9 +1015,10 @@ async fn run_portfolio_cycle(
         &symbols_reports,
         total_balance,
         &config.portfolio_mode,
-        &config.deepseek_api_key,
+        config.llm.as_ref(),
     )
     .await?;
+    journal::record_agent_output(AgentRole::PortfolioCoordinator, None, &portfolio_allocation);
 
     let desired_strategy = config.desired_portfolio_strategy();
     if portfolio_allocation.strategy != desired_strategy {
@@ -562,7 +1049,13 @@ async fn run_portfolio_cycle(
         .map(|analysis| (analysis.symbol.clone(), analysis))
         .collect();
 
-    let mut any_traded = false;
+    let mut executed_trades = Vec::new();
+    // 组合持仓视图，供风控计算总/净敞口；每个标的执行后更新
+    let mut portfolio_positions: std::collections::HashMap<String, types::PositionBook> =
+        analysis_map
+            .iter()
+            .map(|(symbol, analysis)| (symbol.clone(), analysis.positions.clone()))
+            .collect();
 
     for alloc in &portfolio_allocation.allocations {
         let analysis = match analysis_map.remove(&alloc.symbol) {
@@ -579,20 +1072,20 @@ async fn run_portfolio_cycle(
             symbols_cache
                 .entry(alloc.symbol.clone())
                 .or_insert_with(SymbolCacheEntry::empty)
-                .update(analysis.position.clone());
+                .update(analysis.positions.clone(), analysis.positions_fetched);
             continue;
         }
 
         let allocated_balance = alloc.allocated_balance;
         let symbol = alloc.symbol.clone();
         let constraint = match constraints_map.get(&symbol) {
-            Some(c) => *c,
+            Some(c) => c,
             None => {
                 error!("缺少交易约束，跳过标的 {}", symbol);
                 symbols_cache
                     .entry(symbol.clone())
                     .or_insert_with(SymbolCacheEntry::empty)
-                    .update(None);
+                    .update(analysis.positions.clone(), analysis.positions_fetched);
                 continue;
             }
         };
@@ -602,7 +1095,7 @@ async fn run_portfolio_cycle(
             symbols_cache
                 .entry(symbol.clone())
                 .or_insert_with(SymbolCacheEntry::empty)
-                .update(analysis.position.clone());
+                .update(analysis.positions.clone(), analysis.positions_fetched);
             continue;
         }
 
@@ -615,7 +1108,7 @@ async fn run_portfolio_cycle(
             symbols_cache
                 .entry(symbol.clone())
                 .or_insert_with(SymbolCacheEntry::empty)
-                .update(analysis.position.clone());
+                .update(analysis.positions.clone(), analysis.positions_fetched);
             continue;
         }
 
@@ -627,17 +1120,21 @@ async fn run_portfolio_cycle(
             symbols_cache
                 .entry(symbol.clone())
                 .or_insert_with(SymbolCacheEntry::empty)
-                .update(analysis.position.clone());
+                .update(analysis.positions.clone(), analysis.positions_fetched);
             continue;
         }
 
         match execute_symbol_cycle(
+            exchange,
             &analysis,
             max_amount,
             allocated_balance,
-            &constraint,
+            constraint,
             &current_account,
+            &portfolio_positions,
+            halt_reason.as_deref(),
             config,
+            cycle,
         )
         .await
         {
@@ -649,35 +1146,53 @@ async fn run_portfolio_cycle(
                     trade_result,
                 } = result;
 
-                if cycle_traded {
-                    any_traded = true;
-                }
-
                 if let Some(snapshot) = account_snapshot {
                     current_account = snapshot;
                 }
 
                 if let Some(trade) = trade_result.as_ref() {
-                    if performance_tracker.update(trade) {
+                    if cycle_traded {
+                        executed_trades.push(trade.clone());
+                    }
+                    if let Some(attribution) = performance_tracker.update(trade) {
+                        info!(
+                            "交易净盈亏: 已实现 {:.2}, 手续费 {:.4}, 资金费 {:.4}, 净额 {:.2} USDT",
+                            attribution.realized_pnl,
+                            attribution.commission,
+                            attribution.funding,
+                            attribution.net_pnl
+                        );
+                        state::log_trade_pnl(&attribution)?;
+                    }
+                    if cycle_traded {
                         performance_tracker.persist()?;
+                        let snapshot = performance_tracker.snapshot();
+                        info!(
+                            "累计绩效: 交易 {} 笔, 净盈亏 {:.2} USDT (已实现 {:.2}, 手续费 {:.2}, 资金费 {:.2}), 最大回撤 {:.2} USDT",
+                            snapshot.total_trades,
+                            snapshot.net_pnl,
+                            snapshot.total_realized_pnl,
+                            snapshot.total_commission,
+                            snapshot.total_funding,
+                            snapshot.max_drawdown
+                        );
                     }
                 }
 
-                let cached_position = position_snapshot
-                    .clone()
-                    .or_else(|| analysis.position.clone());
+                let fetched = position_snapshot.is_some() || analysis.positions_fetched;
+                let cached_positions =
+                    position_snapshot.unwrap_or_else(|| analysis.positions.clone());
+                portfolio_positions.insert(symbol.clone(), cached_positions.clone());
 
                 symbols_cache
                     .entry(symbol)
                     .or_insert_with(SymbolCacheEntry::empty)
-                    .update(cached_position);
+                    .update(cached_positions, fetched);
             }
             Err(e) => {
                 error!("标的 {} 交易周期失败: {:#}", symbol, e);
-                symbols_cache
-                    .entry(symbol)
-                    .or_insert_with(SymbolCacheEntry::empty)
-                    .update(analysis.position.clone());
+                // 失败时持仓可能已部分变化，下一周期重新查询
+                symbols_cache.remove(&symbol);
             }
         }
     }
@@ -687,10 +1202,140 @@ async fn run_portfolio_cycle(
         symbols_cache
             .entry(symbol)
             .or_insert_with(SymbolCacheEntry::empty)
-            .update(analysis.position.clone());
+            .update(analysis.positions.clone(), analysis.positions_fetched);
+    }
+
+    // 权益曲线采样：钱包余额 + 各标的持仓未实现盈亏；先同步本周期成交流水，使分标的盈亏与持仓一致
+    if let Err(e) = sync_income(exchange, config, performance_tracker).await {
+        warn!("资金流水同步失败: {:#}", e);
+    }
+    // 每周期统一查询一次持仓，缓存的持仓未实现盈亏已过时
+    let positions = match exchange
+        .get_portfolio_positions(&config.trade_symbols)
+        .await
+    {
+        Ok(positions) => {
+            for (symbol, book) in &positions {
+                symbols_cache
+                    .entry(symbol.clone())
+                    .or_insert_with(SymbolCacheEntry::empty)
+                    .update(book.clone(), true);
+            }
+            Some(positions)
+        }
+        Err(e) => {
+            warn!("查询组合持仓失败: {:#}", e);
+            None
+        }
+    };
+    // 熔断与权益按周期末重新查询的钱包余额计算，交易所侧止损止盈成交也计入
+    match exchange.get_account_info().await {
+        Ok(account) => current_account = account,
+        Err(e) => warn!("刷新账户信息失败: {:#}", e),
+    }
+    let wallet_balance: f64 = current_account.totalWalletBalance.parse().unwrap_or(0.0);
+    if let Some(positions) = positions.filter(|_| wallet_balance > 0.0) {
+        let unrealized: f64 = positions
+            .values()
+            .flat_map(|book| book.legs())
+            .map(|pos| pos.unrealized_pnl)
+            .sum();
+        let equity = wallet_balance + unrealized;
+        journal::record_account(&current_account, equity, &positions);
+        performance_tracker.sample_equity(cycle_ms, wallet_balance, &positions)?;
+        performance_tracker.persist()?;
+        info!("权益曲线: {}", performance_tracker.snapshot().metrics);
+
+        let tripped = breaker.update(cycle_ms, equity)?;
+        let (daily_pnl, rolling_pnl) = breaker.pnl();
+        info!(
+            "熔断监控: 日内盈亏 {:+.2} USDT, 滚动窗口自峰值回撤 {:+.2} USDT",
+            daily_pnl, rolling_pnl
+        );
+        if let Some(reason) = tripped {
+            error!("触发熔断，停止开仓: {}", reason);
+            if config.circuit_breaker.flatten_on_halt {
+                let flattened = flatten_portfolio(
+                    exchange,
+                    config,
+                    constraints_map,
+                    &positions,
+                    cycle,
+                    symbols_cache,
+                    performance_tracker,
+                )
+                .await?;
+                executed_trades.extend(flattened);
+            }
+        }
+    } else {
+        warn!("钱包余额或持仓不可用，跳过权益采样");
     }
+    persist_symbol_cache(symbols_cache)?;
+
+    Ok(executed_trades)
+}
+
+// 熔断平仓：市价平掉所有标的的持仓并撤销保护单
+async fn flatten_portfolio(
+    exchange: &dyn Exchange,
+    config: &Config,
+    constraints_map: &std::collections::HashMap<String, executor::SymbolConstraints>,
+    positions: &std::collections::HashMap<String, types::PositionBook>,
+    cycle: i64,
+    symbols_cache: &mut std::collections::HashMap<String, SymbolCacheEntry>,
+    performance_tracker: &mut PerformanceTracker,
+) -> Result<Vec<types::TradeResult>> {
+    let mut trades = Vec::new();
+    for (symbol, book) in positions {
+        if book.is_flat() {
+            continue;
+        }
+        let order_ids = order_tracker::ClientOrderIds::new(cycle, symbol);
+        match executor::flatten_positions(exchange, symbol, book, &order_ids).await {
+            Ok(results) => {
+                for trade in results {
+                    info!("{}: {}", symbol, trade.reason);
+                    state::log_trade(&trade)?;
+                    config.risk_engine.record_order(trade.timestamp);
+                    if let Some(attribution) = performance_tracker.update(&trade) {
+                        state::log_trade_pnl(&attribution)?;
+                    }
+                    trades.push(trade);
+                }
+            }
+            Err(e) => error!("{} 熔断平仓失败: {:#}", symbol, e),
+        }
 
-    Ok(any_traded)
+        let latest = exchange.get_positions(symbol).await.ok();
+        if let (Some(latest), Some(constraints)) = (&latest, constraints_map.get(symbol)) {
+            if let Err(e) = executor::sync_protective_orders(
+                exchange,
+                symbol,
+                latest,
+                None,
+                constraints.tick_size,
+                config.protective_close_position,
+                &order_ids,
+            )
+            .await
+            {
+                warn!("{} 撤销保护单失败: {:#}", symbol, e);
+            }
+        }
+        match latest {
+            Some(latest) => symbols_cache
+                .entry(symbol.clone())
+                .or_insert_with(SymbolCacheEntry::empty)
+                .update(latest, true),
+            None => {
+                symbols_cache.remove(symbol);
+            }
+        }
+    }
+    config.risk_engine.persist()?;
+    performance_tracker.persist()?;
+    Ok(trades)
 }
 
 // Task 7.2 & 7.3: 主函数
@@ -701,39 +1346,112 @@ async fn main() -> Result<()> {
 
     logging::init_logging().context("初始化日志系统失败")?;
 
+    // K线存储维护子命令无需交易配置
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("klines") {
+        return kline_store::run_cli(&args[2..]).await;
+    }
+
     // 加载配置
     let config = Config::from_env().context("配置加载失败")?;
 
-    let symbol_constraints = executor::fetch_symbol_constraints(&config.trade_symbols)
+    if config.exchange_mode == ExchangeMode::Backtest {
+        return backtest::run(&config).await;
+    }
+
+    let mut binance = BinanceExchange::new(&config.binance_api_key, &config.binance_secret)
+        .with_position_mode(config.position_mode);
+    if let Some(dir) = &config.kline_store_dir {
+        info!("K线本地存储: {}", dir);
+        binance = binance.with_kline_store(Arc::new(KlineStore::new(dir)));
+    }
+    if config.market_stream {
+        // 附加分析周期一并订阅，每周期无需再走 REST
+        let intervals: Vec<String> = std::iter::once(config.interval_str())
+            .chain(config.context_timeframes())
+            .map(str::to_string)
+            .collect();
+        info!(
+            "行情 WebSocket: {:?} @ {:?} (含盘口增量流)",
+            config.trade_symbols, intervals
+        );
+        binance = binance
+            .with_market_stream(MarketStream::start(&config.trade_symbols, &intervals))
+            .with_depth_stream(DepthStream::start(&config.trade_symbols));
+    }
+    let binance: Arc<dyn Exchange> = Arc::new(binance);
+    // 模拟盘账户随绩效、熔断等状态一起落盘，重启后从上次的账户继续
+    let paper = match config.exchange_mode {
+        ExchangeMode::Live | ExchangeMode::Backtest => None,
+        ExchangeMode::Paper => {
+            let paper = PaperExchange::live(
+                binance.clone(),
+                PaperConfig::from_env().context("模拟盘配置加载失败")?,
+            )
+            .with_position_mode(config.position_mode);
+            paper.restore().context("恢复模拟盘账户失败")?;
+            Some(Arc::new(paper))
+        }
+    };
+    let exchange: Arc<dyn Exchange> = match &paper {
+        Some(paper) => paper.clone(),
+        None => binance,
+    };
+
+    let mut symbol_constraints = exchange
+        .fetch_symbol_constraints(&config.trade_symbols)
         .await
         .context("拉取交易规则失败")?;
+    load_leverage_brackets(exchange.as_ref(), &mut symbol_constraints).await;
 
     // 启动日志
     info!("============================================================");
     info!("多智能体加密货币自动交易系统 - 投资组合版");
     info!("============================================================");
     info!("交易标的: {:?}", config.trade_symbols);
+    info!("交易所: {}", exchange.name());
+    info!("LLM 后端: {}", config.llm.name());
     info!("组合策略: {}", config.portfolio_mode);
     info!("杠杆倍数: {}x", config.leverage);
+    info!("持仓模式: {}", config.position_mode.label());
+    info!("风控规则: {}", config.risk_engine.limits());
+    info!("熔断规则: {}", config.circuit_breaker);
+    info!("扩展指标: {}", config.indicator_selection);
     for symbol in &config.trade_symbols {
         if let Some(cons) = symbol_constraints.get(symbol) {
             info!(
-                "约束 {}: step={}, minQty={}, minNotional={}, maxQty={:?}",
-                symbol, cons.step_size, cons.min_qty, cons.min_notional, cons.max_qty
+                "约束 {}: step={}, minQty={}, minNotional={}, maxQty={:?}, 杠杆分层 {} 档",
+                symbol,
+                cons.step_size,
+                cons.min_qty,
+                cons.min_notional,
+                cons.max_qty,
+                cons.leverage_brackets.len()
             );
         }
+        if let Some(policy) = config.execution_policies.get(symbol) {
+            info!("执行方式 {}: {:?}", symbol, policy);
+        }
     }
     info!(
         "交易周期: {}秒 ({}分钟)",
         config.trade_interval_secs,
         config.trade_interval_secs / 60
     );
-    info!("API密钥前缀: {}***", &config.binance_api_key[..8]);
+    if !config.analysis_timeframes.is_empty() {
+        info!("附加分析周期: {:?}", config.analysis_timeframes);
+    }
+    if config.exchange_mode == ExchangeMode::Live {
+        info!(
+            "API密钥前缀: {}***",
+            config.binance_api_key.chars().take(8).collect::<String>()
+        );
+    }
     info!("启动中...");
 
     // 环境检查 - 测试Binance连接（使用第一个标的）
     if let Some(first_symbol) = config.trade_symbols.first() {
-        match market::fetch_current_price(first_symbol).await {
+        match exchange.fetch_current_price(first_symbol).await {
             Ok(price) => info!(
                 "Binance API 连接成功, {} 当前价格: ${:.2}",
                 first_symbol, price
@@ -745,25 +1463,27 @@ async fn main() -> Result<()> {
         }
     }
 
-    // 设置持仓模式为双向 (必须在交易前设置)
-    match executor::set_dual_position_mode(&config.binance_api_key, &config.binance_secret).await {
-        Ok(_) => info!("持仓模式设置成功: 双向持仓"),
+    // 按配置设置持仓模式 (必须在交易前设置)
+    match exchange.apply_position_mode().await {
+        Ok(_) => info!("持仓模式设置成功: {}", exchange.position_mode().label()),
         Err(e) => {
             error!("持仓模式设置失败: {:#}", e);
             return Err(e);
         }
     }
 
+    // 对账上次运行中结果未确认的订单
+    let reconciled = match order_tracker::reconcile(exchange.as_ref()).await {
+        Ok(reconciled) => reconciled,
+        Err(e) => {
+            warn!("在途订单对账失败: {:#}", e);
+            Vec::new()
+        }
+    };
+
     // 为所有标的设置杠杆倍数
     for symbol in &config.trade_symbols {
-        match executor::set_leverage(
-            symbol,
-            config.leverage,
-            &config.binance_api_key,
-            &config.binance_secret,
-        )
-        .await
-        {
+        match exchange.set_leverage(symbol, config.leverage).await {
             Ok(_) => info!("{} 杠杆设置成功: {}x", symbol, config.leverage),
             Err(e) => {
                 error!("{} 杠杆设置失败: {:#}", symbol, e);
@@ -774,7 +1494,7 @@ async fn main() -> Result<()> {
 
     // 获取并显示账户信息
     info!("账户状态:");
-    match executor::get_account_info(&config.binance_api_key, &config.binance_secret).await {
+    match exchange.get_account_info().await {
         Ok(account) => {
             info!("总余额: {} USDT", account.totalWalletBalance);
             info!("可用余额: {} USDT", account.availableBalance);
@@ -787,16 +1507,9 @@ async fn main() -> Result<()> {
     // 获取并显示所有标的的持仓
     info!("各标的持仓:");
     for symbol in &config.trade_symbols {
-        match executor::get_position(symbol, &config.binance_api_key, &config.binance_secret).await
-        {
-            Ok(Some(pos)) => {
-                info!(
-                    "{} - {:?}仓 {:.4}, 入场 ${:.2}, 盈亏 {:.2} USDT",
-                    symbol, pos.side, pos.amount, pos.entry_price, pos.unrealized_pnl
-                );
-            }
-            Ok(None) => {
-                info!("{} - 空仓", symbol);
+        match exchange.get_positions(symbol).await {
+            Ok(positions) => {
+                info!("{} - {}", symbol, positions);
             }
             Err(e) => {
                 error!("{} - 获取失败: {:#}", symbol, e);
@@ -805,18 +1518,86 @@ async fn main() -> Result<()> {
     }
 
     // 确定interval字符串
-    let interval_str = match config.trade_interval_secs {
-        60 => "1m",
-        900 => "15m",
-        1800 => "30m",
-        3600 => "1h",
-        _ => "1m",
+    let interval_str = config.interval_str();
+
+    // 恢复缓存状态与绩效统计
+    let mut symbols_cache = match load_symbol_cache() {
+        Ok(cache) => {
+            if !cache.is_empty() {
+                info!("已恢复 {} 个标的的持仓缓存", cache.len());
+            }
+            cache
+        }
+        Err(e) => {
+            warn!("持仓缓存恢复失败，重新获取持仓: {:#}", e);
+            std::collections::HashMap::new()
+        }
     };
+    let mut performance_tracker = if config.performance_rebuild {
+        info!("从 trades.jsonl 重建绩效统计");
+        let tracker = PerformanceTracker::rebuild_from_trades()?;
+        tracker.persist()?;
+        tracker
+    } else {
+        PerformanceTracker::load()
+            .context("恢复绩效数据失败，可设置 PERFORMANCE_REBUILD=true 从交易日志重建")?
+    };
+    let snapshot = performance_tracker.snapshot();
+    if snapshot.total_trades > 0 || performance_tracker.equity_samples() > 0 {
+        info!(
+            "已恢复绩效统计: 交易 {} 笔, 净盈亏 {:.2} USDT, 最大回撤 {:.2} USDT, 权益采样 {} 个",
+            snapshot.total_trades,
+            snapshot.net_pnl,
+            snapshot.max_drawdown,
+            performance_tracker.equity_samples()
+        );
+    }
+
+    // 恢复订单频率计数与熔断状态
+    config.risk_engine.restore().context("恢复风控状态失败")?;
+    let mut breaker =
+        CircuitBreaker::load(config.circuit_breaker.clone()).context("恢复熔断状态失败")?;
+    if let Some(reason) = breaker.halt_reason(exchange.now_millis())? {
+        warn!("当前处于熔断状态，暂停开仓: {}", reason);
+    }
 
-    // 初始化缓存状态
-    let mut symbols_cache: std::collections::HashMap<String, SymbolCacheEntry> =
-        std::collections::HashMap::new();
-    let mut performance_tracker = PerformanceTracker::new();
+    // 对账确认的成交计入交易记录、绩效与订单频率，并重新查询其持仓
+    let reconciled_trades: Vec<types::TradeResult> = reconciled
+        .iter()
+        .filter_map(order_tracker::ReconciledOrder::trade_result)
+        .collect();
+    for trade in &reconciled_trades {
+        info!(
+            "在途订单对账成交: {} {:?} {:.4} @ {:.4}",
+            trade.symbol, trade.action, trade.amount, trade.price
+        );
+        symbols_cache.remove(&trade.symbol);
+        state::log_trade(trade)?;
+        if let Some(attribution) = performance_tracker.update(trade) {
+            state::log_trade_pnl(&attribution)?;
+        }
+        config.risk_engine.record_order(trade.timestamp);
+    }
+    if !reconciled_trades.is_empty() {
+        persist_symbol_cache(&symbols_cache)?;
+        performance_tracker.persist()?;
+        config.risk_engine.persist()?;
+    }
+    #[cfg(unix)]
+    {
+        // SIGUSR1 触发手动熔断（写入熔断文件，删除后恢复）
+        let breaker_config = config.circuit_breaker.clone();
+        let mut sigusr1 =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1())
+                .context("注册 SIGUSR1 信号处理失败")?;
+        tokio::spawn(async move {
+            while sigusr1.recv().await.is_some() {
+                if let Err(e) = circuit_breaker::trip_manually(&breaker_config, "SIGUSR1") {
+                    error!("手动熔断失败: {:#}", e);
+                }
+            }
+        });
+    }
 
     // 主循环
     let mut ticker = interval(Duration::from_secs(config.trade_interval_secs));
@@ -825,21 +1606,33 @@ async fn main() -> Result<()> {
         ticker.tick().await;
 
         match run_portfolio_cycle(
+            exchange.as_ref(),
             &config,
             interval_str,
             &symbol_constraints,
             &mut symbols_cache,
             &mut performance_tracker,
+            &mut breaker,
         )
         .await
         {
-            Ok(_any_traded) => {
+            Ok(_executed_trades) => {
                 // run_portfolio_cycle内部已处理缓存更新
+                journal::finish_cycle(exchange.now_millis(), None);
             }
             Err(e) => {
                 error!("投资组合交易周期失败: {:#}", e);
+                journal::finish_cycle(exchange.now_millis(), Some(&format!("{:#}", e)));
                 // 错误时清空所有缓存
                 symbols_cache.clear();
+                if let Err(e) = persist_symbol_cache(&symbols_cache) {
+                    warn!("持仓缓存保存失败: {:#}", e);
+                }
+            }
+        }
+        if let Some(paper) = &paper {
+            if let Err(e) = paper.persist() {
+                warn!("模拟盘账户保存失败: {:#}", e);
             }
         }
     }
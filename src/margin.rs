@@ -0,0 +1,243 @@
+// 保证金与强平估算：按杠杆计算初始保证金，按币安杠杆分层 (/fapi/v1/leverageBracket)
+// 的维持保证金率估算强平价，并限制开仓数量使强平价与标记价格保持 k 倍 ATR 以上的距离
+
+use crate::executor::quantize_down;
+use crate::types::{Position, PositionSide};
+use serde::{Deserialize, Serialize};
+
+// 未获取到杠杆分层时采用的维持保证金率（偏保守）
+const DEFAULT_MAINT_MARGIN_RATIO: f64 = 0.01;
+
+// 杠杆分层：名义价值落在 [notional_floor, notional_cap) 时适用的维持保证金率与速算数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeverageBracket {
+    pub bracket: u32,
+    pub initial_leverage: u32,
+    pub notional_cap: f64,
+    pub notional_floor: f64,
+    pub maint_margin_ratio: f64,
+    pub cum: f64, // 维持保证金速算数
+}
+
+// 给定名义价值的 (维持保证金率, 速算数)
+fn maintenance(brackets: &[LeverageBracket], notional: f64) -> (f64, f64) {
+    brackets
+        .iter()
+        .find(|b| notional >= b.notional_floor && notional < b.notional_cap)
+        .or_else(|| brackets.last())
+        .map_or((DEFAULT_MAINT_MARGIN_RATIO, 0.0), |b| {
+            (b.maint_margin_ratio, b.cum)
+        })
+}
+
+// 当前杠杆下开仓所需的初始保证金
+pub fn initial_margin(notional: f64, leverage: u32) -> f64 {
+    notional / leverage.max(1) as f64
+}
+
+// 单仓强平价估算：collateral 为该仓位可承受亏损的保证金
+// 多: LP = (Q·EP - C - cum) / (Q·(1 - MMR))，空: LP = (Q·EP + C + cum) / (Q·(1 + MMR))
+pub fn liquidation_price(
+    side: &PositionSide,
+    entry_price: f64,
+    quantity: f64,
+    collateral: f64,
+    brackets: &[LeverageBracket],
+) -> Option<f64> {
+    if quantity <= 0.0 || entry_price <= 0.0 {
+        return None;
+    }
+    let (mmr, cum) = maintenance(brackets, quantity * entry_price);
+    let price = match side {
+        PositionSide::Long => {
+            (quantity * entry_price - collateral - cum) / (quantity * (1.0 - mmr))
+        }
+        PositionSide::Short => {
+            (quantity * entry_price + collateral + cum) / (quantity * (1.0 + mmr))
+        }
+    };
+    // 多头强平价不为正表示保证金足以覆盖价格归零
+    (price > 0.0).then_some(price)
+}
+
+#[derive(Debug, Clone)]
+pub struct LiquidationSizing {
+    pub amount: f64,                    // 满足强平距离的最大新增数量（已按步长截断）
+    pub liquidation_price: Option<f64>, // 按该数量成交后的估算强平价
+    pub min_distance: f64,              // 要求的强平价与标记价格最小距离 (k × ATR)
+}
+
+// 在 [0, amount] 中寻找成交后强平价距标记价格不小于 min_distance 的最大数量；
+// 已有同向持仓时按加权均价与合并数量计算，保证金为该腿已占用保证金加新增数量的初始保证金
+#[allow(clippy::too_many_arguments)]
+pub fn size_for_liquidation(
+    side: &PositionSide,
+    held: Option<&Position>,
+    price: f64,
+    mark_price: f64,
+    amount: f64,
+    leverage: u32,
+    min_distance: f64,
+    brackets: &[LeverageBracket],
+    step_size: f64,
+) -> LiquidationSizing {
+    let (held_amount, held_cost, held_margin) = held.map_or((0.0, 0.0, 0.0), |pos| {
+        (pos.amount, pos.amount * pos.entry_price, pos.margin)
+    });
+    let estimate = |qty: f64| {
+        let total = held_amount + qty;
+        if total <= 0.0 {
+            return None;
+        }
+        let entry = (held_cost + qty * price) / total;
+        let collateral = held_margin + initial_margin(qty * price, leverage);
+        liquidation_price(side, entry, total, collateral, brackets)
+    };
+    // 强平按标记价格触发，距离以当前标记价格计算
+    let safe = |qty: f64| estimate(qty).is_none_or(|lp| (mark_price - lp).abs() >= min_distance);
+
+    let sized = if safe(amount) {
+        amount
+    } else {
+        // 数量越大强平价越近，二分查找满足条件的上限
+        let (mut low, mut high) = (0.0, amount);
+        for _ in 0..50 {
+            let mid = (low + high) / 2.0;
+            if safe(mid) {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        quantize_down(low, step_size)
+    };
+    LiquidationSizing {
+        amount: sized,
+        liquidation_price: estimate(sized),
+        min_distance,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 币安 BTCUSDT 杠杆分层的前两档：名义价值 5 万以下维持保证金率 0.4%，5 万至 25 万 0.5%、速算数 50
+    fn btc_brackets() -> Vec<LeverageBracket> {
+        vec![
+            LeverageBracket {
+                bracket: 1,
+                initial_leverage: 125,
+                notional_cap: 50_000.0,
+                notional_floor: 0.0,
+                maint_margin_ratio: 0.004,
+                cum: 0.0,
+            },
+            LeverageBracket {
+                bracket: 2,
+                initial_leverage: 100,
+                notional_cap: 250_000.0,
+                notional_floor: 50_000.0,
+                maint_margin_ratio: 0.005,
+                cum: 50.0,
+            },
+        ]
+    }
+
+    fn long(amount: f64, entry_price: f64, margin: f64) -> Position {
+        Position {
+            side: PositionSide::Long,
+            amount,
+            entry_price,
+            unrealized_pnl: 0.0,
+            mark_price: entry_price,
+            liquidation_price: 0.0,
+            margin,
+            leverage: 20,
+        }
+    }
+
+    // 预期值按币安逐仓强平价公式
+    // LP = (WB + cum - Side·Q·EP) / (Q·MMR - Side·Q)（单仓、无其他持仓与未实现盈亏）手算得出
+    #[test]
+    fn liquidation_price_matches_binance_formula() {
+        let brackets = btc_brackets();
+        // 多 1 BTC @ 10000，10 倍逐仓保证金 1000：(1000 - 10000) / (0.004 - 1)
+        let lp = liquidation_price(&PositionSide::Long, 10_000.0, 1.0, 1_000.0, &brackets).unwrap();
+        assert!((lp - 9_036.144_578_313_253).abs() < 1e-6);
+        // 空 1 BTC @ 10000：(1000 + 10000) / (0.004 + 1)
+        let lp =
+            liquidation_price(&PositionSide::Short, 10_000.0, 1.0, 1_000.0, &brackets).unwrap();
+        assert!((lp - 10_956.175_298_804_78).abs() < 1e-6);
+        // 多 10 BTC @ 10000，名义 10 万落在第二档，20 倍保证金 5000：
+        // (5000 + 50 - 100000) / (0.05 - 10)
+        let lp =
+            liquidation_price(&PositionSide::Long, 10_000.0, 10.0, 5_000.0, &brackets).unwrap();
+        assert!((lp - 9_542.713_567_839_196).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fully_collateralized_long_has_no_liquidation() {
+        let lp = liquidation_price(
+            &PositionSide::Long,
+            10_000.0,
+            1.0,
+            10_000.0,
+            &btc_brackets(),
+        );
+        assert!(lp.is_none());
+    }
+
+    #[test]
+    fn sizing_merges_existing_leg_margin() {
+        let brackets = btc_brackets();
+        let held = long(1.0, 10_000.0, 500.0);
+        let sizing = size_for_liquidation(
+            &PositionSide::Long,
+            Some(&held),
+            11_000.0,
+            11_000.0,
+            1.0,
+            20,
+            600.0,
+            &brackets,
+            0.001,
+        );
+        // 合并后 2 BTC @ 10500，保证金 500 + 550：(1050 - 21000) / (2 × (0.004 - 1))
+        assert_eq!(sizing.amount, 1.0);
+        let lp = sizing.liquidation_price.unwrap();
+        assert!((lp - 10_015.060_240_963_856).abs() < 1e-6);
+
+        // 已有腿保证金更多时强平价更远，可加仓数量不减少
+        let richer = long(1.0, 10_000.0, 2_000.0);
+        let tight = size_for_liquidation(
+            &PositionSide::Long,
+            Some(&held),
+            11_000.0,
+            11_000.0,
+            1.0,
+            20,
+            1_200.0,
+            &brackets,
+            0.001,
+        );
+        let loose = size_for_liquidation(
+            &PositionSide::Long,
+            Some(&richer),
+            11_000.0,
+            11_000.0,
+            1.0,
+            20,
+            1_200.0,
+            &brackets,
+            0.001,
+        );
+        assert!(tight.amount < 1.0);
+        assert!(loose.amount > tight.amount);
+        for sizing in [&tight, &loose] {
+            let lp = sizing.liquidation_price.unwrap();
+            assert!(11_000.0 - lp >= 1_200.0);
+        }
+    }
+}
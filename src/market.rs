@@ -1,4 +1,5 @@
-use crate::types::{Kline, TechnicalIndicators};
+use crate::indicators;
+use crate::types::{Kline, TechnicalIndicators, TimeframeContext, TimeframeTrend};
 use anyhow::{Context, Result};
 use serde::Deserialize;
 use std::env;
@@ -13,6 +14,7 @@ fn get_binance_base_url() -> String {
 
 // Binance API K线响应格式
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)] // 完整映射接口字段，部分暂未使用
 struct BinanceKline(
     i64,    // 开盘时间
     String, // 开盘价
@@ -28,6 +30,19 @@ struct BinanceKline(
     String, // 忽略
 );
 
+fn convert_kline(k: BinanceKline) -> Kline {
+    Kline {
+        timestamp: k.0,
+        open: k.1.parse().unwrap_or(0.0),
+        high: k.2.parse().unwrap_or(0.0),
+        low: k.3.parse().unwrap_or(0.0),
+        close: k.4.parse().unwrap_or(0.0),
+        volume: k.5.parse().unwrap_or(0.0),
+        taker_buy_volume: k.9.parse().unwrap_or(0.0),
+        taker_buy_quote_volume: k.10.parse().unwrap_or(0.0),
+    }
+}
+
 // Task 3.1: 获取K线数据
 pub async fn fetch_klines(symbol: &str, interval: &str, limit: u32) -> Result<Vec<Kline>> {
     let base_url = get_binance_base_url();
@@ -44,17 +59,7 @@ pub async fn fetch_klines(symbol: &str, interval: &str, limit: u32) -> Result<Ve
             Ok(response) => {
                 let klines: Vec<BinanceKline> = response.json().await.context("解析K线数据失败")?;
 
-                return Ok(klines
-                    .into_iter()
-                    .map(|k| Kline {
-                        timestamp: k.0,
-                        open: k.1.parse().unwrap_or(0.0),
-                        high: k.2.parse().unwrap_or(0.0),
-                        low: k.3.parse().unwrap_or(0.0),
-                        close: k.4.parse().unwrap_or(0.0),
-                        volume: k.5.parse().unwrap_or(0.0),
-                    })
-                    .collect());
+                return Ok(klines.into_iter().map(convert_kline).collect());
             }
             Err(e) => {
                 retries += 1;
@@ -67,6 +72,77 @@ pub async fn fetch_klines(symbol: &str, interval: &str, limit: u32) -> Result<Ve
     }
 }
 
+// K线周期对应的毫秒数
+pub fn interval_to_millis(interval: &str) -> Result<i64> {
+    let (value, unit) = interval.split_at(interval.len().saturating_sub(1));
+    let value: i64 = value
+        .parse()
+        .with_context(|| format!("无法识别的K线周期: {}", interval))?;
+    let unit_ms = match unit {
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        "w" => 7 * 86_400_000,
+        _ => anyhow::bail!("无法识别的K线周期: {}", interval),
+    };
+    Ok(value * unit_ms)
+}
+
+// 解析时间参数，支持 RFC3339 或 YYYY-MM-DD（按UTC零点），返回毫秒时间戳
+pub fn parse_time(value: &str) -> Result<i64> {
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(dt.timestamp_millis());
+    }
+    let date = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .with_context(|| format!("无法解析时间: {}", value))?;
+    Ok(date
+        .and_hms_opt(0, 0, 0)
+        .context("无效日期")?
+        .and_utc()
+        .timestamp_millis())
+}
+
+// 按时间区间分页拉取K线 [start_ms, end_ms]，按开盘时间升序
+pub async fn fetch_klines_range(
+    symbol: &str,
+    interval: &str,
+    start_ms: i64,
+    end_ms: i64,
+) -> Result<Vec<Kline>> {
+    const PAGE_LIMIT: usize = 1500;
+
+    let base_url = get_binance_base_url();
+    let step = interval_to_millis(interval)?;
+    let mut cursor = start_ms;
+    let mut result: Vec<Kline> = Vec::new();
+
+    while cursor <= end_ms {
+        let url = format!(
+            "{}/fapi/v1/klines?symbol={}&interval={}&startTime={}&endTime={}&limit={}",
+            base_url, symbol, interval, cursor, end_ms, PAGE_LIMIT
+        );
+        let page: Vec<BinanceKline> = reqwest::get(&url)
+            .await
+            .with_context(|| format!("获取 {} 历史K线失败", symbol))?
+            .json()
+            .await
+            .context("解析K线数据失败")?;
+
+        let page_len = page.len();
+        let Some(last) = page.last() else {
+            break;
+        };
+        cursor = last.0 + step;
+        result.extend(page.into_iter().map(convert_kline));
+
+        if page_len < PAGE_LIMIT {
+            break;
+        }
+    }
+
+    Ok(result)
+}
+
 // Task 3.2: 计算技术指标
 pub fn calculate_indicators(klines: &[Kline]) -> Result<TechnicalIndicators> {
     if klines.len() < 5 {
@@ -114,30 +190,8 @@ pub fn calculate_indicators(klines: &[Kline]) -> Result<TechnicalIndicators> {
         volumes[len - 1] / avg_volume
     };
 
-    let atr_period = 14.min(len.saturating_sub(1));
-    let mut atr_sum = 0.0;
-    let mut atr_count = 0;
-    for i in len - atr_period..len {
-        let current = &klines[i];
-        let prev_close = if i == 0 {
-            current.close
-        } else {
-            klines[i - 1].close
-        };
-
-        let tr = (current.high - current.low)
-            .max((current.high - prev_close).abs())
-            .max((current.low - prev_close).abs());
-
-        atr_sum += tr;
-        atr_count += 1;
-    }
-
-    let atr_14 = if atr_count > 0 {
-        atr_sum / atr_count as f64
-    } else {
-        0.0
-    };
+    // Wilder ATR，K线不足 15 根时缩短周期
+    let atr_14 = indicators::atr(klines, 14.min(len - 1)).unwrap_or(0.0);
 
     let latest_close = closes[len - 1];
     let atr_percent = if latest_close.abs() < f64::EPSILON {
@@ -161,6 +215,39 @@ pub fn calculate_indicators(klines: &[Kline]) -> Result<TechnicalIndicators> {
     })
 }
 
+// 单个附加周期的指标与趋势摘要
+pub fn timeframe_context(interval: &str, klines: &[Kline]) -> Result<TimeframeContext> {
+    let indicators = calculate_indicators(klines)?;
+    let close = klines.last().map_or(0.0, |k| k.close);
+    let range_high = klines.iter().map(|k| k.high).fold(f64::MIN, f64::max);
+    let range_low = klines.iter().map(|k| k.low).fold(f64::MAX, f64::min);
+    let trend = if close > indicators.sma_20 && indicators.sma_20 > indicators.sma_50 {
+        TimeframeTrend::Bullish
+    } else if close < indicators.sma_20 && indicators.sma_20 < indicators.sma_50 {
+        TimeframeTrend::Bearish
+    } else {
+        TimeframeTrend::Mixed
+    };
+    Ok(TimeframeContext {
+        interval: interval.to_string(),
+        trend,
+        close,
+        range_high,
+        range_low,
+        range_position: if range_high > range_low {
+            (close - range_low) / (range_high - range_low)
+        } else {
+            0.5
+        },
+        close_vs_sma_50: if indicators.sma_50.abs() < f64::EPSILON {
+            0.0
+        } else {
+            (close - indicators.sma_50) / indicators.sma_50 * 100.0
+        },
+        indicators,
+    })
+}
+
 // Task 3.3: 获取当前价格
 #[derive(Debug, Deserialize)]
 struct PriceResponse {
@@ -180,3 +267,55 @@ pub async fn fetch_current_price(symbol: &str) -> Result<f64> {
 
     response.price.parse().context("价格字符串转换失败")
 }
+
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct PremiumIndexResponse {
+    markPrice: String,
+}
+
+// 标记价格（强平按标记价格触发）
+pub async fn fetch_mark_price(symbol: &str) -> Result<f64> {
+    let base_url = get_binance_base_url();
+    let url = format!("{}/fapi/v1/premiumIndex?symbol={}", base_url, symbol);
+
+    let response: PremiumIndexResponse = reqwest::get(&url)
+        .await
+        .context("获取标记价格失败")?
+        .json()
+        .await
+        .context("解析标记价格失败")?;
+
+    response.markPrice.parse().context("标记价格转换失败")
+}
+
+// 最优买卖价
+#[derive(Debug, Clone, Copy)]
+pub struct BookTicker {
+    pub bid_price: f64,
+    pub ask_price: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct BookTickerResponse {
+    bidPrice: String,
+    askPrice: String,
+}
+
+pub async fn fetch_book_ticker(symbol: &str) -> Result<BookTicker> {
+    let base_url = get_binance_base_url();
+    let url = format!("{}/fapi/v1/ticker/bookTicker?symbol={}", base_url, symbol);
+
+    let response: BookTickerResponse = reqwest::get(&url)
+        .await
+        .context("获取盘口失败")?
+        .json()
+        .await
+        .context("解析盘口数据失败")?;
+
+    Ok(BookTicker {
+        bid_price: response.bidPrice.parse().context("买一价转换失败")?,
+        ask_price: response.askPrice.parse().context("卖一价转换失败")?,
+    })
+}
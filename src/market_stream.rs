@@ -0,0 +1,350 @@
+// 行情 WebSocket：订阅 <symbol>@kline_<interval>、@markPrice 与 @bookTicker 组合流，
+// 在内存中维护滚动K线缓冲与最新价格；断线按退避重连，连上后用 REST 补齐缺口。
+// 流数据过期或缓冲不足时由调用方回退到 REST
+
+use crate::market::{self, BookTicker};
+use crate::types::Kline;
+use anyhow::{anyhow, Context, Result};
+use futures::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::time::{sleep, timeout, Duration};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+const BUFFER_CAPACITY: usize = 500; // 每个标的/周期保留的K线数量
+const STALE_AFTER: Duration = Duration::from_secs(30); // 超过该时间无推送视为过期
+const READ_TIMEOUT: Duration = Duration::from_secs(60); // 无任何消息时主动重连
+
+fn stream_base_url() -> String {
+    match env::var("BINANCE_TESTNET").as_deref() {
+        Ok("true") => "wss://stream.binancefuture.com/stream".to_string(),
+        _ => "wss://fstream.binance.com/stream".to_string(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CombinedMessage {
+    data: StreamEvent,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "e")]
+enum StreamEvent {
+    #[serde(rename = "kline")]
+    Kline {
+        #[serde(rename = "s")]
+        symbol: String,
+        #[serde(rename = "k")]
+        kline: KlinePayload,
+    },
+    #[serde(rename = "markPriceUpdate")]
+    MarkPrice {
+        #[serde(rename = "s")]
+        symbol: String,
+        #[serde(rename = "p")]
+        price: String,
+    },
+    #[serde(rename = "bookTicker")]
+    BookTicker {
+        #[serde(rename = "s")]
+        symbol: String,
+        #[serde(rename = "b")]
+        bid: String,
+        #[serde(rename = "a")]
+        ask: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct KlinePayload {
+    t: i64,    // 开盘时间
+    i: String, // 周期
+    o: String,
+    h: String,
+    l: String,
+    c: String,
+    v: String,
+    #[serde(rename = "V")]
+    taker_buy_volume: String,
+    #[serde(rename = "Q")]
+    taker_buy_quote_volume: String,
+}
+
+impl KlinePayload {
+    fn to_kline(&self) -> Kline {
+        Kline {
+            timestamp: self.t,
+            open: self.o.parse().unwrap_or(0.0),
+            high: self.h.parse().unwrap_or(0.0),
+            low: self.l.parse().unwrap_or(0.0),
+            close: self.c.parse().unwrap_or(0.0),
+            volume: self.v.parse().unwrap_or(0.0),
+            taker_buy_volume: self.taker_buy_volume.parse().unwrap_or(0.0),
+            taker_buy_quote_volume: self.taker_buy_quote_volume.parse().unwrap_or(0.0),
+        }
+    }
+}
+
+#[derive(Default)]
+struct SymbolMarket {
+    series: HashMap<String, BTreeMap<i64, Kline>>, // 周期 -> 开盘时间 -> K线（含未收盘K线）
+    last_price: Option<f64>,
+    mark_price: Option<f64>,
+    book: Option<BookTicker>,
+}
+
+#[derive(Default)]
+struct StreamState {
+    symbols: HashMap<String, SymbolMarket>,
+    connected: bool,               // 已连接且完成缺口补齐
+    last_message: Option<Instant>, // 最近一次推送时间
+}
+
+impl StreamState {
+    fn fresh(&self) -> bool {
+        self.connected
+            && self
+                .last_message
+                .is_some_and(|time| time.elapsed() < STALE_AFTER)
+    }
+
+    fn merge(&mut self, symbol: &str, interval: &str, klines: Vec<Kline>) {
+        let series = self
+            .symbols
+            .entry(symbol.to_string())
+            .or_default()
+            .series
+            .entry(interval.to_string())
+            .or_default();
+        for kline in klines {
+            series.insert(kline.timestamp, kline);
+        }
+        while series.len() > BUFFER_CAPACITY {
+            series.pop_first();
+        }
+    }
+}
+
+pub struct MarketStream {
+    symbols: Vec<String>,
+    intervals: Vec<String>,
+    state: Mutex<StreamState>,
+}
+
+impl MarketStream {
+    // 启动后台订阅任务，首次连接前缓冲为空，读取时回退到 REST
+    pub fn start(symbols: &[String], intervals: &[String]) -> Arc<Self> {
+        let stream = Arc::new(MarketStream {
+            symbols: symbols.to_vec(),
+            intervals: intervals.to_vec(),
+            state: Mutex::new(StreamState::default()),
+        });
+        let worker = stream.clone();
+        tokio::spawn(async move {
+            worker.run().await;
+        });
+        stream
+    }
+
+    // 最近 limit 根K线；流未就绪、已过期、缓冲不足或不连续时返回 None
+    pub fn klines(&self, symbol: &str, interval: &str, limit: u32) -> Option<Vec<Kline>> {
+        let step = market::interval_to_millis(interval).ok()?;
+        let state = self.state.lock().unwrap();
+        if !state.fresh() {
+            return None;
+        }
+        let series = state.symbols.get(symbol)?.series.get(interval)?;
+        if series.len() < limit as usize {
+            return None;
+        }
+        let klines: Vec<Kline> = series
+            .values()
+            .skip(series.len() - limit as usize)
+            .cloned()
+            .collect();
+        let contiguous = klines
+            .windows(2)
+            .all(|pair| pair[1].timestamp - pair[0].timestamp == step);
+        contiguous.then_some(klines)
+    }
+
+    // 最新成交价（来自K线流的实时收盘价）
+    pub fn last_price(&self, symbol: &str) -> Option<f64> {
+        self.read(symbol, |market| market.last_price)
+    }
+
+    pub fn mark_price(&self, symbol: &str) -> Option<f64> {
+        self.read(symbol, |market| market.mark_price)
+    }
+
+    pub fn book_ticker(&self, symbol: &str) -> Option<BookTicker> {
+        self.read(symbol, |market| market.book)
+    }
+
+    fn read<T>(&self, symbol: &str, f: impl FnOnce(&SymbolMarket) -> Option<T>) -> Option<T> {
+        let state = self.state.lock().unwrap();
+        if !state.fresh() {
+            return None;
+        }
+        state.symbols.get(symbol).and_then(f)
+    }
+
+    async fn run(&self) {
+        let mut backoff = Duration::from_secs(5);
+        loop {
+            match self.connect_and_stream().await {
+                Ok(()) => {
+                    debug!("行情 WebSocket 流结束，准备重连");
+                    backoff = Duration::from_secs(5);
+                    sleep(Duration::from_secs(1)).await;
+                }
+                Err(err) => {
+                    warn!(
+                        "行情 WebSocket 流异常，{} 秒后重连: {:#}",
+                        backoff.as_secs(),
+                        err
+                    );
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(Duration::from_secs(60));
+                }
+            }
+        }
+    }
+
+    async fn connect_and_stream(&self) -> Result<()> {
+        let mut streams = Vec::new();
+        for symbol in &self.symbols {
+            let lower = symbol.to_lowercase();
+            for interval in &self.intervals {
+                streams.push(format!("{}@kline_{}", lower, interval));
+            }
+            streams.push(format!("{}@markPrice@1s", lower));
+            streams.push(format!("{}@bookTicker", lower));
+        }
+        let url = format!("{}?streams={}", stream_base_url(), streams.join("/"));
+        let (mut ws_stream, _) = connect_async(&url)
+            .await
+            .with_context(|| format!("连接行情 WebSocket 失败: {}", url))?;
+
+        // 连接建立后再补齐，确保补齐数据与推送之间没有空档
+        let result = async {
+            for symbol in &self.symbols {
+                for interval in &self.intervals {
+                    self.gap_fill(symbol, interval).await?;
+                }
+            }
+            {
+                let mut state = self.state.lock().unwrap();
+                state.connected = true;
+                state.last_message = Some(Instant::now());
+            }
+            info!("行情 WebSocket 已连接: {} 个订阅", streams.len());
+
+            loop {
+                let message = timeout(READ_TIMEOUT, ws_stream.next())
+                    .await
+                    .map_err(|_| anyhow!("行情 WebSocket {} 秒无消息", READ_TIMEOUT.as_secs()))?;
+                match message {
+                    Some(Ok(Message::Text(text))) => self.handle_text(&text).await?,
+                    Some(Ok(Message::Ping(payload))) => {
+                        ws_stream.send(Message::Pong(payload)).await?;
+                    }
+                    Some(Ok(Message::Close(frame))) => {
+                        return Err(anyhow!("行情 WebSocket 主动关闭: {:?}", frame));
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(err)) => return Err(err.into()),
+                    None => return Ok(()),
+                }
+            }
+        }
+        .await;
+
+        self.state.lock().unwrap().connected = false;
+        result
+    }
+
+    async fn handle_text(&self, text: &str) -> Result<()> {
+        let Ok(message) = serde_json::from_str::<CombinedMessage>(text) else {
+            return Ok(());
+        };
+        match message.data {
+            StreamEvent::Kline { symbol, kline } => {
+                let step = market::interval_to_millis(&kline.i)?;
+                let kline_data = kline.to_kline();
+                let gap = {
+                    let mut state = self.state.lock().unwrap();
+                    state.last_message = Some(Instant::now());
+                    let market = state.symbols.entry(symbol.clone()).or_default();
+                    market.last_price = Some(kline_data.close);
+                    let gap = market
+                        .series
+                        .get(&kline.i)
+                        .and_then(|series| series.keys().next_back())
+                        .is_some_and(|last| kline.t > last + step);
+                    state.merge(&symbol, &kline.i, vec![kline_data]);
+                    gap
+                };
+                if gap {
+                    warn!("{} {} K线推送出现缺口，使用 REST 补齐", symbol, kline.i);
+                    self.gap_fill(&symbol, &kline.i).await?;
+                }
+            }
+            StreamEvent::MarkPrice { symbol, price } => {
+                let mut state = self.state.lock().unwrap();
+                state.last_message = Some(Instant::now());
+                if let Ok(price) = price.parse() {
+                    state.symbols.entry(symbol).or_default().mark_price = Some(price);
+                }
+            }
+            StreamEvent::BookTicker { symbol, bid, ask } => {
+                let mut state = self.state.lock().unwrap();
+                state.last_message = Some(Instant::now());
+                if let (Ok(bid_price), Ok(ask_price)) = (bid.parse(), ask.parse()) {
+                    state.symbols.entry(symbol).or_default().book = Some(BookTicker {
+                        bid_price,
+                        ask_price,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 按缓冲中最后一根K线到当前的时间估算缺失数量，从 REST 拉取并合并
+    async fn gap_fill(&self, symbol: &str, interval: &str) -> Result<()> {
+        let step = market::interval_to_millis(interval)?;
+        let last = {
+            let state = self.state.lock().unwrap();
+            state
+                .symbols
+                .get(symbol)
+                .and_then(|market| market.series.get(interval))
+                .and_then(|series| series.keys().next_back().copied())
+        };
+        // 缺口超过缓冲容量时整段重建
+        let missing = last.map(|last| (chrono::Utc::now().timestamp_millis() - last) / step + 2);
+        let limit = match missing {
+            Some(missing) if missing < BUFFER_CAPACITY as i64 => missing.max(2),
+            _ => BUFFER_CAPACITY as i64,
+        };
+        let klines = market::fetch_klines(symbol, interval, limit as u32)
+            .await
+            .with_context(|| format!("补齐 {} {} K线失败", symbol, interval))?;
+        let mut state = self.state.lock().unwrap();
+        let market = state.symbols.entry(symbol.to_string()).or_default();
+        if limit == BUFFER_CAPACITY as i64 {
+            market.series.remove(interval);
+        }
+        if let Some(latest) = klines.last() {
+            market.last_price = Some(latest.close);
+        }
+        state.merge(symbol, interval, klines);
+        Ok(())
+    }
+}
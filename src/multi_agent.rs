@@ -1,17 +1,12 @@
 // 多智能体交易决策系统
 
+use crate::depth::DepthMetrics;
+use crate::derivatives::DerivativesSnapshot;
 use crate::executor::{AccountInfo, SymbolConstraints};
+use crate::llm::{AgentRole, LlmBackend, LlmRequest};
 use crate::types::*;
 use anyhow::{Context, Result};
-use async_openai::{
-    config::OpenAIConfig,
-    types::{
-        ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
-        ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs,
-    },
-    Client,
-};
-use serde_json::json;
+use serde_json::{json, Value};
 
 // ========== 1. 行情分析员 (Market Analyst) ==========
 
@@ -73,12 +68,14 @@ fn get_market_analyst_system_prompt() -> &'static str {
 * 当前市场处于哪个阶段？（积累/上升/分配/下跌）
 * 主导力量是多头、空头还是震荡？
 * 价格走势与历史结构的关系如何？
+* 附加周期（higher_timeframes）的趋势与区间位置是什么？当前周期信号是顺势还是逆势？
 
 **第二层：趋势方向与强度**
 
 * 趋势方向：多头(bullish)/空头(bearish)/中性(neutral)
 * 趋势强度：强(strong)/中(medium)/弱(weak)
 * 均线排列、动量指标、成交量是否确认趋势？
+* 与更高周期趋势相反的信号（如日线明确下跌中的 1m 反弹）应降低强度或判为中性
 
 **第三层：关键价格定位**
 
@@ -91,6 +88,10 @@ fn get_market_analyst_system_prompt() -> &'static str {
 * 均线排列说明什么？（多头排列/空头排列/缠绕）
 * 价格动量显示什么信号？（加速/减速/背离）
 * 成交量是否确认趋势？（放量/缩量/异常）
+* 扩展指标（technicals：EMA、RSI、MACD、布林带、ADX、KD、VWAP、OBV、唐奇安通道）是否共振？有无超买超卖或背离？
+* 盘口价差与买卖失衡（order_book）是否支持该方向？
+* 主动买入占比（taker_buy_ratio）与主动买卖比是否确认量能方向？
+* 资金费率、基差与持仓量变化（derivatives）是否显示多空拥挤或增仓/减仓驱动？
 
 ---
 
@@ -124,36 +125,38 @@ fn structured_prompt(
     ))
 }
 
+#[allow(clippy::too_many_arguments)]
 fn build_risk_manager_prompt(
     symbol: &str,
     market_report: &MarketReport,
     strategy: &StrategyAdvice,
     account: &AccountInfo,
-    position: &Option<Position>,
+    positions: &PositionBook,
     constraints: &SymbolConstraints,
     allocated_balance: f64,
     allocated_max_amount: f64,
     max_position: f64,
+    depth: Option<&DepthMetrics>,
+    technicals: Option<&Value>,
 ) -> Result<String> {
     let available_balance = account.availableBalance.parse::<f64>().unwrap_or(0.0);
     let total_balance = account.totalWalletBalance.parse::<f64>().unwrap_or(0.0);
     let used_balance = (total_balance - available_balance).max(0.0);
-    let position_value = position
-        .as_ref()
-        .map(|pos| pos.amount * pos.entry_price)
-        .unwrap_or(0.0);
+    let position_value = positions.notional();
 
     let payload = json!({
         "symbol": symbol,
         "market_report": market_report,
         "strategy": strategy,
-        "position": position,
+        "positions": positions,
         "account": {
             "available_balance": available_balance,
             "total_balance": total_balance,
             "used_balance": used_balance
         },
         "position_value": position_value,
+        "order_book": depth,
+        "technicals": technicals,
         "limits": {
             "step_size": constraints.step_size,
             "min_qty": constraints.min_qty,
@@ -166,7 +169,7 @@ fn build_risk_manager_prompt(
     });
 
     structured_prompt(
-        "输入包含账户资源、当前仓位、策略建议与市场分析（JSON），请评估风险并给出审批结论。",
+        "输入包含账户资源、当前仓位（双向持仓，long/short 两腿可能同时存在）、策略建议与市场分析（JSON），请评估风险并给出审批结论。",
         &payload,
         r#"{
   "risk_level": "low" | "medium" | "high",
@@ -178,11 +181,16 @@ fn build_risk_manager_prompt(
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 fn build_market_analyst_prompt(
     symbol: &str,
     interval: &str,
     klines: &[Kline],
     indicators: &TechnicalIndicators,
+    technicals: Option<&Value>,
+    timeframes: &[TimeframeContext],
+    depth: Option<&DepthMetrics>,
+    derivatives: Option<&DerivativesSnapshot>,
 ) -> Result<String> {
     let latest = klines.last().context("缺少最新K线数据")?;
     let recent: Vec<_> = klines
@@ -197,6 +205,12 @@ fn build_market_analyst_prompt(
     let kline_payload: Vec<_> = recent
         .into_iter()
         .map(|k| {
+            // 主动买入量占成交量的比例
+            let taker_buy_ratio = if k.volume > 0.0 {
+                k.taker_buy_volume / k.volume
+            } else {
+                0.0
+            };
             json!({
                 "timestamp": k.timestamp,
                 "open": k.open,
@@ -204,6 +218,7 @@ fn build_market_analyst_prompt(
                 "low": k.low,
                 "close": k.close,
                 "volume": k.volume,
+                "taker_buy_ratio": taker_buy_ratio,
             })
         })
         .collect();
@@ -276,6 +291,10 @@ fn build_market_analyst_prompt(
             "atr_percent": indicators.atr_percent,
             "volume_ratio": indicators.volume_ratio
         },
+        "technicals": technicals,
+        "higher_timeframes": timeframes,
+        "order_book": depth,
+        "derivatives": derivatives,
         "recent_klines": kline_payload,
         "long_window": {
             "length": long_window_len,
@@ -301,15 +320,36 @@ fn build_market_analyst_prompt(
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn market_analyst_analyze(
     symbol: &str,
     interval: &str,
     klines: &[Kline],
     indicators: &TechnicalIndicators,
-    api_key: &str,
+    technicals: Option<&Value>,
+    timeframes: &[TimeframeContext],
+    depth: Option<&DepthMetrics>,
+    derivatives: Option<&DerivativesSnapshot>,
+    llm: &dyn LlmBackend,
 ) -> Result<MarketReport> {
-    let prompt = build_market_analyst_prompt(symbol, interval, klines, indicators)?;
-    let response = call_deepseek(get_market_analyst_system_prompt(), &prompt, api_key).await?;
+    let prompt = build_market_analyst_prompt(
+        symbol,
+        interval,
+        klines,
+        indicators,
+        technicals,
+        timeframes,
+        depth,
+        derivatives,
+    )?;
+    let response = call_llm(
+        llm,
+        AgentRole::MarketAnalyst,
+        Some(symbol),
+        get_market_analyst_system_prompt(),
+        &prompt,
+    )
+    .await?;
     parse_json_response(&response)
 }
 
@@ -373,11 +413,14 @@ fn get_strategy_researcher_system_prompt() -> &'static str {
 * 趋势明确且强劲 → 顺势开仓或加仓
 * 趋势反转信号出现 → 平仓观望或反向开仓
 * 趋势不明朗 → 持有当前仓位或观望
+* 资金费率极端或持仓量快速堆积（derivatives）→ 警惕拥挤方向的挤压，降低追单意愿
 
 **第二层：仓位状态与操作逻辑**
 
 * 已有仓位且趋势一致 → 考虑加仓放大收益
-* 已有仓位但趋势反转 → 建议平仓止盈/止损
+* 已有仓位但趋势转弱 → 建议部分减仓 reduce_position 锁定利润/降低风险
+* 已有仓位但趋势反转 → 建议平仓 close_position 止盈/止损，不反向开仓
+* 已有仓位且反向趋势强劲 → 建议反手 reverse：平掉原仓位并按 target_side 反向开仓
 * 空仓且趋势明确 → 建议开仓捕捉机会
 * 空仓且趋势不明 → 继续观望等待信号
 
@@ -386,12 +429,13 @@ fn get_strategy_researcher_system_prompt() -> &'static str {
 * 8-10分：强信号，趋势明确，时机成熟
 * 5-7分：中等信号，可交易但需谨慎
 * 1-4分：弱信号，建议观望
+* 参考技术指标（technicals）：RSI 超买超卖、MACD 柱体方向、ADX 趋势强度是否支持当下入场
 
 **第四层：目标持仓方向**
 
 * 看多环境 → target_side: Long
 * 看空环境 → target_side: Short
-* 观望或平仓 → target_side: null
+* 观望、减仓或平仓 → target_side: null（反手时为新方向）
 
 **第五层：仓位与风险控制**
 
@@ -406,7 +450,7 @@ fn get_strategy_researcher_system_prompt() -> &'static str {
 严格返回JSON格式：
 
 {
-  "action": "open_long"|"open_short"|"add_position"|"close_position"|"hold",
+  "action": "open_long"|"open_short"|"add_position"|"reduce_position"|"close_position"|"reverse"|"hold",
   "reasoning": "策略逻辑，50字内",
   "timing_score": 8,
   "target_side": "Long"|"Short"|null,
@@ -421,19 +465,23 @@ fn get_strategy_researcher_system_prompt() -> &'static str {
 fn build_strategy_researcher_prompt(
     symbol: &str,
     market_report: &MarketReport,
-    position: &Option<Position>,
+    positions: &PositionBook,
+    technicals: Option<&Value>,
+    derivatives: Option<&DerivativesSnapshot>,
 ) -> Result<String> {
     let payload = json!({
         "symbol": symbol,
         "market_report": market_report,
-        "position": position,
+        "positions": positions,
+        "technicals": technicals,
+        "derivatives": derivatives,
     });
 
     structured_prompt(
-        "输入是上一阶段的市场分析与当前持仓，全部以 JSON 形式给出。请基于这些数据输出最合理的策略建议。",
+        "输入是上一阶段的市场分析、当前持仓（双向持仓，long/short 两腿可能同时存在，null 表示该方向无仓位）与衍生品数据（derivatives，null 表示暂无），全部以 JSON 形式给出。请基于这些数据输出最合理的策略建议。",
         &payload,
         r#"{
-  "action": "open_long" | "open_short" | "add_position" | "close_position" | "hold",
+  "action": "open_long" | "open_short" | "add_position" | "reduce_position" | "close_position" | "reverse" | "hold",
   "reasoning": "策略逻辑，<=50字",
   "timing_score": 1-10 的整数,
   "target_side": "Long" | "Short" | null,
@@ -447,11 +495,26 @@ fn build_strategy_researcher_prompt(
 pub async fn strategy_researcher_suggest(
     symbol: &str,
     market_report: &MarketReport,
-    position: &Option<Position>,
-    api_key: &str,
+    positions: &PositionBook,
+    technicals: Option<&Value>,
+    derivatives: Option<&DerivativesSnapshot>,
+    llm: &dyn LlmBackend,
 ) -> Result<StrategyAdvice> {
-    let prompt = build_strategy_researcher_prompt(symbol, market_report, position)?;
-    let response = call_deepseek(get_strategy_researcher_system_prompt(), &prompt, api_key).await?;
+    let prompt = build_strategy_researcher_prompt(
+        symbol,
+        market_report,
+        positions,
+        technicals,
+        derivatives,
+    )?;
+    let response = call_llm(
+        llm,
+        AgentRole::StrategyResearcher,
+        Some(symbol),
+        get_strategy_researcher_system_prompt(),
+        &prompt,
+    )
+    .await?;
     parse_json_response(&response)
 }
 
@@ -525,6 +588,8 @@ fn get_risk_manager_system_prompt() -> &'static str {
 * 时机评分是否达到可操作标准？（建议≥6分）
 * 当前波动率是否异常？
 * 是否存在突发事件风险（政策、黑天鹅）？
+* 盘口深度能否承接该数量？预估滑点（order_book）是否过高？
+* 波动率与通道（technicals：ATR、布林带宽度、唐奇安通道）是否要求更小的仓位或更宽的止损？
 
 **第三层：策略风险验证**
 
@@ -556,30 +621,42 @@ fn get_risk_manager_system_prompt() -> &'static str {
 **禁止输出任何JSON之外的内容。**"#
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn risk_manager_assess(
     symbol: &str,
     market_report: &MarketReport,
     strategy: &StrategyAdvice,
     account: &AccountInfo,
-    position: &Option<Position>,
+    positions: &PositionBook,
     constraints: &SymbolConstraints,
     allocated_balance: f64,
     allocated_max_amount: f64,
     max_position: f64,
-    api_key: &str,
+    depth: Option<&DepthMetrics>,
+    technicals: Option<&Value>,
+    llm: &dyn LlmBackend,
 ) -> Result<RiskAssessment> {
     let prompt = build_risk_manager_prompt(
         symbol,
         market_report,
         strategy,
         account,
-        position,
+        positions,
         constraints,
         allocated_balance,
         allocated_max_amount,
         max_position,
+        depth,
+        technicals,
     )?;
-    let response = call_deepseek(get_risk_manager_system_prompt(), &prompt, api_key).await?;
+    let response = call_llm(
+        llm,
+        AgentRole::RiskManager,
+        Some(symbol),
+        get_risk_manager_system_prompt(),
+        &prompt,
+    )
+    .await?;
     parse_json_response(&response)
 }
 
@@ -663,6 +740,8 @@ fn get_trade_executor_system_prompt() -> &'static str {
 
 * **BUY信号**：趋势bullish + 策略open_long/add_position + 风控approved/adjusted
 * **SELL信号**：趋势bearish + 策略open_short + 风控approved/adjusted
+* **减仓/平仓**：策略reduce_position/close_position时，减多/平多为SELL，减空/平空为BUY，amount 为减仓数量（平仓时忽略）
+* **反手**：策略reverse时按 target_side 给出方向，Long为BUY，Short为SELL
 * **HOLD信号**：风控rejected / 信号矛盾 / 趋势不明
 
 **第五层：数量与信心评估**
@@ -694,12 +773,14 @@ fn build_trade_executor_prompt(
     market_report: &MarketReport,
     strategy: &StrategyAdvice,
     risk: &RiskAssessment,
+    technicals: Option<&Value>,
 ) -> Result<String> {
     let payload = json!({
         "symbol": symbol,
         "market_report": market_report,
         "strategy": strategy,
         "risk": risk,
+        "technicals": technicals,
     });
 
     structured_prompt(
@@ -719,10 +800,18 @@ pub async fn trade_executor_decide(
     market_report: &MarketReport,
     strategy: &StrategyAdvice,
     risk: &RiskAssessment,
-    api_key: &str,
+    technicals: Option<&Value>,
+    llm: &dyn LlmBackend,
 ) -> Result<TradingDecision> {
-    let prompt = build_trade_executor_prompt(symbol, market_report, strategy, risk)?;
-    let response = call_deepseek(get_trade_executor_system_prompt(), &prompt, api_key).await?;
+    let prompt = build_trade_executor_prompt(symbol, market_report, strategy, risk, technicals)?;
+    let response = call_llm(
+        llm,
+        AgentRole::TradeExecutor,
+        Some(symbol),
+        get_trade_executor_system_prompt(),
+        &prompt,
+    )
+    .await?;
     parse_json_response(&response)
 }
 // ========== 5. 投资组合协调员 (Portfolio Coordinator) ==========
@@ -832,14 +921,15 @@ fn get_portfolio_coordinator_system_prompt() -> &'static str {
 fn build_portfolio_coordinator_prompt(
     total_balance: f64,
     portfolio_strategy: &str,
-    reports: &[(String, MarketReport)],
+    reports: &[(String, MarketReport, Option<Value>)],
 ) -> Result<String> {
     let simplified: Vec<_> = reports
         .iter()
-        .map(|(symbol, report)| {
+        .map(|(symbol, report, technicals)| {
             json!({
                 "symbol": symbol,
                 "market_report": report,
+                "technicals": technicals,
             })
         })
         .collect();
@@ -865,56 +955,40 @@ fn build_portfolio_coordinator_prompt(
 }
 
 pub async fn portfolio_coordinator_allocate(
-    symbols_reports: &[(String, MarketReport)],
+    symbols_reports: &[(String, MarketReport, Option<Value>)],
     total_balance: f64,
     portfolio_strategy: &str,
-    api_key: &str,
+    llm: &dyn LlmBackend,
 ) -> Result<PortfolioAllocation> {
     let prompt =
         build_portfolio_coordinator_prompt(total_balance, portfolio_strategy, symbols_reports)?;
-    let response =
-        call_deepseek(get_portfolio_coordinator_system_prompt(), &prompt, api_key).await?;
+    let response = call_llm(
+        llm,
+        AgentRole::PortfolioCoordinator,
+        None,
+        get_portfolio_coordinator_system_prompt(),
+        &prompt,
+    )
+    .await?;
     parse_json_response(&response)
 }
 
 // ========== 通用工具函数 ==========
 
-async fn call_deepseek(system_prompt: &str, user_prompt: &str, api_key: &str) -> Result<String> {
-    let config = OpenAIConfig::new()
-        .with_api_key(api_key)
-        .with_api_base("https://api.deepseek.com");
-
-    let client = Client::with_config(config);
-
-    let request = CreateChatCompletionRequestArgs::default()
-        .model("deepseek-chat")
-        .messages(vec![
-            ChatCompletionRequestMessage::System(
-                ChatCompletionRequestSystemMessageArgs::default()
-                    .content(system_prompt)
-                    .build()?,
-            ),
-            ChatCompletionRequestMessage::User(
-                ChatCompletionRequestUserMessageArgs::default()
-                    .content(user_prompt)
-                    .build()?,
-            ),
-        ])
-        .build()?;
-
-    let response = client
-        .chat()
-        .create(request)
-        .await
-        .context("DeepSeek API 调用失败")?;
-
-    let content = response
-        .choices
-        .first()
-        .and_then(|c| c.message.content.as_ref())
-        .context("DeepSeek 返回为空")?;
-
-    Ok(content.clone())
+async fn call_llm(
+    llm: &dyn LlmBackend,
+    role: AgentRole,
+    symbol: Option<&str>,
+    system_prompt: &str,
+    user_prompt: &str,
+) -> Result<String> {
+    let request = LlmRequest {
+        role,
+        symbol,
+        system_prompt,
+        user_prompt,
+    };
+    llm.complete(&request).await
 }
 
 fn parse_json_response<T: serde::de::DeserializeOwned>(response: &str) -> Result<T> {
@@ -0,0 +1,592 @@
+// 订单生命周期跟踪：幂等提交（客户端订单号 + 在途记录），轮询订单直至结束，
+// 汇总实际成交量、成交均价、手续费与已实现盈亏
+
+use crate::exchange::{Exchange, Fill, OrderRequest, OrderState};
+use crate::journal;
+use crate::logging;
+use crate::types::{PositionSide, TradeAction, TradeResult};
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::time::{sleep, Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const FINAL_TIMEOUT: Duration = Duration::from_secs(10); // 等待订单进入终态
+const FILL_RETRIES: u32 = 3; // 成交明细可能略晚于订单状态可查
+const SUBMIT_ATTEMPTS: u32 = 3;
+const IN_FLIGHT_FILE: &str = "inflight_orders.json";
+const SEQUENCE_FILE: &str = "order_sequences.json";
+const MAX_CLIENT_ORDER_ID_LEN: usize = 36; // Binance newClientOrderId 长度上限
+
+static IN_FLIGHT_LOCK: Mutex<()> = Mutex::new(());
+// (周期, 标的) → 已生成的订单号个数，同一周期内多次生成的ID不会重复；
+// 首次使用时从日志目录恢复，重启后继续编号，不会重新生成可能已成交订单的ID
+static ID_SEQUENCES: Mutex<Option<HashMap<(i64, String), u32>>> = Mutex::new(None);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IdSequence {
+    cycle: i64,
+    symbol: String,
+    seq: u32,
+}
+
+fn sequence_path() -> PathBuf {
+    PathBuf::from(logging::logs_directory()).join(SEQUENCE_FILE)
+}
+
+fn load_sequences() -> Result<HashMap<(i64, String), u32>> {
+    let path = sequence_path();
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let content = fs::read_to_string(&path).context("读取订单序号失败")?;
+    let sequences: Vec<IdSequence> = serde_json::from_str(&content).context("解析订单序号失败")?;
+    Ok(sequences
+        .into_iter()
+        .map(|s| ((s.cycle, s.symbol), s.seq))
+        .collect())
+}
+
+fn save_sequences(sequences: &HashMap<(i64, String), u32>) -> Result<()> {
+    let path = sequence_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("创建logs目录失败")?;
+    }
+    let records: Vec<IdSequence> = sequences
+        .iter()
+        .map(|((cycle, symbol), seq)| IdSequence {
+            cycle: *cycle,
+            symbol: symbol.clone(),
+            seq: *seq,
+        })
+        .collect();
+    let tmp_path = path.with_extension("json.tmp");
+    let json = serde_json::to_string_pretty(&records).context("序列化订单序号失败")?;
+    fs::write(&tmp_path, json).context("写入订单序号失败")?;
+    fs::rename(&tmp_path, &path).context("替换订单序号失败")?;
+    Ok(())
+}
+
+// 取 (周期, 标的) 的下一个序号，只保留当前及之后周期的计数
+fn next_sequence(sequences: &mut HashMap<(i64, String), u32>, cycle: i64, symbol: &str) -> u32 {
+    sequences.retain(|(c, _), _| *c >= cycle);
+    let seq = sequences.entry((cycle, symbol.to_string())).or_default();
+    *seq += 1;
+    *seq
+}
+
+// ma-{周期}-{标的}-{动作}-{序号}；超长时以完整ID的摘要代替标的与动作，保持唯一
+fn format_client_order_id(cycle: i64, symbol: &str, action: &str, seq: u32) -> String {
+    let id = format!("ma-{}-{}-{}-{}", cycle, symbol, action, seq);
+    if id.len() <= MAX_CLIENT_ORDER_ID_LEN {
+        return id;
+    }
+    let digest: String = Sha256::digest(id.as_bytes())
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("ma-{}-{}", cycle, digest)
+}
+
+// 确定性的客户端订单号：周期、标的、动作加周期内序号，序号跨重启延续
+#[derive(Debug, Clone)]
+pub struct ClientOrderIds {
+    cycle: i64,
+    symbol: String,
+}
+
+impl ClientOrderIds {
+    pub fn new(cycle: i64, symbol: &str) -> Self {
+        ClientOrderIds {
+            cycle,
+            symbol: symbol.to_string(),
+        }
+    }
+
+    // action 如 OL/OS（开多/开空）、CL/CS（平多/平空）、SL/TP（保护单）
+    pub fn id(&self, action: &str) -> String {
+        let seq = {
+            let mut guard = ID_SEQUENCES.lock().unwrap();
+            let sequences = guard.get_or_insert_with(|| {
+                load_sequences().unwrap_or_else(|e| {
+                    warn!("订单序号恢复失败，从头编号: {:#}", e);
+                    HashMap::new()
+                })
+            });
+            let seq = next_sequence(sequences, self.cycle, &self.symbol);
+            if let Err(e) = save_sequences(sequences) {
+                warn!("订单序号保存失败: {:#}", e);
+            }
+            seq
+        };
+        format_client_order_id(self.cycle, &self.symbol, action, seq)
+    }
+}
+
+// 已发出但尚未确认结果的订单，重启后据此对账
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct InFlightOrder {
+    exchange: String,
+    client_order_id: String,
+    symbol: String,
+    side: String,
+    position_side: PositionSide,
+    order_type: String,
+    quantity: f64,
+    created_at: i64,
+}
+
+fn in_flight_path() -> PathBuf {
+    PathBuf::from(logging::logs_directory()).join(IN_FLIGHT_FILE)
+}
+
+fn load_in_flight() -> Result<Vec<InFlightOrder>> {
+    let path = in_flight_path();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&path).context("读取在途订单记录失败")?;
+    serde_json::from_str(&content).context("解析在途订单记录失败")
+}
+
+fn save_in_flight(orders: &[InFlightOrder]) -> Result<()> {
+    let path = in_flight_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("创建logs目录失败")?;
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    let json = serde_json::to_string_pretty(orders).context("序列化在途订单记录失败")?;
+    fs::write(&tmp_path, json).context("写入在途订单记录失败")?;
+    fs::rename(&tmp_path, &path).context("替换在途订单记录失败")?;
+    Ok(())
+}
+
+fn update_in_flight(f: impl FnOnce(&mut Vec<InFlightOrder>)) -> Result<()> {
+    let _guard = IN_FLIGHT_LOCK.lock().unwrap();
+    let mut orders = load_in_flight()?;
+    f(&mut orders);
+    save_in_flight(&orders)
+}
+
+// 网络错误或交易所执行超时 (-1007) 时无法确定订单是否已被接受
+fn outcome_unknown(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .any(|cause| cause.downcast_ref::<reqwest::Error>().is_some())
+        || format!("{:#}", error).contains("code:-1007")
+}
+
+// 幂等提交：结果未知时先按客户端订单号查询，确认不存在才以同一ID重试；
+// 仍无法确认时保留在途记录，留待重启对账
+pub async fn submit(exchange: &dyn Exchange, order: &OrderRequest) -> Result<OrderState> {
+    let Some(client_order_id) = order.client_order_id.clone() else {
+        let result = exchange.place_order(order).await;
+        journal::record_order(order, &result);
+        return result;
+    };
+
+    // 同一ID的在途记录说明上次运行已发出该订单，先查询确认，避免重复下单
+    let previously_sent = {
+        let _guard = IN_FLIGHT_LOCK.lock().unwrap();
+        load_in_flight()?
+            .iter()
+            .any(|o| o.exchange == exchange.name() && o.client_order_id == client_order_id)
+    };
+    if previously_sent {
+        if let Some(state) = exchange
+            .find_order(&order.symbol, &client_order_id)
+            .await
+            .with_context(|| format!("查询在途订单 {} 失败", client_order_id))?
+        {
+            info!(
+                "订单 {} 已在上次运行中被接受，不再重复下单",
+                client_order_id
+            );
+            update_in_flight(|orders| orders.retain(|o| o.client_order_id != client_order_id))?;
+            let result = Ok(state);
+            journal::record_order(order, &result);
+            return result;
+        }
+    }
+
+    let record = InFlightOrder {
+        exchange: exchange.name().to_string(),
+        client_order_id: client_order_id.clone(),
+        symbol: order.symbol.clone(),
+        side: order.side.as_str().to_string(),
+        position_side: order.position_side.clone(),
+        order_type: order.order_type.as_str().to_string(),
+        quantity: order.quantity,
+        created_at: exchange.now_millis(),
+    };
+    if !previously_sent {
+        update_in_flight(|orders| orders.push(record))?;
+    }
+
+    let mut result = Err(anyhow!("订单 {} 未发出", client_order_id));
+    let mut resolved = true;
+    for attempt in 1..=SUBMIT_ATTEMPTS {
+        match exchange.place_order(order).await {
+            Ok(state) => {
+                result = Ok(state);
+                resolved = true;
+                break;
+            }
+            Err(e) if !outcome_unknown(&e) => {
+                result = Err(e);
+                resolved = true;
+                break;
+            }
+            Err(e) => {
+                warn!(
+                    "订单 {} 结果未知 (第 {} 次): {:#}",
+                    client_order_id, attempt, e
+                );
+                result = Err(e);
+                resolved = false;
+            }
+        }
+
+        sleep(POLL_INTERVAL * attempt).await;
+        match exchange.find_order(&order.symbol, &client_order_id).await {
+            Ok(Some(state)) => {
+                info!("订单 {} 已被交易所接受，不再重复下单", client_order_id);
+                result = Ok(state);
+                resolved = true;
+                break;
+            }
+            Ok(None) => debug!("订单 {} 未到达交易所，重试", client_order_id),
+            Err(e) => {
+                warn!("查询订单 {} 失败，停止重试: {:#}", client_order_id, e);
+                break;
+            }
+        }
+    }
+
+    if resolved {
+        if let Err(e) =
+            update_in_flight(|orders| orders.retain(|o| o.client_order_id != client_order_id))
+        {
+            warn!("清除在途订单记录失败: {:#}", e);
+        }
+    }
+    journal::record_order(order, &result);
+    result
+}
+
+// 对账确认已到达交易所的在途订单
+pub struct ReconciledOrder {
+    client_order_id: String,
+    symbol: String,
+    side: String,
+    position_side: PositionSide,
+    created_at: i64,
+    order: TrackedOrder,
+}
+
+impl ReconciledOrder {
+    // 有成交时转为交易记录，平仓成交带已实现盈亏
+    pub fn trade_result(&self) -> Option<TradeResult> {
+        let quantity = self.order.executed_qty();
+        if quantity <= 0.0 {
+            return None;
+        }
+        let action = match (self.side.as_str(), &self.position_side) {
+            ("BUY", PositionSide::Long) => TradeAction::OpenLong,
+            ("SELL", PositionSide::Long) => TradeAction::CloseLong,
+            ("SELL", PositionSide::Short) => TradeAction::OpenShort,
+            _ => TradeAction::CloseShort,
+        };
+        let closing = matches!(action, TradeAction::CloseLong | TradeAction::CloseShort);
+        Some(TradeResult {
+            symbol: self.symbol.clone(),
+            price: self.order.notional() / quantity,
+            amount: quantity,
+            timestamp: self
+                .order
+                .fills
+                .iter()
+                .map(|f| f.time)
+                .max()
+                .unwrap_or(self.created_at),
+            reason: format!("在途订单对账 {}", self.client_order_id),
+            pnl: if closing {
+                Some(self.order.realized_pnl().unwrap_or(0.0))
+            } else {
+                None
+            },
+            commission: self.order.commission(),
+            order_details: Some(self.order.to_string()),
+            order_ids: vec![self.order.state.order_id],
+            action,
+        })
+    }
+}
+
+// 启动时对账上次未确认的订单：已到达交易所的跟踪至终态并返回，未到达的直接清除，查询失败的保留
+pub async fn reconcile(exchange: &dyn Exchange) -> Result<Vec<ReconciledOrder>> {
+    let records = {
+        let _guard = IN_FLIGHT_LOCK.lock().unwrap();
+        load_in_flight()?
+    };
+    let mut resolved = Vec::new();
+    let mut reconciled = Vec::new();
+    for record in records.iter().filter(|o| o.exchange == exchange.name()) {
+        match exchange
+            .find_order(&record.symbol, &record.client_order_id)
+            .await
+        {
+            Ok(Some(state)) => {
+                let order = track(exchange, &record.symbol, state).await;
+                info!(
+                    "在途订单对账: {} {} {} {:?} {} 已存在 ({})",
+                    record.client_order_id,
+                    record.symbol,
+                    record.side,
+                    record.position_side,
+                    record.order_type,
+                    order
+                );
+                resolved.push(record.client_order_id.clone());
+                reconciled.push(ReconciledOrder {
+                    client_order_id: record.client_order_id.clone(),
+                    symbol: record.symbol.clone(),
+                    side: record.side.clone(),
+                    position_side: record.position_side.clone(),
+                    created_at: record.created_at,
+                    order,
+                });
+            }
+            Ok(None) => {
+                info!(
+                    "在途订单对账: {} {} 数量 {} 未到达交易所",
+                    record.client_order_id, record.symbol, record.quantity
+                );
+                resolved.push(record.client_order_id.clone());
+            }
+            Err(e) => warn!("在途订单 {} 对账失败: {:#}", record.client_order_id, e),
+        }
+    }
+    if !resolved.is_empty() {
+        update_in_flight(|orders| orders.retain(|o| !resolved.contains(&o.client_order_id)))?;
+    }
+    Ok(reconciled)
+}
+
+// 已结束（或超时仍未结束）的订单及其成交明细
+#[derive(Debug, Clone)]
+pub struct TrackedOrder {
+    pub state: OrderState,
+    pub fills: Vec<Fill>,
+}
+
+impl TrackedOrder {
+    fn fills_complete(&self) -> bool {
+        let filled: f64 = self.fills.iter().map(|f| f.quantity).sum();
+        filled + 1e-12 >= self.state.executed_qty
+    }
+
+    pub fn executed_qty(&self) -> f64 {
+        self.state.executed_qty
+    }
+
+    // 按成交明细加权，明细缺失时取订单回报的均价
+    pub fn notional(&self) -> f64 {
+        if !self.fills.is_empty() && self.fills_complete() {
+            self.fills.iter().map(|f| f.price * f.quantity).sum()
+        } else {
+            self.state.avg_price * self.state.executed_qty
+        }
+    }
+
+    // 明细缺失时为 None
+    pub fn commission(&self) -> Option<f64> {
+        (!self.fills.is_empty()).then(|| self.fills.iter().map(|f| f.commission).sum())
+    }
+
+    pub fn realized_pnl(&self) -> Option<f64> {
+        (!self.fills.is_empty()).then(|| self.fills.iter().map(|f| f.realized_pnl).sum())
+    }
+}
+
+impl fmt::Display for TrackedOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.state)?;
+        if let Some(commission) = self.commission() {
+            let asset = self
+                .fills
+                .first()
+                .map(|fill| fill.commission_asset.as_str())
+                .unwrap_or_default();
+            write!(f, ", 手续费:{:.6} {}", commission, asset)?;
+            if self.fills.iter().all(|fill| fill.maker) {
+                write!(f, " (Maker)")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// 跟踪订单至终态并拉取成交明细；查询失败时保留已知状态，不影响交易流程
+pub async fn track(exchange: &dyn Exchange, symbol: &str, state: OrderState) -> TrackedOrder {
+    let mut state = state;
+    let deadline = Instant::now() + FINAL_TIMEOUT;
+    while !state.status.is_final() && Instant::now() < deadline {
+        sleep(POLL_INTERVAL).await;
+        match exchange.get_order(symbol, state.order_id).await {
+            Ok(latest) => state = latest,
+            Err(e) => warn!("查询订单 {} 状态失败: {:#}", state.order_id, e),
+        }
+    }
+    if !state.status.is_final() {
+        warn!("订单 {} 未在超时内结束: {}", state.order_id, state);
+    }
+
+    let mut tracked = TrackedOrder {
+        state,
+        fills: Vec::new(),
+    };
+    if tracked.state.executed_qty <= 0.0 {
+        journal::record_order_result(symbol, &tracked.state, &tracked.fills);
+        return tracked;
+    }
+    for attempt in 0..FILL_RETRIES {
+        if attempt > 0 {
+            sleep(POLL_INTERVAL).await;
+        }
+        match exchange.order_fills(symbol, tracked.state.order_id).await {
+            Ok(fills) => {
+                tracked.fills = fills;
+                if tracked.fills_complete() {
+                    break;
+                }
+                debug!(
+                    "订单 {} 成交明细不完整 ({} 笔)，重试",
+                    tracked.state.order_id,
+                    tracked.fills.len()
+                );
+            }
+            Err(e) => warn!("查询订单 {} 成交明细失败: {:#}", tracked.state.order_id, e),
+        }
+    }
+    journal::record_order_result(symbol, &tracked.state, &tracked.fills);
+    tracked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::OrderStatus;
+
+    #[test]
+    fn client_order_id_format() {
+        assert_eq!(
+            format_client_order_id(28_912_345, "BTCUSDT", "OL", 1),
+            "ma-28912345-BTCUSDT-OL-1"
+        );
+        // 恰好 36 个字符时保留原样
+        let id = format_client_order_id(28_912_345, "ABCDEFGHIJKLMNOPQRS", "OL", 2);
+        assert_eq!(id.len(), MAX_CLIENT_ORDER_ID_LEN);
+        assert_eq!(id, "ma-28912345-ABCDEFGHIJKLMNOPQRS-OL-2");
+    }
+
+    #[test]
+    fn long_client_order_id_is_hashed() {
+        // 完整ID为 ma-28912345-1000000MOGUSDTPERP-RBOL-12，共 38 个字符
+        let id = format_client_order_id(28_912_345, "1000000MOGUSDTPERP", "RBOL", 12);
+        let prefix = "ma-28912345-";
+        assert_eq!(id.len(), prefix.len() + 16);
+        assert!(id.starts_with(prefix));
+        assert!(id[prefix.len()..].chars().all(|c| c.is_ascii_hexdigit()));
+        // 相同输入得到相同ID，序号不同则不同
+        assert_eq!(
+            id,
+            format_client_order_id(28_912_345, "1000000MOGUSDTPERP", "RBOL", 12)
+        );
+        assert_ne!(
+            id,
+            format_client_order_id(28_912_345, "1000000MOGUSDTPERP", "RBOL", 13)
+        );
+    }
+
+    #[test]
+    fn sequence_is_per_cycle_and_symbol() {
+        let mut sequences = HashMap::new();
+        assert_eq!(next_sequence(&mut sequences, 10, "BTCUSDT"), 1);
+        assert_eq!(next_sequence(&mut sequences, 10, "BTCUSDT"), 2);
+        assert_eq!(next_sequence(&mut sequences, 10, "ETHUSDT"), 1);
+        // 进入新周期后清理旧周期的计数
+        assert_eq!(next_sequence(&mut sequences, 11, "BTCUSDT"), 1);
+        assert_eq!(sequences.len(), 1);
+        // 计数恢复后继续编号
+        let mut restored: HashMap<(i64, String), u32> =
+            [((11, "BTCUSDT".to_string()), 1)].into_iter().collect();
+        assert_eq!(next_sequence(&mut restored, 11, "BTCUSDT"), 2);
+    }
+
+    #[test]
+    fn reconciled_fills_become_trades() {
+        let fill = |price: f64, quantity: f64, realized_pnl: f64| Fill {
+            trade_id: 1,
+            price,
+            quantity,
+            commission: 0.1,
+            commission_asset: "USDT".to_string(),
+            realized_pnl,
+            maker: false,
+            time: 2_000,
+        };
+        let order = |side: &str, position_side: PositionSide, fills: Vec<Fill>| ReconciledOrder {
+            client_order_id: "ma-1-BTCUSDT-CL-1".to_string(),
+            symbol: "BTCUSDT".to_string(),
+            side: side.to_string(),
+            position_side,
+            created_at: 1_000,
+            order: TrackedOrder {
+                state: OrderState {
+                    order_id: 42,
+                    status: if fills.is_empty() {
+                        OrderStatus::New
+                    } else {
+                        OrderStatus::Filled
+                    },
+                    executed_qty: fills.iter().map(|f| f.quantity).sum(),
+                    avg_price: 0.0,
+                },
+                fills,
+            },
+        };
+
+        let close = order(
+            "SELL",
+            PositionSide::Long,
+            vec![fill(100.0, 1.0, 5.0), fill(102.0, 1.0, 7.0)],
+        )
+        .trade_result()
+        .unwrap();
+        assert!(matches!(close.action, TradeAction::CloseLong));
+        assert!((close.price - 101.0).abs() < 1e-9);
+        assert!((close.amount - 2.0).abs() < 1e-9);
+        assert_eq!(close.pnl, Some(12.0));
+        assert_eq!(close.order_ids, vec![42]);
+        assert_eq!(close.timestamp, 2_000);
+
+        let open = order("SELL", PositionSide::Short, vec![fill(100.0, 1.0, 0.0)])
+            .trade_result()
+            .unwrap();
+        assert!(matches!(open.action, TradeAction::OpenShort));
+        assert_eq!(open.pnl, None);
+
+        // 未成交的挂单不产生交易记录
+        assert!(order("BUY", PositionSide::Long, Vec::new())
+            .trade_result()
+            .is_none());
+    }
+}
@@ -0,0 +1,1426 @@
+// 模拟盘交易所：按实时或回放行情本地撮合市价、限价与止损止盈单，计算手续费、滑点与保证金
+
+use crate::depth::OrderBook;
+use crate::derivatives::DerivativesSnapshot;
+use crate::exchange::{
+    AccountTrade, Exchange, Fill, Income, IncomeKind, OpenOrder, OrderRequest, OrderSide,
+    OrderState, OrderStatus, OrderType, PositionMode, TimeInForce,
+};
+use crate::executor::{AccountInfo, SymbolConstraints};
+use crate::logging;
+use crate::margin::LeverageBracket;
+use crate::market::{self, BookTicker};
+use crate::types::{Kline, Position, PositionBook, PositionSide};
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, create_dir_all};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_LEVERAGE: u32 = 20;
+const MAINT_MARGIN_RATE: f64 = 0.004; // 估算强平价使用的维持保证金率
+const ACCOUNT_FILE: &str = "paper_account.json";
+
+#[derive(Debug, Clone)]
+pub struct PaperConfig {
+    pub initial_balance: f64,
+    pub taker_fee_rate: f64, // 吃单费率，例如 0.0005 = 0.05%
+    pub maker_fee_rate: f64, // 挂单费率
+    pub slippage_bps: f64,   // 市价单滑点（基点）
+}
+
+impl PaperConfig {
+    pub fn from_env() -> Result<Self> {
+        let read = |key: &str, default: &str| -> Result<f64> {
+            env::var(key)
+                .unwrap_or_else(|_| default.to_string())
+                .parse()
+                .with_context(|| format!("{} 格式错误", key))
+        };
+
+        Ok(PaperConfig {
+            initial_balance: read("PAPER_INITIAL_BALANCE", "10000")?,
+            taker_fee_rate: read("PAPER_TAKER_FEE", "0.0005")?,
+            maker_fee_rate: read("PAPER_MAKER_FEE", "0.0002")?,
+            slippage_bps: read("PAPER_SLIPPAGE_BPS", "2")?,
+        })
+    }
+}
+
+// 价格来源：实时行情或外部回放
+enum PriceMode {
+    Live,
+    Replay,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PaperPosition {
+    amount: f64,
+    entry_price: f64,
+    margin: f64, // 占用的初始保证金
+}
+
+// 挂起的限价单或条件单
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PaperOrder {
+    order_id: i64,
+    symbol: String,
+    side: OrderSide,
+    position_side: PositionSide,
+    order_type: OrderType,
+    price: f64,            // 限价或触发价
+    quantity: Option<f64>, // None 表示 closePosition
+}
+
+impl PaperOrder {
+    // 价格区间 [low, high] 是否触及；限价单需价格穿越才视为成交
+    fn triggered(&self, low: f64, high: f64) -> bool {
+        match (&self.position_side, self.order_type, self.side) {
+            (_, OrderType::Market, _) => false,
+            (_, OrderType::Limit, OrderSide::Buy) => low < self.price,
+            (_, OrderType::Limit, OrderSide::Sell) => high > self.price,
+            (PositionSide::Long, OrderType::StopMarket, _) => low <= self.price,
+            (PositionSide::Long, _, _) => high >= self.price,
+            (PositionSide::Short, OrderType::StopMarket, _) => high >= self.price,
+            (PositionSide::Short, _, _) => low <= self.price,
+        }
+    }
+}
+
+#[derive(Default)]
+struct PaperState {
+    wallet_balance: f64,
+    positions: HashMap<(String, PositionSide), PaperPosition>,
+    leverage: HashMap<String, u32>,
+    marks: HashMap<String, f64>,
+    history: HashMap<(String, String), Vec<Kline>>,
+    orders: Vec<PaperOrder>,
+    order_states: HashMap<i64, OrderState>,
+    fills: HashMap<i64, Vec<Fill>>,
+    trades: Vec<AccountTrade>, // 全部成交，按时间先后
+    income: Vec<Income>,       // 模拟盘不结算资金费，仅有已实现盈亏与手续费
+    client_order_ids: HashMap<String, i64>,
+    position_mode: PositionMode,
+    clock_ms: i64, // 回放时钟
+    next_order_id: i64,
+    next_trade_id: i64,
+}
+
+// 实时模拟盘落盘的账户状态，使重启后的账户与已恢复的绩效、熔断等状态一致；
+// 行情、K线与回放时钟不保存
+#[derive(Serialize, Deserialize)]
+struct PaperAccount {
+    wallet_balance: f64,
+    positions: Vec<(String, PositionSide, PaperPosition)>,
+    leverage: HashMap<String, u32>,
+    orders: Vec<PaperOrder>,
+    order_states: HashMap<i64, OrderState>,
+    fills: HashMap<i64, Vec<Fill>>,
+    trades: Vec<AccountTrade>,
+    income: Vec<Income>,
+    client_order_ids: HashMap<String, i64>,
+    next_order_id: i64,
+    next_trade_id: i64,
+}
+
+fn account_path() -> PathBuf {
+    Path::new(logging::logs_directory()).join(ACCOUNT_FILE)
+}
+
+impl PaperState {
+    fn account(&self) -> PaperAccount {
+        PaperAccount {
+            wallet_balance: self.wallet_balance,
+            positions: self
+                .positions
+                .iter()
+                .map(|((symbol, side), pos)| (symbol.clone(), side.clone(), pos.clone()))
+                .collect(),
+            leverage: self.leverage.clone(),
+            orders: self.orders.clone(),
+            order_states: self.order_states.clone(),
+            fills: self.fills.clone(),
+            trades: self.trades.clone(),
+            income: self.income.clone(),
+            client_order_ids: self.client_order_ids.clone(),
+            next_order_id: self.next_order_id,
+            next_trade_id: self.next_trade_id,
+        }
+    }
+
+    fn restore_account(&mut self, account: PaperAccount) {
+        self.wallet_balance = account.wallet_balance;
+        self.positions = account
+            .positions
+            .into_iter()
+            .map(|(symbol, side, pos)| ((symbol, side), pos))
+            .collect();
+        self.leverage = account.leverage;
+        self.orders = account.orders;
+        self.order_states = account.order_states;
+        self.fills = account.fills;
+        self.trades = account.trades;
+        self.income = account.income;
+        self.client_order_ids = account.client_order_ids;
+        self.next_order_id = account.next_order_id;
+        self.next_trade_id = account.next_trade_id;
+    }
+
+    fn leverage_for(&self, symbol: &str) -> u32 {
+        self.leverage
+            .get(symbol)
+            .copied()
+            .unwrap_or(DEFAULT_LEVERAGE)
+    }
+
+    fn unrealized_pnl(&self) -> f64 {
+        self.positions
+            .iter()
+            .map(|((symbol, side), pos)| {
+                let mark = self.marks.get(symbol).copied().unwrap_or(pos.entry_price);
+                side_pnl(side, pos.entry_price, mark, pos.amount)
+            })
+            .sum()
+    }
+
+    fn used_margin(&self) -> f64 {
+        self.positions.values().map(|pos| pos.margin).sum()
+    }
+
+    fn available_balance(&self) -> f64 {
+        self.wallet_balance + self.unrealized_pnl() - self.used_margin()
+    }
+
+    fn next_order_id(&mut self) -> i64 {
+        let order_id = self.next_order_id;
+        self.next_order_id += 1;
+        order_id
+    }
+
+    fn record(
+        &mut self,
+        order_id: i64,
+        status: OrderStatus,
+        executed_qty: f64,
+        avg_price: f64,
+    ) -> OrderState {
+        let state = OrderState {
+            order_id,
+            status,
+            executed_qty,
+            avg_price,
+        };
+        self.order_states.insert(order_id, state.clone());
+        state
+    }
+
+    // 记录订单的一笔成交明细及对应的资金流水，手续费以 USDT 计
+    fn record_fill(
+        &mut self,
+        order_id: i64,
+        symbol: &str,
+        side: OrderSide,
+        position_side: &PositionSide,
+        mut fill: Fill,
+    ) {
+        self.next_trade_id += 1;
+        fill.trade_id = self.next_trade_id;
+        let flows = [
+            (IncomeKind::RealizedPnl, fill.realized_pnl),
+            (IncomeKind::Commission, -fill.commission),
+        ];
+        for (kind, amount) in flows {
+            if amount != 0.0 {
+                let tran_id = self.income.len() as i64 + 1;
+                self.income.push(Income {
+                    symbol: symbol.to_string(),
+                    kind,
+                    amount,
+                    asset: fill.commission_asset.clone(),
+                    time: fill.time,
+                    tran_id,
+                });
+            }
+        }
+        self.trades.push(AccountTrade {
+            symbol: symbol.to_string(),
+            order_id,
+            side,
+            position_side: position_side.clone(),
+            fill: fill.clone(),
+        });
+        self.fills.entry(order_id).or_default().push(fill);
+    }
+
+    // 按成交价撮合一笔订单，返回 (已实现盈亏, 手续费)；
+    // 单向持仓下先冲减反方向持仓，开仓单的剩余数量再开出新方向，减仓单不超过持仓
+    fn fill(
+        &mut self,
+        symbol: &str,
+        side: OrderSide,
+        position_side: &PositionSide,
+        quantity: f64,
+        price: f64,
+        fee_rate: f64,
+    ) -> Result<(f64, f64)> {
+        if self.position_mode == PositionMode::Hedge {
+            return self.fill_leg(symbol, side, position_side, quantity, price, fee_rate);
+        }
+
+        let (reduced_side, opened_side) = match side {
+            OrderSide::Buy => (PositionSide::Short, PositionSide::Long),
+            OrderSide::Sell => (PositionSide::Long, PositionSide::Short),
+        };
+        let held = self
+            .positions
+            .get(&(symbol.to_string(), reduced_side.clone()))
+            .map_or(0.0, |pos| pos.amount);
+        let reducing = quantity.min(held);
+        if *position_side == reduced_side {
+            if reducing <= 0.0 {
+                bail!("订单失败 [code:-2022]: ReduceOnly Order is rejected (无可平持仓)");
+            }
+            return self.fill_leg(symbol, side, &reduced_side, reducing, price, fee_rate);
+        }
+
+        let (mut realized, mut fee) = (0.0, 0.0);
+        if reducing > 0.0 {
+            (realized, fee) =
+                self.fill_leg(symbol, side, &reduced_side, reducing, price, fee_rate)?;
+        }
+        let remaining = quantity - reducing;
+        if remaining > 1e-12 {
+            let (_, open_fee) =
+                self.fill_leg(symbol, side, &opened_side, remaining, price, fee_rate)?;
+            fee += open_fee;
+        }
+        Ok((realized, fee))
+    }
+
+    // 在指定持仓方向上撮合，返回 (已实现盈亏, 手续费)
+    fn fill_leg(
+        &mut self,
+        symbol: &str,
+        side: OrderSide,
+        position_side: &PositionSide,
+        quantity: f64,
+        price: f64,
+        fee_rate: f64,
+    ) -> Result<(f64, f64)> {
+        let notional = price * quantity;
+        let fee = notional * fee_rate;
+        let key = (symbol.to_string(), position_side.clone());
+        let opening = matches!(
+            (position_side, side),
+            (PositionSide::Long, OrderSide::Buy) | (PositionSide::Short, OrderSide::Sell)
+        );
+
+        let realized = if opening {
+            let leverage = self.leverage_for(symbol) as f64;
+            let margin = notional / leverage;
+            if margin + fee > self.available_balance() {
+                return Err(anyhow!(
+                    "订单失败 [code:-2019]: 保证金不足 (需要 {:.2}, 可用 {:.2})",
+                    margin + fee,
+                    self.available_balance()
+                ));
+            }
+
+            let pos = self.positions.entry(key).or_insert(PaperPosition {
+                amount: 0.0,
+                entry_price: 0.0,
+                margin: 0.0,
+            });
+            let new_amount = pos.amount + quantity;
+            pos.entry_price = (pos.entry_price * pos.amount + notional) / new_amount;
+            pos.amount = new_amount;
+            pos.margin += margin;
+            0.0
+        } else {
+            let pos = self.positions.get_mut(&key).ok_or_else(|| {
+                anyhow!("订单失败 [code:-2022]: ReduceOnly Order is rejected (无可平持仓)")
+            })?;
+            if quantity > pos.amount + 1e-12 {
+                return Err(anyhow!(
+                    "订单失败 [code:-2022]: 平仓数量 {} 超过持仓 {}",
+                    quantity,
+                    pos.amount
+                ));
+            }
+
+            let realized = side_pnl(position_side, pos.entry_price, price, quantity);
+            let ratio = quantity / pos.amount;
+            pos.margin -= pos.margin * ratio;
+            pos.amount -= quantity;
+            if pos.amount <= 1e-12 {
+                self.positions.remove(&key);
+                // 持仓归零后同方向的剩余保护单失去意义
+                let orphaned: Vec<i64> = self
+                    .orders
+                    .iter()
+                    .filter(|o| {
+                        o.symbol == symbol
+                            && o.position_side == *position_side
+                            && o.order_type.is_protective()
+                    })
+                    .map(|o| o.order_id)
+                    .collect();
+                for order_id in orphaned {
+                    self.orders.retain(|o| o.order_id != order_id);
+                    self.record(order_id, OrderStatus::Expired, 0.0, 0.0);
+                }
+            }
+            realized
+        };
+
+        self.wallet_balance += realized - fee;
+        Ok((realized, fee))
+    }
+}
+
+fn side_pnl(side: &PositionSide, entry: f64, price: f64, amount: f64) -> f64 {
+    match side {
+        PositionSide::Long => (price - entry) * amount,
+        PositionSide::Short => (entry - price) * amount,
+    }
+}
+
+pub struct PaperExchange {
+    market: Arc<dyn Exchange>,
+    mode: PriceMode,
+    config: PaperConfig,
+    state: Mutex<PaperState>,
+}
+
+impl PaperExchange {
+    // 使用实时行情撮合，行情与交易规则来自 market
+    pub fn live(market: Arc<dyn Exchange>, config: PaperConfig) -> Self {
+        Self::with_mode(market, PriceMode::Live, config)
+    }
+
+    // 使用回放行情撮合，价格由 push_kline 驱动，market 仅提供交易规则
+    pub fn replay(market: Arc<dyn Exchange>, config: PaperConfig) -> Self {
+        Self::with_mode(market, PriceMode::Replay, config)
+    }
+
+    fn with_mode(market: Arc<dyn Exchange>, mode: PriceMode, config: PaperConfig) -> Self {
+        info!(
+            "模拟盘初始资金 {:.2} USDT, 吃单费率 {:.4}%, 挂单费率 {:.4}%, 滑点 {:.1}bps",
+            config.initial_balance,
+            config.taker_fee_rate * 100.0,
+            config.maker_fee_rate * 100.0,
+            config.slippage_bps
+        );
+        let state = PaperState {
+            wallet_balance: config.initial_balance,
+            next_order_id: 1,
+            ..PaperState::default()
+        };
+        PaperExchange {
+            market,
+            mode,
+            config,
+            state: Mutex::new(state),
+        }
+    }
+
+    pub fn with_position_mode(self, mode: PositionMode) -> Self {
+        self.state.lock().unwrap().position_mode = mode;
+        self
+    }
+
+    // 从日志目录恢复实时模拟盘账户；不存在时沿用初始资金
+    pub fn restore(&self) -> Result<()> {
+        self.restore_from(&account_path())
+    }
+
+    fn restore_from(&self, path: &Path) -> Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let content = fs::read_to_string(path).context("读取模拟盘账户失败")?;
+        let account: PaperAccount = serde_json::from_str(&content).context("解析模拟盘账户失败")?;
+        let mut state = self.state.lock().unwrap();
+        state.restore_account(account);
+        info!(
+            "已恢复模拟盘账户: 钱包余额 {:.2} USDT, 持仓 {} 个, 挂单 {} 个",
+            state.wallet_balance,
+            state.positions.len(),
+            state.orders.len()
+        );
+        Ok(())
+    }
+
+    pub fn persist(&self) -> Result<()> {
+        self.persist_to(&account_path())
+    }
+
+    fn persist_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).context("创建logs目录失败")?;
+        }
+        let account = self.state.lock().unwrap().account();
+        let tmp_path = path.with_extension("json.tmp");
+        let json = serde_json::to_string_pretty(&account).context("序列化模拟盘账户失败")?;
+        fs::write(&tmp_path, json).context("写入模拟盘账户失败")?;
+        fs::rename(&tmp_path, path).context("替换模拟盘账户失败")?;
+        Ok(())
+    }
+
+    pub fn initial_balance(&self) -> f64 {
+        self.config.initial_balance
+    }
+
+    // 回放模式下推进一根K线，收盘价作为最新成交参考价
+    pub fn push_kline(&self, symbol: &str, interval: &str, kline: Kline) -> Result<()> {
+        let close_time = kline.timestamp + market::interval_to_millis(interval)? - 1;
+        let mut state = self.state.lock().unwrap();
+        self.trigger_orders(&mut state, symbol, kline.open, kline.low, kline.high);
+        state.marks.insert(symbol.to_string(), kline.close);
+        state.clock_ms = state.clock_ms.max(close_time);
+        state
+            .history
+            .entry((symbol.to_string(), interval.to_string()))
+            .or_default()
+            .push(kline);
+        Ok(())
+    }
+
+    // 回放模式下追加一根仅供分析读取的K线（附加分析周期），不驱动价格与撮合
+    pub fn push_history(&self, symbol: &str, interval: &str, kline: Kline) {
+        self.state
+            .lock()
+            .unwrap()
+            .history
+            .entry((symbol.to_string(), interval.to_string()))
+            .or_default()
+            .push(kline);
+    }
+
+    // 钱包余额 + 未实现盈亏
+    pub fn equity(&self) -> f64 {
+        let state = self.state.lock().unwrap();
+        state.wallet_balance + state.unrealized_pnl()
+    }
+
+    async fn mark_price(&self, symbol: &str) -> Result<f64> {
+        match self.mode {
+            PriceMode::Live => {
+                let price = self.market.fetch_current_price(symbol).await?;
+                let mut state = self.state.lock().unwrap();
+                self.trigger_orders(&mut state, symbol, price, price, price);
+                state.marks.insert(symbol.to_string(), price);
+                Ok(price)
+            }
+            PriceMode::Replay => self
+                .state
+                .lock()
+                .unwrap()
+                .marks
+                .get(symbol)
+                .copied()
+                .with_context(|| format!("回放行情中缺少 {} 的价格", symbol)),
+        }
+    }
+
+    async fn refresh_marks(&self) -> Result<()> {
+        if matches!(self.mode, PriceMode::Replay) {
+            return Ok(());
+        }
+        let symbols: Vec<String> = {
+            let state = self.state.lock().unwrap();
+            let mut symbols: Vec<String> = state.positions.keys().map(|(s, _)| s.clone()).collect();
+            symbols.sort();
+            symbols.dedup();
+            symbols
+        };
+        for symbol in symbols {
+            self.mark_price(&symbol).await?;
+        }
+        Ok(())
+    }
+
+    // 撮合挂单：同一根K线同时触及止损与止盈时保守地先执行止损；
+    // 条件单跳空越过触发价时按开盘价成交，限价单按限价以挂单费率成交
+    fn trigger_orders(&self, state: &mut PaperState, symbol: &str, open: f64, low: f64, high: f64) {
+        let mut pending: Vec<PaperOrder> = state
+            .orders
+            .iter()
+            .filter(|o| o.symbol == symbol && o.triggered(low, high))
+            .cloned()
+            .collect();
+        pending.sort_by_key(|o| o.order_type != OrderType::StopMarket);
+
+        for order in pending {
+            if !state.orders.iter().any(|o| o.order_id == order.order_id) {
+                continue; // 已随持仓归零一并撤销
+            }
+            state.orders.retain(|o| o.order_id != order.order_id);
+
+            let (quantity, price, fee_rate) = if order.order_type == OrderType::Limit {
+                let quantity = order.quantity.unwrap_or(0.0);
+                (quantity, order.price, self.config.maker_fee_rate)
+            } else {
+                let key = (symbol.to_string(), order.position_side.clone());
+                let Some(held) = state.positions.get(&key).map(|p| p.amount) else {
+                    state.record(order.order_id, OrderStatus::Expired, 0.0, 0.0);
+                    continue;
+                };
+                let trigger = if order.triggered(open, open) {
+                    open
+                } else {
+                    order.price
+                };
+                (
+                    order.quantity.map_or(held, |q| q.min(held)),
+                    self.fill_price(order.side, trigger),
+                    self.config.taker_fee_rate,
+                )
+            };
+
+            match state.fill(
+                symbol,
+                order.side,
+                &order.position_side,
+                quantity,
+                price,
+                fee_rate,
+            ) {
+                Ok((realized, fee)) => {
+                    state.record(order.order_id, OrderStatus::Filled, quantity, price);
+                    let time = self.clock(state);
+                    state.record_fill(
+                        order.order_id,
+                        symbol,
+                        order.side,
+                        &order.position_side,
+                        Fill {
+                            trade_id: 0,
+                            price,
+                            quantity,
+                            commission: fee,
+                            commission_asset: "USDT".to_string(),
+                            realized_pnl: realized,
+                            maker: order.order_type == OrderType::Limit,
+                            time,
+                        },
+                    );
+                    info!(
+                        "模拟盘成交 {} {} {:?}: 数量 {:.6}, 成交价 {:.6}, 手续费 {:.6}, 已实现盈亏 {:.6}",
+                        order.order_type.as_str(),
+                        symbol,
+                        order.position_side,
+                        quantity,
+                        price,
+                        fee,
+                        realized
+                    )
+                }
+                Err(e) => {
+                    state.record(order.order_id, OrderStatus::Expired, 0.0, 0.0);
+                    warn!("模拟盘挂单 {} 执行失败: {:#}", order.order_id, e)
+                }
+            }
+        }
+    }
+
+    // 实时模式取交易所盘口，回放模式买卖价均为最新价
+    async fn book_ticker(&self, symbol: &str, mark: f64) -> Result<BookTicker> {
+        match self.mode {
+            PriceMode::Live => self.market.fetch_book_ticker(symbol).await,
+            PriceMode::Replay => Ok(BookTicker {
+                bid_price: mark,
+                ask_price: mark,
+            }),
+        }
+    }
+
+    // 按订单类型撮合或挂单
+    async fn match_order(&self, order: &OrderRequest) -> Result<OrderState> {
+        if order.quantity <= 0.0 && !order.close_position {
+            bail!("订单数量必须大于0");
+        }
+
+        let mark = self.mark_price(&order.symbol).await?;
+        let mut resting = PaperOrder {
+            order_id: 0,
+            symbol: order.symbol.clone(),
+            side: order.side,
+            position_side: order.position_side.clone(),
+            order_type: order.order_type,
+            price: 0.0,
+            quantity: (!order.close_position).then_some(order.quantity),
+        };
+
+        let fill_price = match order.order_type {
+            OrderType::Market => self.fill_price(order.side, mark),
+            OrderType::StopMarket | OrderType::TakeProfitMarket => {
+                resting.price = order.stop_price.context("条件单缺少触发价")?;
+                if resting.triggered(mark, mark) {
+                    bail!("订单失败 [code:-2021]: Order would immediately trigger.");
+                }
+                let mut state = self.state.lock().unwrap();
+                resting.order_id = state.next_order_id();
+                let placed = state.record(resting.order_id, OrderStatus::New, 0.0, 0.0);
+                state.orders.push(resting);
+                return Ok(placed);
+            }
+            OrderType::Limit => {
+                let limit = order.price.context("限价单缺少价格")?;
+                let time_in_force = order.time_in_force.unwrap_or(TimeInForce::Gtc);
+                let book = self.book_ticker(&order.symbol, mark).await?;
+                // 可立即成交时按对手价（含滑点）吃单，且不劣于限价
+                let taker_price = match order.side {
+                    OrderSide::Buy if limit >= book.ask_price => {
+                        Some(self.fill_price(order.side, book.ask_price).min(limit))
+                    }
+                    OrderSide::Sell if limit <= book.bid_price => {
+                        Some(self.fill_price(order.side, book.bid_price).max(limit))
+                    }
+                    _ => None,
+                };
+
+                let mut state = self.state.lock().unwrap();
+                match (taker_price, time_in_force) {
+                    (Some(_), TimeInForce::Gtx) => bail!(
+                        "订单失败 [code:-5022]: Due to the order could not be executed as maker, the Post Only order will be rejected."
+                    ),
+                    (Some(price), _) => price,
+                    (None, TimeInForce::Ioc | TimeInForce::Fok) => {
+                        let order_id = state.next_order_id();
+                        return Ok(state.record(order_id, OrderStatus::Expired, 0.0, 0.0));
+                    }
+                    (None, _) => {
+                        resting.price = limit;
+                        resting.order_id = state.next_order_id();
+                        let placed = state.record(resting.order_id, OrderStatus::New, 0.0, 0.0);
+                        state.orders.push(resting);
+                        return Ok(placed);
+                    }
+                }
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let (realized, fee) = state.fill(
+            &order.symbol,
+            order.side,
+            &order.position_side,
+            order.quantity,
+            fill_price,
+            self.config.taker_fee_rate,
+        )?;
+        let order_id = state.next_order_id();
+        let time = self.clock(&state);
+        state.record_fill(
+            order_id,
+            &order.symbol,
+            order.side,
+            &order.position_side,
+            Fill {
+                trade_id: 0,
+                price: fill_price,
+                quantity: order.quantity,
+                commission: fee,
+                commission_asset: "USDT".to_string(),
+                realized_pnl: realized,
+                maker: false,
+                time,
+            },
+        );
+        Ok(state.record(order_id, OrderStatus::Filled, order.quantity, fill_price))
+    }
+
+    // 成交时间：实时模式取系统时间，回放模式取回放时钟
+    fn clock(&self, state: &PaperState) -> i64 {
+        match self.mode {
+            PriceMode::Live => chrono::Utc::now().timestamp_millis(),
+            PriceMode::Replay => state.clock_ms,
+        }
+    }
+
+    fn fill_price(&self, side: OrderSide, mark: f64) -> f64 {
+        let slip = self.config.slippage_bps / 10_000.0;
+        match side {
+            OrderSide::Buy => mark * (1.0 + slip),
+            OrderSide::Sell => mark * (1.0 - slip),
+        }
+    }
+}
+
+#[async_trait]
+impl Exchange for PaperExchange {
+    fn name(&self) -> &str {
+        match self.mode {
+            PriceMode::Live => "paper-live",
+            PriceMode::Replay => "paper-replay",
+        }
+    }
+
+    fn now_millis(&self) -> i64 {
+        match self.mode {
+            PriceMode::Live => chrono::Utc::now().timestamp_millis(),
+            PriceMode::Replay => self.state.lock().unwrap().clock_ms,
+        }
+    }
+
+    async fn fetch_klines(&self, symbol: &str, interval: &str, limit: u32) -> Result<Vec<Kline>> {
+        match self.mode {
+            PriceMode::Live => self.market.fetch_klines(symbol, interval, limit).await,
+            PriceMode::Replay => {
+                let state = self.state.lock().unwrap();
+                let history = state
+                    .history
+                    .get(&(symbol.to_string(), interval.to_string()))
+                    .with_context(|| format!("回放行情中缺少 {} {} K线", symbol, interval))?;
+                let start = history.len().saturating_sub(limit as usize);
+                Ok(history[start..].to_vec())
+            }
+        }
+    }
+
+    async fn fetch_current_price(&self, symbol: &str) -> Result<f64> {
+        self.mark_price(symbol).await
+    }
+
+    async fn fetch_book_ticker(&self, symbol: &str) -> Result<BookTicker> {
+        let mark = self.mark_price(symbol).await?;
+        self.book_ticker(symbol, mark).await
+    }
+
+    // 回放行情没有历史盘口，返回空订单簿
+    async fn fetch_depth(&self, symbol: &str) -> Result<OrderBook> {
+        match self.mode {
+            PriceMode::Live => self.market.fetch_depth(symbol).await,
+            PriceMode::Replay => Ok(OrderBook::default()),
+        }
+    }
+
+    // 回放行情没有历史资金费率与持仓量
+    async fn fetch_derivatives(
+        &self,
+        symbol: &str,
+        interval: &str,
+    ) -> Result<Option<DerivativesSnapshot>> {
+        match self.mode {
+            PriceMode::Live => self.market.fetch_derivatives(symbol, interval).await,
+            PriceMode::Replay => Ok(None),
+        }
+    }
+
+    async fn fetch_symbol_constraints(
+        &self,
+        symbols: &[String],
+    ) -> Result<HashMap<String, SymbolConstraints>> {
+        self.market.fetch_symbol_constraints(symbols).await
+    }
+
+    async fn get_account_info(&self) -> Result<AccountInfo> {
+        self.refresh_marks().await?;
+        let state = self.state.lock().unwrap();
+        Ok(AccountInfo {
+            totalWalletBalance: format!("{:.8}", state.wallet_balance),
+            availableBalance: format!("{:.8}", state.available_balance().max(0.0)),
+        })
+    }
+
+    async fn get_positions(&self, symbol: &str) -> Result<PositionBook> {
+        let has_position = {
+            let state = self.state.lock().unwrap();
+            state.positions.keys().any(|(s, _)| s == symbol)
+        };
+        if has_position && matches!(self.mode, PriceMode::Live) {
+            self.mark_price(symbol).await?;
+        }
+
+        let state = self.state.lock().unwrap();
+        let legs = [PositionSide::Long, PositionSide::Short]
+            .into_iter()
+            .filter_map(|side| {
+                let pos = state.positions.get(&(symbol.to_string(), side.clone()))?;
+                let mark = state.marks.get(symbol).copied().unwrap_or(pos.entry_price);
+                // 模拟盘不执行强平，按逐仓公式估算强平价供参考
+                let margin_per_unit = pos.margin / pos.amount;
+                let maintenance = pos.entry_price * MAINT_MARGIN_RATE;
+                let liquidation_price = match side {
+                    PositionSide::Long => pos.entry_price - margin_per_unit + maintenance,
+                    PositionSide::Short => pos.entry_price + margin_per_unit - maintenance,
+                };
+                Some(Position {
+                    unrealized_pnl: side_pnl(&side, pos.entry_price, mark, pos.amount),
+                    side,
+                    amount: pos.amount,
+                    entry_price: pos.entry_price,
+                    mark_price: mark,
+                    liquidation_price: liquidation_price.max(0.0),
+                    margin: pos.margin,
+                    leverage: state.leverage_for(symbol),
+                })
+            });
+        Ok(PositionBook::from_legs(legs))
+    }
+
+    async fn income(&self, start_ms: i64, end_ms: i64) -> Result<Vec<Income>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .income
+            .iter()
+            .filter(|i| i.time >= start_ms && i.time <= end_ms)
+            .cloned()
+            .collect())
+    }
+
+    async fn account_trades(
+        &self,
+        symbol: &str,
+        start_ms: i64,
+        end_ms: i64,
+    ) -> Result<Vec<AccountTrade>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .trades
+            .iter()
+            .filter(|t| t.symbol == symbol && t.fill.time >= start_ms && t.fill.time <= end_ms)
+            .cloned()
+            .collect())
+    }
+
+    fn position_mode(&self) -> PositionMode {
+        self.state.lock().unwrap().position_mode
+    }
+
+    async fn apply_position_mode(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn set_leverage(&self, symbol: &str, leverage: u32) -> Result<()> {
+        if leverage == 0 {
+            bail!("杠杆倍数必须大于0");
+        }
+        self.state
+            .lock()
+            .unwrap()
+            .leverage
+            .insert(symbol.to_string(), leverage);
+        Ok(())
+    }
+
+    async fn leverage_brackets(&self, symbol: &str) -> Result<Vec<LeverageBracket>> {
+        self.market.leverage_brackets(symbol).await
+    }
+
+    async fn place_order(&self, order: &OrderRequest) -> Result<OrderState> {
+        if let Some(client_order_id) = &order.client_order_id {
+            let state = self.state.lock().unwrap();
+            let duplicated = state
+                .client_order_ids
+                .get(client_order_id)
+                .is_some_and(|id| state.orders.iter().any(|o| o.order_id == *id));
+            if duplicated {
+                bail!("订单失败 [code:-4116]: ClientOrderId is duplicated.");
+            }
+        }
+
+        let placed = self.match_order(order).await?;
+        if let Some(client_order_id) = &order.client_order_id {
+            self.state
+                .lock()
+                .unwrap()
+                .client_order_ids
+                .insert(client_order_id.clone(), placed.order_id);
+        }
+        Ok(placed)
+    }
+
+    async fn get_order(&self, symbol: &str, order_id: i64) -> Result<OrderState> {
+        // 实时模式下先用最新价撮合挂单
+        let resting = {
+            let state = self.state.lock().unwrap();
+            state
+                .orders
+                .iter()
+                .any(|o| o.symbol == symbol && o.order_id == order_id)
+        };
+        if resting && matches!(self.mode, PriceMode::Live) {
+            self.mark_price(symbol).await?;
+        }
+
+        let state = self.state.lock().unwrap();
+        state
+            .order_states
+            .get(&order_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("查询订单失败 [code:-2013]: Order does not exist."))
+    }
+
+    async fn find_order(&self, symbol: &str, client_order_id: &str) -> Result<Option<OrderState>> {
+        let order_id = self
+            .state
+            .lock()
+            .unwrap()
+            .client_order_ids
+            .get(client_order_id)
+            .copied();
+        match order_id {
+            Some(order_id) => self.get_order(symbol, order_id).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn order_fills(&self, _symbol: &str, order_id: i64) -> Result<Vec<Fill>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.fills.get(&order_id).cloned().unwrap_or_default())
+    }
+
+    async fn open_orders(&self, symbol: &str) -> Result<Vec<OpenOrder>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .orders
+            .iter()
+            .filter(|o| o.symbol == symbol)
+            .map(|o| OpenOrder {
+                order_id: o.order_id,
+                side: o.side,
+                position_side: o.position_side.clone(),
+                order_type: o.order_type,
+                quantity: o.quantity.unwrap_or(0.0),
+                price: if o.order_type == OrderType::Limit {
+                    o.price
+                } else {
+                    0.0
+                },
+                stop_price: if o.order_type.is_protective() {
+                    o.price
+                } else {
+                    0.0
+                },
+                close_position: o.quantity.is_none(),
+            })
+            .collect())
+    }
+
+    async fn cancel_order(&self, symbol: &str, order_id: i64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let before = state.orders.len();
+        state
+            .orders
+            .retain(|o| !(o.symbol == symbol && o.order_id == order_id));
+        if state.orders.len() == before {
+            bail!("撤单失败 [code:-2011]: Unknown order sent.");
+        }
+        state.record(order_id, OrderStatus::Canceled, 0.0, 0.0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::BinanceExchange;
+
+    const SYMBOL: &str = "BTCUSDT";
+
+    // 吃单 0.05%、挂单 0.02%、滑点 10bps，回放行情不访问 market
+    fn exchange(mode: PositionMode) -> PaperExchange {
+        let config = PaperConfig {
+            initial_balance: 10_000.0,
+            taker_fee_rate: 0.0005,
+            maker_fee_rate: 0.0002,
+            slippage_bps: 10.0,
+        };
+        PaperExchange::replay(Arc::new(BinanceExchange::new("", "")), config)
+            .with_position_mode(mode)
+    }
+
+    fn state(mode: PositionMode) -> PaperState {
+        PaperState {
+            wallet_balance: 1_000.0,
+            leverage: HashMap::from([(SYMBOL.to_string(), 10)]),
+            position_mode: mode,
+            next_order_id: 1,
+            ..PaperState::default()
+        }
+    }
+
+    fn bar(timestamp: i64, open: f64, high: f64, low: f64, close: f64) -> Kline {
+        Kline {
+            timestamp,
+            open,
+            high,
+            low,
+            close,
+            volume: 1.0,
+            taker_buy_volume: 0.0,
+            taker_buy_quote_volume: 0.0,
+        }
+    }
+
+    fn leg(state: &PaperState, side: PositionSide) -> Option<&PaperPosition> {
+        state.positions.get(&(SYMBOL.to_string(), side))
+    }
+
+    fn approx(actual: f64, expected: f64) -> bool {
+        (actual - expected).abs() < 1e-9
+    }
+
+    #[tokio::test]
+    async fn market_fills_pay_slippage_and_taker_fee() {
+        let paper = exchange(PositionMode::Hedge);
+        paper
+            .push_kline(SYMBOL, "1m", bar(0, 100.0, 100.0, 100.0, 100.0))
+            .unwrap();
+        paper.set_leverage(SYMBOL, 10).await.unwrap();
+
+        // 买入 2 @ 100 × 1.001 = 100.1：保证金 200.2 / 10，手续费 200.2 × 0.0005
+        let opened = paper
+            .place_order(&OrderRequest::market(
+                SYMBOL,
+                OrderSide::Buy,
+                PositionSide::Long,
+                2.0,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(opened.status, OrderStatus::Filled);
+        assert!(approx(opened.avg_price, 100.1));
+        {
+            let state = paper.state.lock().unwrap();
+            assert!(approx(
+                leg(&state, PositionSide::Long).unwrap().margin,
+                20.02
+            ));
+            assert!(approx(state.wallet_balance, 10_000.0 - 0.1001));
+        }
+
+        // 卖出 2 @ 100 × 0.999 = 99.9：已实现 (99.9 - 100.1) × 2，手续费 199.8 × 0.0005
+        let closed = paper
+            .place_order(&OrderRequest::market(
+                SYMBOL,
+                OrderSide::Sell,
+                PositionSide::Long,
+                2.0,
+            ))
+            .await
+            .unwrap();
+        assert!(approx(closed.avg_price, 99.9));
+        let fills = paper.order_fills(SYMBOL, closed.order_id).await.unwrap();
+        assert!(approx(fills[0].realized_pnl, -0.4));
+        assert!(approx(fills[0].commission, 0.0999));
+        assert!(approx(paper.equity(), 10_000.0 - 0.1001 - 0.4 - 0.0999));
+        assert!(paper.state.lock().unwrap().positions.is_empty());
+    }
+
+    #[test]
+    fn hedge_legs_are_margined_and_closed_independently() {
+        let mut state = state(PositionMode::Hedge);
+        // 多空各 5 @ 100，10 倍各占保证金 50，不收手续费
+        state
+            .fill(SYMBOL, OrderSide::Buy, &PositionSide::Long, 5.0, 100.0, 0.0)
+            .unwrap();
+        state
+            .fill(
+                SYMBOL,
+                OrderSide::Sell,
+                &PositionSide::Short,
+                5.0,
+                100.0,
+                0.0,
+            )
+            .unwrap();
+        assert!(approx(state.used_margin(), 100.0));
+        assert!(approx(state.available_balance(), 900.0));
+
+        // 再开多 90 @ 100 需保证金 900 + 手续费 4.5，超过可用 900
+        let err = state
+            .fill(
+                SYMBOL,
+                OrderSide::Buy,
+                &PositionSide::Long,
+                90.0,
+                100.0,
+                0.0005,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("-2019"));
+        assert!(approx(state.wallet_balance, 1_000.0));
+        assert!(approx(leg(&state, PositionSide::Long).unwrap().amount, 5.0));
+
+        // 平多 5 @ 110 已实现 +50，空头腿及其保证金不变
+        let (realized, fee) = state
+            .fill(
+                SYMBOL,
+                OrderSide::Sell,
+                &PositionSide::Long,
+                5.0,
+                110.0,
+                0.0,
+            )
+            .unwrap();
+        assert!(approx(realized, 50.0));
+        assert_eq!(fee, 0.0);
+        assert!(leg(&state, PositionSide::Long).is_none());
+        let short = leg(&state, PositionSide::Short).unwrap();
+        assert!(approx(short.amount, 5.0));
+        assert!(approx(short.margin, 50.0));
+        assert!(approx(state.wallet_balance, 1_050.0));
+    }
+
+    #[test]
+    fn one_way_fill_nets_against_opposite_leg() {
+        let mut state = state(PositionMode::OneWay);
+        // 买入 2 @ 100：保证金 20，手续费 0.1
+        state
+            .fill(
+                SYMBOL,
+                OrderSide::Buy,
+                &PositionSide::Long,
+                2.0,
+                100.0,
+                0.0005,
+            )
+            .unwrap();
+        assert!(approx(state.wallet_balance, 999.9));
+
+        // 卖出 3 @ 110：先平多 2（已实现 +20，手续费 0.11），剩余 1 开空（保证金 11，手续费 0.055）
+        let (realized, fee) = state
+            .fill(
+                SYMBOL,
+                OrderSide::Sell,
+                &PositionSide::Short,
+                3.0,
+                110.0,
+                0.0005,
+            )
+            .unwrap();
+        assert!(approx(realized, 20.0));
+        assert!(approx(fee, 0.165));
+        assert!(approx(state.wallet_balance, 999.9 + 20.0 - 0.165));
+        assert!(leg(&state, PositionSide::Long).is_none());
+        let short = leg(&state, PositionSide::Short).unwrap();
+        assert!(approx(short.amount, 1.0));
+        assert!(approx(short.entry_price, 110.0));
+        assert!(approx(short.margin, 11.0));
+
+        // 已无多头可平，平多单被拒绝
+        let err = state
+            .fill(
+                SYMBOL,
+                OrderSide::Sell,
+                &PositionSide::Long,
+                1.0,
+                110.0,
+                0.0005,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("-2022"));
+    }
+
+    #[tokio::test]
+    async fn limit_orders_honor_time_in_force() {
+        let paper = exchange(PositionMode::Hedge);
+        paper
+            .push_kline(SYMBOL, "1m", bar(0, 100.0, 100.0, 100.0, 100.0))
+            .unwrap();
+        let limit = |price: f64, time_in_force: TimeInForce| {
+            OrderRequest::limit(
+                SYMBOL,
+                OrderSide::Buy,
+                PositionSide::Long,
+                1.0,
+                price,
+                time_in_force,
+            )
+        };
+
+        // 可立即成交的只做 Maker 单被拒绝
+        let err = paper
+            .place_order(&limit(101.0, TimeInForce::Gtx))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("-5022"));
+
+        // 不能立即成交的 IOC/FOK 直接过期，不挂单
+        for time_in_force in [TimeInForce::Ioc, TimeInForce::Fok] {
+            let expired = paper
+                .place_order(&limit(99.0, time_in_force))
+                .await
+                .unwrap();
+            assert_eq!(expired.status, OrderStatus::Expired);
+        }
+        assert!(paper.open_orders(SYMBOL).await.unwrap().is_empty());
+
+        // GTC 挂单；可成交的 GTC 按卖一价加滑点 100.1 吃单，但不劣于限价 100.05
+        let resting = paper
+            .place_order(&limit(99.0, TimeInForce::Gtc))
+            .await
+            .unwrap();
+        assert_eq!(resting.status, OrderStatus::New);
+        let taken = paper
+            .place_order(&limit(100.05, TimeInForce::Gtc))
+            .await
+            .unwrap();
+        assert_eq!(taken.status, OrderStatus::Filled);
+        assert!(approx(taken.avg_price, 100.05));
+
+        // 最低价穿越 99 后挂单按限价以挂单费率成交：99 × 0.0002
+        paper
+            .push_kline(SYMBOL, "1m", bar(60_000, 100.0, 100.0, 98.5, 99.5))
+            .unwrap();
+        let filled = paper.get_order(SYMBOL, resting.order_id).await.unwrap();
+        assert_eq!(filled.status, OrderStatus::Filled);
+        assert!(approx(filled.avg_price, 99.0));
+        let fills = paper.order_fills(SYMBOL, resting.order_id).await.unwrap();
+        assert!(fills[0].maker);
+        assert!(approx(fills[0].commission, 0.0198));
+    }
+
+    #[tokio::test]
+    async fn stop_fills_before_take_profit_in_same_bar() {
+        let paper = exchange(PositionMode::Hedge);
+        paper
+            .push_kline(SYMBOL, "1m", bar(0, 100.0, 100.0, 100.0, 100.0))
+            .unwrap();
+        paper
+            .place_order(&OrderRequest::market(
+                SYMBOL,
+                OrderSide::Buy,
+                PositionSide::Long,
+                1.0,
+            ))
+            .await
+            .unwrap();
+
+        // 已被当前价触发的止损单被拒绝
+        let err = paper
+            .place_order(&OrderRequest::protective(
+                SYMBOL,
+                OrderType::StopMarket,
+                PositionSide::Long,
+                101.0,
+                None,
+            ))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("-2021"));
+
+        let stop = paper
+            .place_order(&OrderRequest::protective(
+                SYMBOL,
+                OrderType::StopMarket,
+                PositionSide::Long,
+                95.0,
+                None,
+            ))
+            .await
+            .unwrap();
+        let take_profit = paper
+            .place_order(&OrderRequest::protective(
+                SYMBOL,
+                OrderType::TakeProfitMarket,
+                PositionSide::Long,
+                105.0,
+                None,
+            ))
+            .await
+            .unwrap();
+
+        // 同一根K线触及 94 与 106：止损按 95 × 0.999 成交，止盈随持仓归零过期
+        paper
+            .push_kline(SYMBOL, "1m", bar(60_000, 100.0, 106.0, 94.0, 100.0))
+            .unwrap();
+        let stopped = paper.get_order(SYMBOL, stop.order_id).await.unwrap();
+        assert_eq!(stopped.status, OrderStatus::Filled);
+        assert!(approx(stopped.avg_price, 94.905));
+        let expired = paper.get_order(SYMBOL, take_profit.order_id).await.unwrap();
+        assert_eq!(expired.status, OrderStatus::Expired);
+        assert!(paper.open_orders(SYMBOL).await.unwrap().is_empty());
+
+        // 开仓手续费 100.1 × 0.0005，已实现 94.905 - 100.1，平仓手续费 94.905 × 0.0005
+        let state = paper.state.lock().unwrap();
+        assert!(state.positions.is_empty());
+        assert!(approx(
+            state.wallet_balance,
+            10_000.0 - 0.05005 - 5.195 - 0.0474525
+        ));
+    }
+
+    #[tokio::test]
+    async fn account_survives_persist_and_restore() {
+        let dir = std::env::temp_dir().join(format!("paper_{}", std::process::id()));
+        let path = dir.join(ACCOUNT_FILE);
+
+        let paper = exchange(PositionMode::Hedge);
+        paper
+            .push_kline(SYMBOL, "1m", bar(0, 100.0, 100.0, 100.0, 100.0))
+            .unwrap();
+        paper.set_leverage(SYMBOL, 10).await.unwrap();
+        paper
+            .place_order(&OrderRequest::market(
+                SYMBOL,
+                OrderSide::Buy,
+                PositionSide::Long,
+                1.0,
+            ))
+            .await
+            .unwrap();
+        paper
+            .place_order(
+                &OrderRequest::protective(
+                    SYMBOL,
+                    OrderType::StopMarket,
+                    PositionSide::Long,
+                    95.0,
+                    None,
+                )
+                .with_client_order_id("ma-stop".to_string()),
+            )
+            .await
+            .unwrap();
+        paper.persist_to(&path).unwrap();
+
+        // 文件不存在时保持初始资金
+        let restored = exchange(PositionMode::Hedge);
+        restored.restore_from(&dir.join("missing.json")).unwrap();
+        assert_eq!(restored.state.lock().unwrap().wallet_balance, 10_000.0);
+
+        restored.restore_from(&path).unwrap();
+        {
+            let state = restored.state.lock().unwrap();
+            assert!(approx(state.wallet_balance, 10_000.0 - 0.05005));
+            let long = leg(&state, PositionSide::Long).unwrap();
+            assert!(approx(long.amount, 1.0));
+            assert!(approx(long.entry_price, 100.1));
+            assert!(approx(long.margin, 10.01));
+            assert_eq!(state.leverage_for(SYMBOL), 10);
+            assert_eq!(state.trades.len(), 1);
+        }
+        let stop = restored
+            .find_order(SYMBOL, "ma-stop")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stop.status, OrderStatus::New);
+        assert_eq!(restored.open_orders(SYMBOL).await.unwrap().len(), 1);
+
+        // 订单号在恢复后继续递增
+        restored
+            .push_kline(SYMBOL, "1m", bar(60_000, 100.0, 100.0, 100.0, 100.0))
+            .unwrap();
+        let next = restored
+            .place_order(&OrderRequest::market(
+                SYMBOL,
+                OrderSide::Sell,
+                PositionSide::Long,
+                1.0,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(next.order_id, stop.order_id + 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,27 +1,252 @@
+use crate::exchange::{AccountTrade, Exchange, Income, IncomeKind};
 use crate::logging;
-use crate::types::{TradeAction, TradeResult};
-use anyhow::{Context, Result};
+use crate::types::{PositionBook, PositionSide, TradeAction, TradeResult};
+use anyhow::{bail, Context, Result};
 use chrono::Utc;
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
-use std::fs::{create_dir_all, write};
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs::{create_dir_all, read_to_string, write, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
+// 资金流水可能延迟入账，每次同步与上次区间重叠并按 tranId 去重
+const INCOME_OVERLAP_MS: i64 = 5 * 60 * 1000;
+const YEAR_MS: f64 = 365.0 * 24.0 * 3600.0 * 1000.0;
+// performance.json 结构版本：1 为仅含快照的旧格式（无版本字段），2 起附带跟踪器内部状态
+const SCHEMA_VERSION: u32 = 2;
+
+// 基于权益曲线与平仓交易的绩效指标；年化按采样间隔折算，无风险利率取 0
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PerformanceMetrics {
+    pub return_pct: f64,
+    pub annualized_return_pct: Option<f64>,
+    pub volatility_pct: f64, // 年化波动率
+    pub sharpe: Option<f64>,
+    pub sortino: Option<f64>,
+    pub calmar: Option<f64>,
+    pub max_drawdown_pct: f64,
+    pub closed_trades: u64,
+    pub profit_factor: Option<f64>,
+    pub avg_win: Option<f64>,
+    pub avg_loss: Option<f64>, // 负数
+    pub exposure_pct: f64,     // 有持仓的采样占比
+}
+
+fn fmt_ratio(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| format!("{:.2}", v))
+}
+
+impl fmt::Display for PerformanceMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "收益 {:+.2}%, 年化波动 {:.2}%, Sharpe {}, Sortino {}, Calmar {}, 最大回撤 {:.2}%, 盈亏因子 {}, 平均盈/亏 {}/{}, 持仓时间 {:.1}%",
+            self.return_pct,
+            self.volatility_pct,
+            fmt_ratio(self.sharpe),
+            fmt_ratio(self.sortino),
+            fmt_ratio(self.calmar),
+            self.max_drawdown_pct,
+            fmt_ratio(self.profit_factor),
+            fmt_ratio(self.avg_win),
+            fmt_ratio(self.avg_loss),
+            self.exposure_pct
+        )
+    }
+}
+
+// 单个标的的盈亏归因，金额均带符号（手续费为负）
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SymbolPnl {
+    pub realized_pnl: f64,
+    pub commission: f64,
+    pub funding: f64,
+    pub net_pnl: f64,
+    #[serde(default)]
+    pub metrics: PerformanceMetrics, // 以组合初始权益为基数
+}
+
+// 权益曲线采样点
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EquitySample {
+    pub time: i64,
+    pub equity: f64, // 钱包余额 + 未实现盈亏
+    pub symbols: BTreeMap<String, SymbolSample>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SymbolSample {
+    pub pnl: f64, // 累计净盈亏 + 未实现盈亏
+    pub exposed: bool,
+}
+
+// 平仓交易的盈亏统计
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct TradeStats {
+    wins: u64,
+    losses: u64,
+    gross_profit: f64,
+    gross_loss: f64,
+}
+
+impl TradeStats {
+    fn record(&mut self, net_pnl: f64) {
+        if net_pnl > 0.0 {
+            self.wins += 1;
+            self.gross_profit += net_pnl;
+        } else if net_pnl < 0.0 {
+            self.losses += 1;
+            self.gross_loss += -net_pnl;
+        }
+    }
+}
+
+// 由 (时间, 权益, 是否持仓) 序列与交易统计计算指标
+fn compute_metrics(points: &[(i64, f64, bool)], stats: &TradeStats) -> PerformanceMetrics {
+    let mut metrics = PerformanceMetrics {
+        closed_trades: stats.wins + stats.losses,
+        profit_factor: (stats.gross_loss > 0.0).then(|| stats.gross_profit / stats.gross_loss),
+        avg_win: (stats.wins > 0).then(|| stats.gross_profit / stats.wins as f64),
+        avg_loss: (stats.losses > 0).then(|| -stats.gross_loss / stats.losses as f64),
+        ..PerformanceMetrics::default()
+    };
+    if points.is_empty() {
+        return metrics;
+    }
+    let exposed = points.iter().filter(|(_, _, exposed)| *exposed).count();
+    metrics.exposure_pct = exposed as f64 / points.len() as f64 * 100.0;
+
+    let (first_time, first) = (points[0].0, points[0].1);
+    let (last_time, last) = (points[points.len() - 1].0, points[points.len() - 1].1);
+    if points.len() < 2 || first <= 0.0 || last_time <= first_time {
+        return metrics;
+    }
+    metrics.return_pct = (last / first - 1.0) * 100.0;
+
+    let mut peak = first;
+    for &(_, equity, _) in points {
+        peak = peak.max(equity);
+        if peak > 0.0 {
+            metrics.max_drawdown_pct = metrics.max_drawdown_pct.max((peak - equity) / peak * 100.0);
+        }
+    }
+
+    let returns: Vec<f64> = points
+        .windows(2)
+        .filter(|w| w[0].1 > 0.0)
+        .map(|w| w[1].1 / w[0].1 - 1.0)
+        .collect();
+    let span_ms = (last_time - first_time) as f64;
+    let periods_per_year = YEAR_MS * returns.len() as f64 / span_ms;
+    let n = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let std = if returns.len() > 1 {
+        (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+    } else {
+        0.0
+    };
+    let downside = (returns.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / n).sqrt();
+    let annualize = periods_per_year.sqrt();
+
+    metrics.volatility_pct = std * annualize * 100.0;
+    metrics.sharpe = (std > 0.0).then(|| mean / std * annualize);
+    metrics.sortino = (downside > 0.0).then(|| mean / downside * annualize);
+    // 区间过短时年化收益会溢出，此时不给出年化与 Calmar
+    let annualized = ((last / first).powf(YEAR_MS / span_ms) - 1.0) * 100.0;
+    metrics.annualized_return_pct = annualized.is_finite().then_some(annualized);
+    metrics.calmar = metrics
+        .annualized_return_pct
+        .filter(|_| metrics.max_drawdown_pct > 0.0)
+        .map(|annualized| annualized / metrics.max_drawdown_pct);
+    metrics
+}
+
+// 总计与分标的盈亏来自交易所资金流水；回撤与比率指标来自每周期采样的权益曲线；
+// 胜负与最佳/最差按单笔交易净盈亏统计
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct PerformanceSnapshot {
     pub total_realized_pnl: f64,
+    #[serde(default)]
+    pub total_commission: f64,
+    #[serde(default)]
+    pub total_funding: f64,
+    #[serde(default)]
+    pub net_pnl: f64, // 已实现盈亏 + 手续费 + 资金费
     pub total_trades: u64,
     pub winning_trades: u64,
     pub losing_trades: u64,
     pub best_trade: Option<f64>,
     pub worst_trade: Option<f64>,
     pub equity_peak: f64,
-    pub max_drawdown: f64,
+    pub max_drawdown: f64, // 权益曲线最大回撤 (USDT)
+    #[serde(default)]
+    pub metrics: PerformanceMetrics,
+    #[serde(default)]
+    pub per_symbol: BTreeMap<String, SymbolPnl>,
     pub last_update: Option<i64>,
 }
 
+// 单笔平仓交易的盈亏归因：开平仓手续费与持仓期间资金费计入该笔交易
+#[derive(Clone, Debug, Serialize)]
+pub struct TradePnl {
+    pub symbol: String,
+    pub timestamp: i64,
+    pub action: TradeAction,
+    pub realized_pnl: f64,
+    pub commission: f64, // 正数表示支出
+    pub funding: f64,    // 正数表示收入
+    pub net_pnl: f64,
+}
+
+// 重启后继续同步资金流水与归因所需的内部状态
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct TrackerState {
+    income_cursor: Option<i64>,
+    seen_income: HashMap<i64, i64>,
+    open_commission: HashMap<String, f64>,
+    pending_funding: HashMap<String, f64>,
+    trade_stats: TradeStats,
+    symbol_trade_stats: HashMap<String, TradeStats>,
+    #[serde(default)]
+    bot_orders: HashMap<String, i64>,
+    #[serde(default)]
+    seen_trades: HashMap<String, i64>,
+}
+
+// performance.json 的磁盘格式：快照字段平铺在顶层，便于直接查看
+#[derive(Serialize, Deserialize)]
+struct PersistedPerformance {
+    #[serde(default = "legacy_schema_version")]
+    schema_version: u32,
+    #[serde(flatten)]
+    snapshot: PerformanceSnapshot,
+    #[serde(default)]
+    state: TrackerState,
+}
+
+fn legacy_schema_version() -> u32 {
+    1
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct PerformanceTracker {
     snapshot: PerformanceSnapshot,
+    income_cursor: Option<i64>,
+    seen_income: HashMap<i64, i64>, // tranId → 时间
+    open_commission: HashMap<String, f64>,
+    pending_funding: HashMap<String, f64>,
+    equity_curve: Vec<EquitySample>,
+    trade_stats: TradeStats,
+    symbol_trade_stats: HashMap<String, TradeStats>,
+    bot_orders: HashMap<String, i64>, // 本程序成交的 "标的:订单号" → 时间
+    seen_trades: HashMap<String, i64>, // 已归因的 "标的:成交号" → 时间
+}
+
+// 成交与订单号仅在标的内唯一
+fn trade_key(symbol: &str, id: i64) -> String {
+    format!("{}:{}", symbol, id)
 }
 
 impl PerformanceTracker {
@@ -29,57 +254,466 @@ impl PerformanceTracker {
         Self::default()
     }
 
+    // 从 performance.json 与 equity.jsonl 恢复；文件不存在时返回全新的跟踪器
+    pub fn load() -> Result<Self> {
+        let path = logs_path("performance.json");
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+        let content = read_to_string(&path).context("读取绩效数据失败")?;
+        let value: serde_json::Value =
+            serde_json::from_str(&content).context("解析绩效数据失败")?;
+        // 先检查版本，更新版本写入的文件字段可能无法按当前结构解析
+        let version = value
+            .get("schema_version")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(legacy_schema_version() as u64);
+        if version > SCHEMA_VERSION as u64 {
+            bail!(
+                "绩效数据版本 {} 高于当前支持的版本 {}",
+                version,
+                SCHEMA_VERSION
+            );
+        }
+        let persisted: PersistedPerformance =
+            serde_json::from_value(value).context("解析绩效数据失败")?;
+
+        let state = persisted.state;
+        let mut tracker = PerformanceTracker {
+            snapshot: persisted.snapshot,
+            income_cursor: state.income_cursor,
+            seen_income: state.seen_income,
+            open_commission: state.open_commission,
+            pending_funding: state.pending_funding,
+            equity_curve: load_equity_curve()?,
+            trade_stats: state.trade_stats,
+            symbol_trade_stats: state.symbol_trade_stats,
+            bot_orders: state.bot_orders,
+            seen_trades: state.seen_trades,
+        };
+        if persisted.schema_version < 2 {
+            // 版本 1 的峰值与回撤按累计已实现盈亏计算，改由权益曲线重算；
+            // 其胜负统计没有盈亏金额，盈亏因子与平均盈亏只覆盖之后的交易
+            tracker.replay_drawdown();
+        }
+        tracker.refresh_metrics();
+        Ok(tracker)
+    }
+
+    // 重放 trades.jsonl 重建统计，已实现盈亏与手续费取自交易回报；
+    // 资金费与交易所端止盈止损成交不在交易日志中，只从重建后的首次资金流水同步起计入
+    pub fn rebuild_from_trades() -> Result<Self> {
+        let mut tracker = Self::new();
+        let path = logs_path("trades.jsonl");
+        if path.exists() {
+            let content = read_to_string(&path).context("读取交易日志失败")?;
+            for (index, line) in content.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<TradeResult>(line) {
+                    Ok(trade) => tracker.replay_trade(&trade),
+                    Err(e) => warn!("trades.jsonl 第 {} 行解析失败，跳过: {}", index + 1, e),
+                }
+            }
+        }
+        tracker.equity_curve = load_equity_curve()?;
+        tracker.replay_drawdown();
+        tracker.refresh_metrics();
+        Ok(tracker)
+    }
+
+    fn replay_trade(&mut self, trade: &TradeResult) {
+        let flows = [
+            (IncomeKind::RealizedPnl, trade.pnl.unwrap_or(0.0)),
+            (IncomeKind::Commission, -trade.commission.unwrap_or(0.0)),
+        ];
+        for (kind, amount) in flows {
+            if amount != 0.0 {
+                self.record_income(&Income {
+                    symbol: trade.symbol.clone(),
+                    kind,
+                    amount,
+                    asset: "USDT".to_string(),
+                    time: trade.timestamp,
+                    tran_id: 0,
+                });
+            }
+        }
+        self.update(trade);
+    }
+
+    // 按已加载的权益曲线重算峰值与最大回撤
+    fn replay_drawdown(&mut self) {
+        self.snapshot.equity_peak = 0.0;
+        self.snapshot.max_drawdown = 0.0;
+        for sample in &self.equity_curve {
+            self.snapshot.equity_peak = self.snapshot.equity_peak.max(sample.equity);
+            self.snapshot.max_drawdown = self
+                .snapshot
+                .max_drawdown
+                .max(self.snapshot.equity_peak - sample.equity);
+        }
+    }
+
     pub fn snapshot(&self) -> &PerformanceSnapshot {
         &self.snapshot
     }
 
-    pub fn update(&mut self, trade: &TradeResult) -> bool {
+    pub fn equity_samples(&self) -> usize {
+        self.equity_curve.len()
+    }
+
+    // 更新交易统计；平仓交易返回其盈亏归因
+    pub fn update(&mut self, trade: &TradeResult) -> Option<TradePnl> {
         if matches!(trade.action, TradeAction::Hold) {
-            return false;
+            return None;
         }
 
         self.snapshot.total_trades += 1;
+        self.snapshot.last_update = Some(Utc::now().timestamp());
+        // 本程序的成交已在此归因，同步成交记录时跳过
+        for order_id in &trade.order_ids {
+            self.bot_orders
+                .insert(trade_key(&trade.symbol, *order_id), trade.timestamp);
+        }
 
-        if let Some(pnl) = trade.pnl {
-            self.snapshot.total_realized_pnl += pnl;
+        let commission = trade.commission.unwrap_or(0.0);
+        let Some(realized_pnl) = trade.pnl else {
+            // 开仓手续费留待平仓时计入
+            *self
+                .open_commission
+                .entry(trade.symbol.clone())
+                .or_default() += commission;
+            return None;
+        };
+
+        Some(self.record_close(
+            &trade.symbol,
+            trade.timestamp,
+            trade.action.clone(),
+            realized_pnl,
+            commission,
+        ))
+    }
 
-            self.snapshot.best_trade = match self.snapshot.best_trade {
-                Some(best) => Some(best.max(pnl)),
-                None => Some(pnl),
-            };
+    // 平仓交易计入开仓手续费与持仓期间资金费并更新胜负统计
+    fn record_close(
+        &mut self,
+        symbol: &str,
+        timestamp: i64,
+        action: TradeAction,
+        realized_pnl: f64,
+        commission: f64,
+    ) -> TradePnl {
+        let commission = commission + self.open_commission.remove(symbol).unwrap_or_default();
+        let funding = self.pending_funding.remove(symbol).unwrap_or_default();
+        let net_pnl = realized_pnl - commission + funding;
 
-            self.snapshot.worst_trade = match self.snapshot.worst_trade {
-                Some(worst) => Some(worst.min(pnl)),
-                None => Some(pnl),
-            };
+        self.snapshot.best_trade = match self.snapshot.best_trade {
+            Some(best) => Some(best.max(net_pnl)),
+            None => Some(net_pnl),
+        };
+
+        self.snapshot.worst_trade = match self.snapshot.worst_trade {
+            Some(worst) => Some(worst.min(net_pnl)),
+            None => Some(net_pnl),
+        };
+
+        if net_pnl > 0.0 {
+            self.snapshot.winning_trades += 1;
+        } else if net_pnl < 0.0 {
+            self.snapshot.losing_trades += 1;
+        }
+        self.trade_stats.record(net_pnl);
+        self.symbol_trade_stats
+            .entry(symbol.to_string())
+            .or_default()
+            .record(net_pnl);
 
-            if pnl > 0.0 {
-                self.snapshot.winning_trades += 1;
-            } else if pnl < 0.0 {
-                self.snapshot.losing_trades += 1;
+        TradePnl {
+            symbol: symbol.to_string(),
+            timestamp,
+            action,
+            realized_pnl,
+            commission,
+            funding,
+            net_pnl,
+        }
+    }
+
+    // 拉取上次同步以来的资金流水并计入总计与分标的数据；首次调用只确定起点。
+    // 交易所侧止损止盈与强平不经过 update，按成交记录归因并返回这些平仓交易
+    pub async fn sync_income(
+        &mut self,
+        exchange: &dyn Exchange,
+        symbols: &[String],
+    ) -> Result<Vec<TradePnl>> {
+        let now = exchange.now_millis();
+        let Some(cursor) = self.income_cursor else {
+            self.income_cursor = Some(now);
+            return Ok(Vec::new());
+        };
+
+        let start = cursor - INCOME_OVERLAP_MS;
+        let incomes: Vec<Income> = exchange
+            .income(start, now)
+            .await
+            .context("同步资金流水失败")?
+            .into_iter()
+            .filter(|income| {
+                symbols.contains(&income.symbol) && !self.seen_income.contains_key(&income.tran_id)
+            })
+            .collect();
+
+        // 有新的已实现盈亏时才查询该标的成交记录；全部查询成功后再计入，失败时下次整体重试
+        let closed_symbols: BTreeSet<&str> = incomes
+            .iter()
+            .filter(|income| income.kind == IncomeKind::RealizedPnl)
+            .map(|income| income.symbol.as_str())
+            .collect();
+        let mut trades = Vec::new();
+        for symbol in closed_symbols {
+            trades.extend(
+                exchange
+                    .account_trades(symbol, start, now)
+                    .await
+                    .with_context(|| format!("同步 {} 成交记录失败", symbol))?,
+            );
+        }
+
+        for income in &incomes {
+            self.seen_income.insert(income.tran_id, income.time);
+            self.record_income(income);
+        }
+        let closes = self.attribute_external_closes(&trades);
+
+        self.seen_income
+            .retain(|_, time| *time >= now - 2 * INCOME_OVERLAP_MS);
+        self.seen_trades
+            .retain(|_, time| *time >= now - 2 * INCOME_OVERLAP_MS);
+        self.bot_orders
+            .retain(|_, time| *time >= now - 2 * INCOME_OVERLAP_MS);
+        self.income_cursor = Some(now);
+        Ok(closes)
+    }
+
+    // 非本程序订单的平仓成交按标的与持仓方向合并为一笔交易
+    fn attribute_external_closes(&mut self, trades: &[AccountTrade]) -> Vec<TradePnl> {
+        let mut groups: BTreeMap<(String, bool), (i64, f64, f64)> = BTreeMap::new();
+        for trade in trades {
+            let key = trade_key(&trade.symbol, trade.fill.trade_id);
+            if !trade.is_close()
+                || self.seen_trades.contains_key(&key)
+                || self
+                    .bot_orders
+                    .contains_key(&trade_key(&trade.symbol, trade.order_id))
+            {
+                continue;
             }
+            self.seen_trades.insert(key, trade.fill.time);
+            let long = trade.position_side == PositionSide::Long;
+            let (time, realized, commission) = groups
+                .entry((trade.symbol.clone(), long))
+                .or_insert((0, 0.0, 0.0));
+            *time = (*time).max(trade.fill.time);
+            *realized += trade.fill.realized_pnl;
+            if trade.fill.commission_asset == "USDT" {
+                *commission += trade.fill.commission;
+            }
+        }
 
-            let equity = self.snapshot.total_realized_pnl;
-            if equity > self.snapshot.equity_peak {
-                self.snapshot.equity_peak = equity;
-            } else {
-                let drawdown = self.snapshot.equity_peak - equity;
-                if drawdown > self.snapshot.max_drawdown {
-                    self.snapshot.max_drawdown = drawdown;
-                }
+        groups
+            .into_iter()
+            .map(|((symbol, long), (time, realized, commission))| {
+                let action = if long {
+                    TradeAction::CloseLong
+                } else {
+                    TradeAction::CloseShort
+                };
+                self.snapshot.total_trades += 1;
+                self.record_close(&symbol, time, action, realized, commission)
+            })
+            .collect()
+    }
+
+    fn record_income(&mut self, income: &Income) {
+        // 手续费可能以 BNB 等资产抵扣，无法直接与 USDT 相加
+        if income.asset != "USDT" {
+            debug!(
+                "忽略非 USDT 流水: {} {:?} {} {}",
+                income.symbol, income.kind, income.amount, income.asset
+            );
+            return;
+        }
+
+        let entry = self
+            .snapshot
+            .per_symbol
+            .entry(income.symbol.clone())
+            .or_default();
+        match income.kind {
+            IncomeKind::RealizedPnl => {
+                entry.realized_pnl += income.amount;
+                self.snapshot.total_realized_pnl += income.amount;
+            }
+            IncomeKind::Commission => {
+                entry.commission += income.amount;
+                self.snapshot.total_commission += income.amount;
+            }
+            IncomeKind::FundingFee => {
+                entry.funding += income.amount;
+                self.snapshot.total_funding += income.amount;
+                *self
+                    .pending_funding
+                    .entry(income.symbol.clone())
+                    .or_default() += income.amount;
             }
         }
+        entry.net_pnl += income.amount;
+        self.snapshot.net_pnl += income.amount;
+        self.snapshot.last_update = Some(Utc::now().timestamp());
+    }
 
+    // 采样权益（钱包余额 + 各标的未实现盈亏），追加到 equity.jsonl 并重算指标
+    pub fn sample_equity(
+        &mut self,
+        time: i64,
+        wallet_balance: f64,
+        positions: &HashMap<String, PositionBook>,
+    ) -> Result<()> {
+        let mut symbols = BTreeMap::new();
+        let mut unrealized_total = 0.0;
+        for (symbol, book) in positions {
+            let unrealized: f64 = book.legs().map(|pos| pos.unrealized_pnl).sum();
+            unrealized_total += unrealized;
+            let net_pnl = self
+                .snapshot
+                .per_symbol
+                .get(symbol)
+                .map_or(0.0, |pnl| pnl.net_pnl);
+            symbols.insert(
+                symbol.clone(),
+                SymbolSample {
+                    pnl: net_pnl + unrealized,
+                    exposed: !book.is_flat(),
+                },
+            );
+        }
+        let sample = EquitySample {
+            time,
+            equity: wallet_balance + unrealized_total,
+            symbols,
+        };
+        append_equity_sample(&sample)?;
+
+        if sample.equity > self.snapshot.equity_peak {
+            self.snapshot.equity_peak = sample.equity;
+        }
+        self.snapshot.max_drawdown = self
+            .snapshot
+            .max_drawdown
+            .max(self.snapshot.equity_peak - sample.equity);
+        self.equity_curve.push(sample);
+        self.refresh_metrics();
         self.snapshot.last_update = Some(Utc::now().timestamp());
-        true
+        Ok(())
+    }
+
+    fn refresh_metrics(&mut self) {
+        let points: Vec<(i64, f64, bool)> = self
+            .equity_curve
+            .iter()
+            .map(|s| (s.time, s.equity, s.symbols.values().any(|sym| sym.exposed)))
+            .collect();
+        self.snapshot.metrics = compute_metrics(&points, &self.trade_stats);
+
+        // 分标的曲线 = 组合初始权益 + 该标的累计盈亏
+        let Some(base) = self.equity_curve.first().map(|s| s.equity) else {
+            return;
+        };
+        let symbols: Vec<String> = self.snapshot.per_symbol.keys().cloned().collect();
+        for symbol in symbols {
+            let points: Vec<(i64, f64, bool)> = self
+                .equity_curve
+                .iter()
+                .map(|s| {
+                    let sample = s.symbols.get(&symbol).cloned().unwrap_or_default();
+                    (s.time, base + sample.pnl, sample.exposed)
+                })
+                .collect();
+            let stats = self
+                .symbol_trade_stats
+                .get(&symbol)
+                .cloned()
+                .unwrap_or_default();
+            if let Some(entry) = self.snapshot.per_symbol.get_mut(&symbol) {
+                entry.metrics = compute_metrics(&points, &stats);
+            }
+        }
     }
 
     pub fn persist(&self) -> Result<()> {
         let base_dir = Path::new(logging::logs_directory());
         create_dir_all(base_dir).context("创建logs目录失败")?;
+        let persisted = PersistedPerformance {
+            schema_version: SCHEMA_VERSION,
+            snapshot: self.snapshot.clone(),
+            state: TrackerState {
+                income_cursor: self.income_cursor,
+                seen_income: self.seen_income.clone(),
+                open_commission: self.open_commission.clone(),
+                pending_funding: self.pending_funding.clone(),
+                trade_stats: self.trade_stats.clone(),
+                symbol_trade_stats: self.symbol_trade_stats.clone(),
+                bot_orders: self.bot_orders.clone(),
+                seen_trades: self.seen_trades.clone(),
+            },
+        };
+        let json = serde_json::to_string_pretty(&persisted).context("序列化绩效数据失败")?;
+        // 先写临时文件再替换，避免中断时留下不完整的文件
         let path = base_dir.join("performance.json");
-        let json = serde_json::to_string_pretty(&self.snapshot).context("序列化绩效数据失败")?;
-        write(path, json).context("写入绩效数据失败")?;
+        let tmp_path = path.with_extension("json.tmp");
+        write(&tmp_path, json).context("写入绩效数据失败")?;
+        std::fs::rename(&tmp_path, &path).context("替换绩效数据失败")?;
         Ok(())
     }
 }
+
+fn logs_path(file: &str) -> PathBuf {
+    Path::new(logging::logs_directory()).join(file)
+}
+
+fn load_equity_curve() -> Result<Vec<EquitySample>> {
+    let path = logs_path("equity.jsonl");
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = read_to_string(&path).context("读取权益曲线失败")?;
+    let mut curve = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<EquitySample>(line) {
+            Ok(sample) => curve.push(sample),
+            Err(e) => warn!("equity.jsonl 第 {} 行解析失败，跳过: {}", index + 1, e),
+        }
+    }
+    Ok(curve)
+}
+
+fn append_equity_sample(sample: &EquitySample) -> Result<()> {
+    let base_dir = Path::new(logging::logs_directory());
+    create_dir_all(base_dir).context("创建logs目录失败")?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(base_dir.join("equity.jsonl"))
+        .context("打开equity.jsonl失败")?;
+
+    let json = serde_json::to_string(sample).context("序列化权益采样失败")?;
+    writeln!(file, "{}", json).context("写入权益曲线失败")?;
+    Ok(())
+}
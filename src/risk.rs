@@ -0,0 +1,678 @@
+// 确定性风控：位于决策交易员与执行层之间，按配置的硬性规则否决或缩减开仓/加仓数量，
+// 减仓与平仓始终放行，被否决的反手降级为只平仓。规则未配置时不生效。
+// 订单频率计数落盘，重启后仍按近一小时的实际下单数限制
+
+use crate::executor::quantize_down;
+use crate::logging;
+use crate::types::{PositionBook, PositionSide, Signal, StrategyAction};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fmt;
+use std::fs::{self, create_dir_all};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const HOUR_MS: i64 = 3600 * 1000;
+const STATE_FILE: &str = "risk_state.json";
+
+#[derive(Debug, Clone, Default)]
+pub struct RiskLimits {
+    pub max_symbol_notional: Option<f64>, // 单标的持仓名义价值上限 (USDT)
+    pub max_gross_exposure: Option<f64>,  // 组合总敞口上限：多空名义价值之和 / 权益
+    pub max_net_exposure: Option<f64>,    // 组合净敞口上限：|多头 - 空头| / 权益
+    pub max_margin_usage: Option<f64>,    // 占用保证金 / 权益上限
+    pub max_orders_per_hour: Option<u32>, // 近一小时开平仓订单数上限
+    pub min_liquidation_distance: Option<f64>, // 加仓时强平价距标记价格的最小比例
+}
+
+fn env_limit<T: std::str::FromStr>(key: &str) -> Result<Option<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match env::var(key) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map(Some)
+            .with_context(|| format!("{} 格式错误: {}", key, value)),
+        _ => Ok(None),
+    }
+}
+
+impl RiskLimits {
+    pub fn from_env() -> Result<Self> {
+        Ok(RiskLimits {
+            max_symbol_notional: env_limit("RISK_MAX_SYMBOL_NOTIONAL")?,
+            max_gross_exposure: env_limit("RISK_MAX_GROSS_EXPOSURE")?,
+            max_net_exposure: env_limit("RISK_MAX_NET_EXPOSURE")?,
+            max_margin_usage: env_limit("RISK_MAX_MARGIN_USAGE")?,
+            max_orders_per_hour: env_limit("RISK_MAX_ORDERS_PER_HOUR")?,
+            min_liquidation_distance: env_limit("RISK_MIN_LIQUIDATION_DISTANCE")?,
+        })
+    }
+}
+
+impl fmt::Display for RiskLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |v| v.to_string());
+        write!(
+            f,
+            "单标的名义 {} USDT, 总敞口 {}x, 净敞口 {}x, 保证金占用 {}, 每小时订单 {}, 强平距离 {}",
+            show(self.max_symbol_notional),
+            show(self.max_gross_exposure),
+            show(self.max_net_exposure),
+            show(self.max_margin_usage),
+            show(self.max_orders_per_hour.map(f64::from)),
+            show(self.min_liquidation_distance)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RiskEventKind {
+    Veto,  // 否决
+    Clamp, // 缩减数量
+}
+
+impl RiskEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RiskEventKind::Veto => "veto",
+            RiskEventKind::Clamp => "clamp",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RiskEventKind::Veto => "否决",
+            RiskEventKind::Clamp => "缩减",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RiskEvent {
+    pub rule: &'static str,
+    pub kind: RiskEventKind,
+    pub detail: String,
+}
+
+// 风控结果：amount 为允许执行的数量，被否决时为 0
+#[derive(Debug, Clone)]
+pub struct RiskCheck {
+    pub requested: f64,
+    pub amount: f64,
+    pub events: Vec<RiskEvent>,
+    pub close_only: bool, // 反手的开仓被否决，仍平掉反向腿
+}
+
+impl RiskCheck {
+    pub fn vetoed(&self) -> bool {
+        self.events.iter().any(|e| e.kind == RiskEventKind::Veto)
+    }
+
+    fn veto(mut self, rule: &'static str, detail: String) -> Self {
+        self.amount = 0.0;
+        self.events.push(RiskEvent {
+            rule,
+            kind: RiskEventKind::Veto,
+            detail,
+        });
+        self
+    }
+}
+
+// 单次检查所需的组合状态
+pub struct RiskContext<'a> {
+    pub symbol: &'a str,
+    pub portfolio: &'a HashMap<String, PositionBook>, // 各标的当前持仓（含本标的）
+    pub price: f64,
+    pub wallet_balance: f64,
+    pub leverage: u32,
+    pub step_size: f64,
+    pub min_qty: f64,
+    pub now_ms: i64,
+    pub halt_reason: Option<&'a str>, // 熔断中时禁止开仓/加仓
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RiskState {
+    order_times: VecDeque<i64>, // 已执行开平仓的时间（交易所时间）
+}
+
+fn state_path() -> PathBuf {
+    Path::new(logging::logs_directory()).join(STATE_FILE)
+}
+
+pub struct RiskEngine {
+    limits: RiskLimits,
+    order_times: Mutex<VecDeque<i64>>, // 已执行开平仓的时间（交易所时间）
+}
+
+impl RiskEngine {
+    pub fn new(limits: RiskLimits) -> Self {
+        RiskEngine {
+            limits,
+            order_times: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn limits(&self) -> &RiskLimits {
+        &self.limits
+    }
+
+    // 从日志目录恢复订单时间
+    pub fn restore(&self) -> Result<()> {
+        let path = state_path();
+        if !path.exists() {
+            return Ok(());
+        }
+        let content = fs::read_to_string(&path).context("读取风控状态失败")?;
+        let state: RiskState = serde_json::from_str(&content).context("解析风控状态失败")?;
+        *self.order_times.lock().unwrap() = state.order_times;
+        Ok(())
+    }
+
+    pub fn persist(&self) -> Result<()> {
+        let path = state_path();
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).context("创建logs目录失败")?;
+        }
+        let state = RiskState {
+            order_times: self.order_times.lock().unwrap().clone(),
+        };
+        let tmp_path = path.with_extension("json.tmp");
+        let json = serde_json::to_string_pretty(&state).context("序列化风控状态失败")?;
+        fs::write(&tmp_path, json).context("写入风控状态失败")?;
+        fs::rename(&tmp_path, &path).context("替换风控状态失败")?;
+        Ok(())
+    }
+
+    // 记录一次已执行的开平仓，用于订单频率限制；超出一小时的记录随之清理
+    pub fn record_order(&self, time_ms: i64) {
+        let mut times = self.order_times.lock().unwrap();
+        times.push_back(time_ms);
+        while times.front().is_some_and(|t| *t <= time_ms - HOUR_MS) {
+            times.pop_front();
+        }
+    }
+
+    fn orders_last_hour(&self, now_ms: i64) -> usize {
+        let mut times = self.order_times.lock().unwrap();
+        while times.front().is_some_and(|t| *t <= now_ms - HOUR_MS) {
+            times.pop_front();
+        }
+        times.len()
+    }
+
+    pub fn check(
+        &self,
+        ctx: &RiskContext<'_>,
+        signal: &Signal,
+        action: &StrategyAction,
+        amount: f64,
+    ) -> RiskCheck {
+        let mut check = self.check_entry(ctx, signal, action, amount);
+        // 平仓降低风险，反手被否决时保留其平掉反向腿的部分
+        let opposite = match signal {
+            Signal::Buy => Some(PositionSide::Short),
+            Signal::Sell => Some(PositionSide::Long),
+            Signal::Hold => None,
+        };
+        let holds_opposite = opposite.is_some_and(|side| {
+            ctx.portfolio
+                .get(ctx.symbol)
+                .is_some_and(|book| book.leg(&side).is_some())
+        });
+        if check.vetoed() && action.reverses() && holds_opposite {
+            check.close_only = true;
+        }
+        check
+    }
+
+    fn check_entry(
+        &self,
+        ctx: &RiskContext<'_>,
+        signal: &Signal,
+        action: &StrategyAction,
+        amount: f64,
+    ) -> RiskCheck {
+        let mut check = RiskCheck {
+            requested: amount,
+            amount,
+            events: Vec::new(),
+            close_only: false,
+        };
+        let (target, opposite, sign) = match signal {
+            Signal::Hold => return check,
+            Signal::Buy => (PositionSide::Long, PositionSide::Short, 1.0),
+            Signal::Sell => (PositionSide::Short, PositionSide::Long, -1.0),
+        };
+        // 减仓/平仓降低风险，不受限制
+        if action.reduces_only() {
+            return check;
+        }
+
+        if let Some(reason) = ctx.halt_reason {
+            return check.veto("circuit_breaker", reason.to_string());
+        }
+
+        if let Some(max_orders) = self.limits.max_orders_per_hour {
+            let count = self.orders_last_hour(ctx.now_ms);
+            if count >= max_orders as usize {
+                return check.veto(
+                    "max_orders_per_hour",
+                    format!("近一小时已执行 {} 笔订单，上限 {}", count, max_orders),
+                );
+            }
+        }
+
+        let book = ctx.portfolio.get(ctx.symbol).cloned().unwrap_or_default();
+        let held = book.leg(&target);
+        if let (Some(min_distance), Some(pos)) = (self.limits.min_liquidation_distance, held) {
+            if pos.liquidation_price > 0.0 && pos.mark_price > 0.0 {
+                let distance = (pos.mark_price - pos.liquidation_price).abs() / pos.mark_price;
+                if distance < min_distance {
+                    return check.veto(
+                        "min_liquidation_distance",
+                        format!(
+                            "强平价 {:.4} 距标记价格 {:.4} 仅 {:.2}%，低于 {:.2}%",
+                            pos.liquidation_price,
+                            pos.mark_price,
+                            distance * 100.0,
+                            min_distance * 100.0
+                        ),
+                    );
+                }
+            }
+        }
+
+        if ctx.price <= 0.0 {
+            return check;
+        }
+        let held_amount = held.map_or(0.0, |pos| pos.amount);
+        // 反手时执行层开仓前会先平掉反向腿，其敞口与保证金随之释放
+        let released_margin = match book.leg(&opposite) {
+            Some(pos) if action.reverses() => pos.margin,
+            _ => 0.0,
+        };
+
+        let mut equity = ctx.wallet_balance;
+        let mut other_gross = 0.0;
+        let mut other_net = 0.0;
+        let mut used_margin = 0.0;
+        for (symbol, book) in ctx.portfolio {
+            for pos in book.legs() {
+                equity += pos.unrealized_pnl;
+                used_margin += pos.margin;
+                if symbol != ctx.symbol {
+                    let notional = pos.amount * pos.mark_price;
+                    other_gross += notional;
+                    other_net += match pos.side {
+                        PositionSide::Long => notional,
+                        PositionSide::Short => -notional,
+                    };
+                }
+            }
+        }
+
+        // 各规则给出本次允许新增的最大数量
+        let mut bounds: Vec<(&'static str, f64, String)> = Vec::new();
+        if let Some(max_notional) = self.limits.max_symbol_notional {
+            bounds.push((
+                "max_symbol_notional",
+                max_notional / ctx.price - held_amount,
+                format!("单标的名义价值上限 {:.2} USDT", max_notional),
+            ));
+        }
+        if equity > 0.0 {
+            if let Some(max_gross) = self.limits.max_gross_exposure {
+                bounds.push((
+                    "max_gross_exposure",
+                    (max_gross * equity - other_gross) / ctx.price - held_amount,
+                    format!(
+                        "总敞口上限 {:.2}x 权益 {:.2}，其他标的 {:.2} USDT",
+                        max_gross, equity, other_gross
+                    ),
+                ));
+            }
+            if let Some(max_net) = self.limits.max_net_exposure {
+                bounds.push((
+                    "max_net_exposure",
+                    (max_net * equity - sign * other_net) / ctx.price - held_amount,
+                    format!(
+                        "净敞口上限 {:.2}x 权益 {:.2}，其他标的 {:+.2} USDT",
+                        max_net, equity, other_net
+                    ),
+                ));
+            }
+            if let Some(max_usage) = self.limits.max_margin_usage {
+                bounds.push((
+                    "max_margin_usage",
+                    (max_usage * equity - used_margin + released_margin) * ctx.leverage as f64
+                        / ctx.price,
+                    format!(
+                        "保证金占用上限 {:.0}%，已占用 {:.2} / 权益 {:.2}",
+                        max_usage * 100.0,
+                        used_margin,
+                        equity
+                    ),
+                ));
+            }
+        } else if self.limits.max_gross_exposure.is_some()
+            || self.limits.max_net_exposure.is_some()
+            || self.limits.max_margin_usage.is_some()
+        {
+            return check.veto("equity", format!("账户权益 {:.2} 不足", equity));
+        }
+
+        for (rule, bound, detail) in bounds {
+            if bound >= check.amount {
+                continue;
+            }
+            let clamped = quantize_down(bound.max(0.0), ctx.step_size);
+            if clamped <= 0.0 || clamped < ctx.min_qty {
+                return check.veto(
+                    rule,
+                    format!("{}，可新增 {:.6} 低于最小下单量", detail, bound.max(0.0)),
+                );
+            }
+            check.events.push(RiskEvent {
+                rule,
+                kind: RiskEventKind::Clamp,
+                detail: format!("{}，数量 {:.6} → {:.6}", detail, check.amount, clamped),
+            });
+            check.amount = clamped;
+        }
+        check
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Position;
+
+    fn position(side: PositionSide, amount: f64, price: f64, margin: f64) -> Position {
+        Position {
+            side,
+            amount,
+            entry_price: price,
+            unrealized_pnl: 0.0,
+            mark_price: price,
+            liquidation_price: 0.0,
+            margin,
+            leverage: 10,
+        }
+    }
+
+    fn context<'a>(
+        portfolio: &'a HashMap<String, PositionBook>,
+        halt_reason: Option<&'a str>,
+    ) -> RiskContext<'a> {
+        RiskContext {
+            symbol: "BTCUSDT",
+            portfolio,
+            price: 100.0,
+            wallet_balance: 1000.0,
+            leverage: 10,
+            step_size: 0.01,
+            min_qty: 0.01,
+            now_ms: 10 * HOUR_MS,
+            halt_reason,
+        }
+    }
+
+    fn portfolio(legs: Vec<(&str, Position)>) -> HashMap<String, PositionBook> {
+        let mut books: HashMap<String, Vec<Position>> = HashMap::new();
+        for (symbol, leg) in legs {
+            books.entry(symbol.to_string()).or_default().push(leg);
+        }
+        books
+            .into_iter()
+            .map(|(symbol, legs)| (symbol, PositionBook::from_legs(legs)))
+            .collect()
+    }
+
+    #[test]
+    fn reductions_pass_even_when_halted() {
+        let engine = RiskEngine::new(RiskLimits {
+            max_symbol_notional: Some(1.0),
+            ..RiskLimits::default()
+        });
+        let book = portfolio(vec![(
+            "BTCUSDT",
+            position(PositionSide::Long, 5.0, 100.0, 50.0),
+        )]);
+        let check = engine.check(
+            &context(&book, Some("熔断")),
+            &Signal::Sell,
+            &StrategyAction::ClosePosition,
+            5.0,
+        );
+        assert!(!check.vetoed());
+        assert_eq!(check.amount, 5.0);
+    }
+
+    #[test]
+    fn halt_vetoes_entries() {
+        let engine = RiskEngine::new(RiskLimits::default());
+        let book = HashMap::new();
+        let check = engine.check(
+            &context(&book, Some("熔断")),
+            &Signal::Buy,
+            &StrategyAction::OpenLong,
+            1.0,
+        );
+        assert!(check.vetoed());
+        assert_eq!(check.amount, 0.0);
+        assert_eq!(check.events[0].rule, "circuit_breaker");
+    }
+
+    #[test]
+    fn symbol_notional_clamps_to_remaining_room() {
+        let engine = RiskEngine::new(RiskLimits {
+            max_symbol_notional: Some(500.0),
+            ..RiskLimits::default()
+        });
+        let book = portfolio(vec![(
+            "BTCUSDT",
+            position(PositionSide::Long, 2.0, 100.0, 20.0),
+        )]);
+        let check = engine.check(
+            &context(&book, None),
+            &Signal::Buy,
+            &StrategyAction::AddPosition,
+            5.0,
+        );
+        // 上限 5 个，已持有 2 个
+        assert!(!check.vetoed());
+        assert!((check.amount - 3.0).abs() < 1e-9);
+        assert_eq!(check.events[0].kind, RiskEventKind::Clamp);
+    }
+
+    #[test]
+    fn clamp_below_min_qty_is_veto() {
+        let engine = RiskEngine::new(RiskLimits {
+            max_symbol_notional: Some(200.5),
+            ..RiskLimits::default()
+        });
+        let book = portfolio(vec![(
+            "BTCUSDT",
+            position(PositionSide::Long, 2.0, 100.0, 20.0),
+        )]);
+        let check = engine.check(
+            &context(&book, None),
+            &Signal::Buy,
+            &StrategyAction::AddPosition,
+            1.0,
+        );
+        // 可新增 0.005，按步长截断为 0
+        assert!(check.vetoed());
+        assert_eq!(check.events[0].rule, "max_symbol_notional");
+    }
+
+    #[test]
+    fn exposure_limits_count_other_symbols() {
+        let engine = RiskEngine::new(RiskLimits {
+            max_gross_exposure: Some(2.0),
+            max_net_exposure: Some(1.0),
+            ..RiskLimits::default()
+        });
+        let book = portfolio(vec![(
+            "ETHUSDT",
+            position(PositionSide::Short, 8.0, 100.0, 80.0),
+        )]);
+        // 总敞口：(2000 - 800) / 100 = 12；净敞口：做多抵消空头，(1000 + 800) / 100 = 18
+        let check = engine.check(
+            &context(&book, None),
+            &Signal::Buy,
+            &StrategyAction::OpenLong,
+            20.0,
+        );
+        assert!((check.amount - 12.0).abs() < 1e-9);
+        assert_eq!(check.events.len(), 1);
+        assert_eq!(check.events[0].rule, "max_gross_exposure");
+
+        // 做空与空头同向：(1000 - 800) / 100 = 2
+        let check = engine.check(
+            &context(&book, None),
+            &Signal::Sell,
+            &StrategyAction::OpenShort,
+            20.0,
+        );
+        assert!((check.amount - 2.0).abs() < 1e-9);
+        assert_eq!(check.events[1].rule, "max_net_exposure");
+    }
+
+    #[test]
+    fn margin_usage_releases_opposite_leg() {
+        let engine = RiskEngine::new(RiskLimits {
+            max_margin_usage: Some(0.5),
+            ..RiskLimits::default()
+        });
+        let book = portfolio(vec![(
+            "BTCUSDT",
+            position(PositionSide::Short, 30.0, 100.0, 300.0),
+        )]);
+        // (0.5 × 1000 - 300 + 300) × 10 / 100 = 50
+        let check = engine.check(
+            &context(&book, None),
+            &Signal::Buy,
+            &StrategyAction::Reverse,
+            60.0,
+        );
+        assert!((check.amount - 50.0).abs() < 1e-9);
+
+        // 非反手开仓不平反向腿：(0.5 × 1000 - 300) × 10 / 100 = 20
+        let check = engine.check(
+            &context(&book, None),
+            &Signal::Buy,
+            &StrategyAction::OpenLong,
+            60.0,
+        );
+        assert!((check.amount - 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn liquidation_distance_vetoes_adding() {
+        let engine = RiskEngine::new(RiskLimits {
+            min_liquidation_distance: Some(0.1),
+            ..RiskLimits::default()
+        });
+        let mut leg = position(PositionSide::Long, 1.0, 100.0, 10.0);
+        leg.liquidation_price = 95.0;
+        let book = portfolio(vec![("BTCUSDT", leg)]);
+        let check = engine.check(
+            &context(&book, None),
+            &Signal::Buy,
+            &StrategyAction::AddPosition,
+            1.0,
+        );
+        assert!(check.vetoed());
+        assert_eq!(check.events[0].rule, "min_liquidation_distance");
+    }
+
+    #[test]
+    fn order_rate_counts_last_hour_only() {
+        let engine = RiskEngine::new(RiskLimits {
+            max_orders_per_hour: Some(2),
+            ..RiskLimits::default()
+        });
+        let book = HashMap::new();
+        let now = 10 * HOUR_MS;
+        engine.record_order(now - HOUR_MS); // 恰好一小时前，不计入
+        engine.record_order(now - 1000);
+        let check = engine.check(
+            &context(&book, None),
+            &Signal::Buy,
+            &StrategyAction::OpenLong,
+            1.0,
+        );
+        assert!(!check.vetoed());
+
+        engine.record_order(now - 500);
+        let check = engine.check(
+            &context(&book, None),
+            &Signal::Buy,
+            &StrategyAction::OpenLong,
+            1.0,
+        );
+        assert!(check.vetoed());
+        assert_eq!(check.events[0].rule, "max_orders_per_hour");
+    }
+
+    #[test]
+    fn vetoed_reversal_still_closes() {
+        let engine = RiskEngine::new(RiskLimits::default());
+        let book = portfolio(vec![(
+            "BTCUSDT",
+            position(PositionSide::Short, 1.0, 100.0, 10.0),
+        )]);
+        let check = engine.check(
+            &context(&book, Some("熔断")),
+            &Signal::Buy,
+            &StrategyAction::Reverse,
+            1.0,
+        );
+        assert!(check.vetoed());
+        assert!(check.close_only);
+
+        // 开仓动作不触碰反向腿，否决后无需平仓
+        let check = engine.check(
+            &context(&book, Some("熔断")),
+            &Signal::Buy,
+            &StrategyAction::OpenLong,
+            1.0,
+        );
+        assert!(check.vetoed());
+        assert!(!check.close_only);
+
+        // 无反向腿可平时保持否决
+        let empty = HashMap::new();
+        let check = engine.check(
+            &context(&empty, Some("熔断")),
+            &Signal::Buy,
+            &StrategyAction::Reverse,
+            1.0,
+        );
+        assert!(check.vetoed());
+        assert!(!check.close_only);
+    }
+
+    #[test]
+    fn non_positive_equity_vetoes_exposure_rules() {
+        let engine = RiskEngine::new(RiskLimits {
+            max_gross_exposure: Some(2.0),
+            ..RiskLimits::default()
+        });
+        let book = HashMap::new();
+        let mut ctx = context(&book, None);
+        ctx.wallet_balance = 0.0;
+        let check = engine.check(&ctx, &Signal::Buy, &StrategyAction::OpenLong, 1.0);
+        assert!(check.vetoed());
+        assert_eq!(check.events[0].rule, "equity");
+    }
+}
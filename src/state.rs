@@ -1,5 +1,7 @@
+use crate::journal;
 use crate::logging;
-use crate::types::{Position, TradeResult, TradingDecision};
+use crate::performance::TradePnl;
+use crate::types::{PositionBook, TradeResult, TradingDecision};
 use anyhow::{Context, Result};
 use std::fs::{create_dir_all, OpenOptions};
 use std::io::Write;
@@ -18,6 +20,24 @@ pub fn log_trade(trade_result: &TradeResult) -> Result<()> {
 
     let json = serde_json::to_string(trade_result).context("序列化交易结果失败")?;
     writeln!(file, "{}", json).context("写入交易日志失败")?;
+    journal::record_trade(trade_result);
+
+    Ok(())
+}
+
+// 记录平仓交易的盈亏归因
+pub fn log_trade_pnl(trade_pnl: &TradePnl) -> Result<()> {
+    let base_dir = Path::new(logging::logs_directory());
+    create_dir_all(base_dir).context("创建logs目录失败")?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(base_dir.join("trade_pnl.jsonl"))
+        .context("打开trade_pnl.jsonl失败")?;
+
+    let json = serde_json::to_string(trade_pnl).context("序列化盈亏归因失败")?;
+    writeln!(file, "{}", json).context("写入盈亏归因失败")?;
 
     Ok(())
 }
@@ -28,13 +48,13 @@ struct DecisionLog {
     timestamp: i64,
     symbol: String,
     decision: TradingDecision,
-    position: Option<Position>,
+    positions: PositionBook,
 }
 
 pub fn log_decision(
     symbol: &str,
     decision: &TradingDecision,
-    position: &Option<Position>,
+    positions: &PositionBook,
 ) -> Result<()> {
     let base_dir = Path::new(logging::logs_directory());
     create_dir_all(base_dir).context("创建logs目录失败")?;
@@ -49,11 +69,12 @@ pub fn log_decision(
         timestamp: chrono::Utc::now().timestamp(),
         symbol: symbol.to_string(),
         decision: decision.clone(),
-        position: position.clone(),
+        positions: positions.clone(),
     };
 
     let json = serde_json::to_string(&log).context("序列化决策日志失败")?;
     writeln!(file, "{}", json).context("写入决策日志失败")?;
+    journal::record_decision(symbol, decision, positions);
 
     Ok(())
 }
@@ -10,6 +10,10 @@ pub struct Kline {
     pub low: f64,
     pub close: f64,
     pub volume: f64,
+    #[serde(default)]
+    pub taker_buy_volume: f64, // 主动买入成交量，来源缺失时为 0
+    #[serde(default)]
+    pub taker_buy_quote_volume: f64, // 主动买入成交额 (USDT)
 }
 
 // ===== 技术指标 (Task 2.2) =====
@@ -23,11 +27,33 @@ pub struct TechnicalIndicators {
     pub price_change_3: f64,  // 3周期涨跌幅 (%)
     pub price_change_6: f64,  // 6周期涨跌幅 (%)
     pub price_change_12: f64, // 12周期涨跌幅 (%)
-    pub atr_14: f64,          // 14周期 ATR
+    pub atr_14: f64,          // 14周期 Wilder ATR
     pub atr_percent: f64,     // ATR 占收盘价的比例 (%)
     pub volume_ratio: f64,    // 当前成交量/20均量
 }
 
+// 附加分析周期的结构化摘要，供行情分析员判断高周期背景
+#[derive(Debug, Clone, Serialize)]
+pub struct TimeframeContext {
+    pub interval: String,
+    pub trend: TimeframeTrend,
+    pub close: f64,
+    pub range_high: f64,      // 窗口内最高价
+    pub range_low: f64,       // 窗口内最低价
+    pub range_position: f64,  // 收盘价在窗口区间中的位置 (0-1)
+    pub close_vs_sma_50: f64, // 收盘价相对 SMA50 (%)
+    pub indicators: TechnicalIndicators,
+}
+
+// 均线排列给出的周期趋势
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeframeTrend {
+    Bullish, // 收盘价 > SMA20 > SMA50
+    Bearish, // 收盘价 < SMA20 < SMA50
+    Mixed,
+}
+
 // ===== 交易决策相关类型 (Task 2.3) =====
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TradingDecision {
@@ -109,11 +135,28 @@ pub struct StrategyAdvice {
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum StrategyAction {
-    OpenLong,      // 开多
-    OpenShort,     // 开空
-    AddPosition,   // 加仓
-    ClosePosition, // 平仓
-    Hold,          // 持有
+    OpenLong,       // 开多
+    OpenShort,      // 开空
+    AddPosition,    // 加仓
+    ReducePosition, // 部分减仓
+    ClosePosition,  // 全部平仓，不反手
+    Reverse,        // 平仓后反向开仓
+    Hold,           // 持有
+}
+
+impl StrategyAction {
+    // 只减少现有持仓、不开新仓的动作
+    pub fn reduces_only(&self) -> bool {
+        matches!(
+            self,
+            StrategyAction::ReducePosition | StrategyAction::ClosePosition
+        )
+    }
+
+    // 平掉反向腿后开出信号方向的动作；其他开仓动作不触碰反向腿
+    pub fn reverses(&self) -> bool {
+        matches!(self, StrategyAction::Reverse)
+    }
 }
 
 // 3. 风险管理员输出
@@ -180,21 +223,89 @@ pub enum PortfolioStrategy {
 }
 
 // ===== 持仓和交易结果类型 (Task 2.4) =====
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Position {
     pub side: PositionSide,
     pub amount: f64,
     pub entry_price: f64,
     pub unrealized_pnl: f64,
+    pub mark_price: f64,
+    pub liquidation_price: f64, // 0 表示无强平价（如保证金充足）
+    pub margin: f64,            // 占用保证金
+    pub leverage: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+// 单个标的的双向持仓：多空两腿可同时存在
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PositionBook {
+    pub long: Option<Position>,
+    pub short: Option<Position>,
+}
+
+impl PositionBook {
+    pub fn from_legs(legs: impl IntoIterator<Item = Position>) -> Self {
+        let mut book = PositionBook::default();
+        for leg in legs {
+            match leg.side {
+                PositionSide::Long => book.long = Some(leg),
+                PositionSide::Short => book.short = Some(leg),
+            }
+        }
+        book
+    }
+
+    pub fn leg(&self, side: &PositionSide) -> Option<&Position> {
+        match side {
+            PositionSide::Long => self.long.as_ref(),
+            PositionSide::Short => self.short.as_ref(),
+        }
+    }
+
+    pub fn legs(&self) -> impl Iterator<Item = &Position> {
+        self.long.iter().chain(self.short.iter())
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.long.is_none() && self.short.is_none()
+    }
+
+    // 按标记价格计算的持仓名义价值（多空两腿之和）
+    pub fn notional(&self) -> f64 {
+        self.legs().map(|p| p.amount * p.mark_price).sum()
+    }
+}
+
+impl fmt::Display for PositionBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_flat() {
+            return write!(f, "空仓");
+        }
+        let legs: Vec<String> = self
+            .legs()
+            .map(|p| {
+                format!(
+                    "{:?}仓 {:.4} @ {:.2}, 标记 {:.2}, 强平 {:.2}, {}x, 盈亏 {:.2} USDT",
+                    p.side,
+                    p.amount,
+                    p.entry_price,
+                    p.mark_price,
+                    p.liquidation_price,
+                    p.leverage,
+                    p.unrealized_pnl
+                )
+            })
+            .collect();
+        write!(f, "{}", legs.join(" | "))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum PositionSide {
     Long,
     Short,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TradeResult {
     pub symbol: String,
     pub action: TradeAction,
@@ -202,11 +313,14 @@ pub struct TradeResult {
     pub amount: f64,
     pub timestamp: i64,
     pub reason: String,
-    pub pnl: Option<f64>,
+    pub pnl: Option<f64>,              // 平仓的已实现盈亏（不含手续费）
+    pub commission: Option<f64>,       // 实际手续费，无成交明细时为 None
     pub order_details: Option<String>, // 订单执行详情或错误信息
+    #[serde(default)]
+    pub order_ids: Vec<i64>, // 成交订单号，绩效统计据此区分交易所侧止损止盈成交
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TradeAction {
     OpenLong,
     CloseLong,